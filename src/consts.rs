@@ -2,17 +2,109 @@ use std::time::Duration;
 
 // Supported devices listed by vendor/product IDs
 pub const VENDOR_ID: u16 = 0x1189;
-pub const PRODUCT_IDS: [u16; 3] = [0x8840, 0x8842, 0x8890];
+pub const PRODUCT_IDS: [u16; 4] = [0x8840, 0x8842, 0x8850, 0x8890];
+
+/// Default configuration file, stored next to the executable
+///
+pub const DEFAULT_CONFIG: &str = "mapping.ron";
+
+/// Version of the configuration format written by this build. Older configurations
+/// are migrated when they are read
+///
+pub const CONFIG_VERSION: u32 = 1;
+
+/// Config file name that reads the configuration from stdin instead of a file
+///
+pub const STDIN_CONFIG: &str = "-";
+
+/// Environment variable overriding the directory the GUI keeps named profiles in
+///
+pub const PROFILES_DIR_ENV: &str = "MACROPAD_PROFILES";
+
+/// Configurations with fewer mapped key and knob actions are treated as (almost) empty
+/// and need confirming before they overwrite the device
+///
+pub const MIN_MAPPED_ACTIONS: usize = 2;
+
+/// Number of configuration backups kept next to the config before older ones are pruned
+///
+pub const MAX_BACKUPS: usize = 10;
 
 /// Timeout for reading from USB
 ///
 pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(100);
 
+/// Time without edits after which the GUI autosaves the configuration
+///
+pub const AUTOSAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Number of times opening the macropad is attempted when it is busy or
+/// momentarily disconnected
+///
+pub const OPEN_ATTEMPTS: u32 = 3;
+
+/// Pause before the first retry of opening the macropad, doubled for every further retry
+///
+pub const OPEN_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// How often the GUI looks for connected macropads
+///
+pub const CONNECTION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Time between frames of the animated LED preview in the GUI
+///
+pub const LED_PREVIEW_FRAME: Duration = Duration::from_millis(66);
+
+/// Number of messages kept in the status log of the GUI
+///
+pub const STATUS_LOG_SIZE: usize = 100;
+
+/// Number of changes the GUI can undo
+///
+pub const UNDO_DEPTH: usize = 50;
+
+/// Seconds to wait before simulating an action on this computer, to focus the target window
+///
+pub const SIMULATE_COUNTDOWN: u64 = 3;
+
+/// How often the watch command checks the configuration file for changes
+///
+pub const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Time a changed configuration file must be left alone before the watch command
+/// programs it
+///
+pub const WATCH_DEBOUNCE: Duration = Duration::from_millis(750);
+
 /// Number of layers on the macropad. Depending on the model,
 /// some layers are no accessible
 ///
 pub const NUM_LAYERS: u8 = 3;
 
+/// Product ids that support setting the LED color of individual keys. None yet: the
+/// packet for it hasn't been verified on a device, so the colors are kept but never sent
+///
+pub const PER_KEY_LED_PIDS: [u16; 0] = [];
+
+/// Product ids that can dim their LEDs. None yet: the brightness byte of the LED packet
+/// hasn't been verified on a device, so the brightness is kept but never sent
+///
+pub const LED_BRIGHTNESS_PIDS: [u16; 0] = [];
+
+/// Full LED brightness, in percent
+///
+pub const MAX_LED_BRIGHTNESS: u8 = 100;
+
+/// Maximum number of layers for each supported product id. 0x8890 has a single layer,
+/// its programming always targets layer 1
+///
+pub const MAX_LAYERS: [(u16, u8); 4] = [(0x8840, 3), (0x8842, 3), (0x8850, 3), (0x8890, 1)];
+
+/// Key matrix (rows, cols, knobs) of product ids that only come in one size and
+/// can't be asked for their layout
+///
+pub const FIXED_LAYOUTS: [(u16, (u8, u8, u8)); 1] = [(0x8890, (1, 3, 1))];
+
 /// Read buffer size (in bytes)
 ///
 pub const READ_BUF_SIZE: u8 = 72;
@@ -22,11 +114,27 @@ pub const READ_BUF_SIZE: u8 = 72;
 ///
 pub const MAX_KEY_PRESSES_884X: usize = 17;
 
+/// Maximum number of key presses that can be assigned to a key
+/// for a 0x8850 macropad
+///
+pub const MAX_KEY_PRESSES_8850: usize = 17;
+
 /// Maximum number of key presses that can be assigned to a key
 /// for a 0x8890 macropad
 ///
 pub const MAX_KEY_PRESSES_8890: usize = 5;
 
+/// Total number of key presses each product id can store over all keys, knob actions and
+/// layers. A configuration can stay within the limit of every key and still exceed it.
+/// Only add a product id once its capacity was measured on the device, ids not listed
+/// here aren't checked
+///
+pub const KEY_PRESS_CAPACITY: [(u16, usize); 0] = [];
+
+/// Maximum number of times a knob sends its rotation action per detent
+///
+pub const MAX_STEPS_PER_DETENT: u8 = 10;
+
 /// Maximum delay for a keypress
 ///
 pub const MAX_DELAY: u16 = 6000;
@@ -34,3 +142,96 @@ pub const MAX_DELAY: u16 = 6000;
 /// Packet size
 ///
 pub const PACKET_SIZE: usize = 65;
+
+/// Prefix of a mapping that types out literal text (eg. text:hello@example.com)
+///
+pub const TEXT_PREFIX: &str = "text:";
+
+/// Prefix of a relative mouse movement (eg. move:10,-5)
+///
+pub const MOVE_PREFIX: &str = "move:";
+
+/// Prefix of a key that is kept pressed (eg. hold:space)
+///
+pub const HOLD_PREFIX: &str = "hold:";
+
+/// Number of presses a held key is approximated with. The macropads can't keep a
+/// key pressed, so holding is emulated by repeating the key. This fits within the
+/// key presses of every supported model
+///
+pub const HOLD_REPEATS: usize = 5;
+
+/// Prefix of a pause between the key presses of a sequence (eg. ctrl-c,wait:200,ctrl-v)
+///
+pub const WAIT_PREFIX: &str = "wait:";
+
+/// Maximum distance (in either direction) of a relative mouse movement
+///
+pub const MAX_MOUSE_MOVE: i16 = 127;
+
+/// Returns the maximum number of layers for the specified product id. When
+/// no product id is given (eg. no device connected), the highest known value
+/// is returned
+///
+/// #Arguments
+/// `pid` - product id of the macropad
+///
+pub fn max_layers(pid: Option<u16>) -> u8 {
+    let highest = MAX_LAYERS
+        .iter()
+        .map(|(_, l)| *l)
+        .max()
+        .unwrap_or(NUM_LAYERS);
+    match pid {
+        Some(pid) => MAX_LAYERS
+            .iter()
+            .find(|(p, _)| *p == pid)
+            .map(|(_, l)| *l)
+            .unwrap_or(highest),
+        None => highest,
+    }
+}
+
+/// Returns the key matrix (rows, cols, knobs) of a product id that only comes in one size
+///
+/// #Arguments
+/// `pid` - product id of the macropad
+///
+pub fn fixed_layout(pid: u16) -> Option<(u8, u8, u8)> {
+    FIXED_LAYOUTS
+        .iter()
+        .find(|(p, _)| *p == pid)
+        .map(|(_, layout)| *layout)
+}
+
+/// Returns the total number of key presses a product id can store, `None` when it isn't
+/// known
+///
+/// #Arguments
+/// `pid` - product id of the macropad
+///
+pub fn key_press_capacity(pid: u16) -> Option<usize> {
+    KEY_PRESS_CAPACITY
+        .iter()
+        .find(|(p, _)| *p == pid)
+        .map(|(_, capacity)| *capacity)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn max_layers_per_pid() {
+        assert_eq!(max_layers(Some(0x8840)), 3);
+        assert_eq!(max_layers(Some(0x8842)), 3);
+        assert_eq!(max_layers(Some(0x8850)), 3);
+        assert_eq!(max_layers(Some(0x8890)), 1);
+        assert_eq!(
+            max_layers(Some(0x1234)),
+            3,
+            "unknown pids get the highest value"
+        );
+        assert_eq!(max_layers(None), 3, "no device gets the highest value");
+    }
+}
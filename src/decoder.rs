@@ -1,4 +1,4 @@
-use crate::keyboard::{MediaCode, WellKnownCode};
+use crate::keyboard::{MediaCode, MouseAction, WellKnownCode};
 use anyhow::{anyhow, Result};
 use log::debug;
 use num::FromPrimitive;
@@ -101,6 +101,14 @@ impl Decoder {
                 }
                 _ => (),
             }
+
+            // relative mouse movement
+            if buf[13] != 0 || buf[14] != 0 {
+                if !key_str.is_empty() {
+                    key_str += ",";
+                }
+                key_str += &MouseAction::Move(buf[13] as i8, buf[14] as i8).to_string();
+            }
             key_press.push(key_str);
 
             // TODO: is it possible to make a binding like wheelup-a? doesn't make much sense
@@ -124,11 +132,11 @@ impl Decoder {
             let result = val.unwrap();
             //println!("result: {:?}", result);
             let mut key_str = Self::modifier_to_str(result.modifier);
-            if result.media_code.is_some() {
+            if let Some(media_code) = result.media_code {
                 if !key_str.is_empty() {
                     key_str += "-";
                 }
-                key_str += &result.media_code.unwrap().to_string();
+                key_str += &media_code.to_string();
             }
             key_press.push(key_str);
             i += 1;
@@ -143,12 +151,12 @@ impl Decoder {
             // get the mapping
             let result = val.unwrap();
             let mut key_str = Self::modifier_to_str(result.modifier);
-            if result.wkc.is_some() {
+            if let Some(wkc) = result.wkc {
                 //println!("WKC!!!!");
                 if !key_str.is_empty() {
                     key_str += "-";
                 }
-                key_str += &result.wkc.unwrap().to_string();
+                key_str += &wkc.to_string();
             }
             key_press.push(key_str);
 
@@ -405,6 +413,18 @@ mod tests {
         println!("{:?}", key);
         assert_eq!(key.keys[0], "ctrl-wheeldown");
 
+        msg = vec![
+            0x03, 0xfa, 0x13, 0x01, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x0a,
+            0xfb, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        println!("\ntest 11b");
+        key = Decoder::get_key_mapping(&msg)?;
+        println!("{:?}", key);
+        assert_eq!(key.keys[0], "move:10,-5");
+
         msg = vec![
             0x03, 0xfa, 0x10, 0x03, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xea, 0x00, 0x00,
             0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
@@ -1,11 +1,15 @@
 use eframe::egui;
-use crate::options::{Options, Command, DevelOptions};
-use crate::consts::VENDOR_ID;
-use crate::mapping::{Mapping, Macropad, Layer, LedSettings};
-use crate::keyboard::LedColor;
-use crate::config::Orientation;
-use crate::{open_keyboard, find_device};
-use std::sync::{Arc, Mutex, MutexGuard};
+use crate::options::DevelOptions;
+use crate::consts::{self, VENDOR_ID};
+use crate::mapping::{Mapping, Macropad, Layer, LedSettings, Button, Finding, Position};
+use crate::keyboard::{self, program_steps, PacketGroup, KnobAction, LedColor, DeviceInfo, Unsupported, UsbFailure, WellKnownCode};
+use crate::config::{self, Orientation};
+use crate::layout::{self, HostLayout};
+use crate::{open_keyboard, open_keyboard_retry, find_all_devices, profiles, simulate, templates};
+use std::collections::{BTreeMap, VecDeque};
+use strum::IntoEnumIterator as _;
+use rusb::UsbContext as _;
+use std::sync::{mpsc, Arc, Mutex, MutexGuard};
 use std::time::{Duration, Instant};
 use std::thread;
 
@@ -14,18 +18,55 @@ pub fn main() {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([1000.0, 900.0])
             .with_min_inner_size([850.0, 700.0]),
+        // the size above only applies to the first run, afterwards eframe restores the stored window geometry
+        persist_window: true,
         ..Default::default()
     };
     let _ = eframe::run_native(
         "Macropad Editor Pro",
         native_options,
         Box::new(|cc| {
-            cc.egui_ctx.set_visuals(egui::Visuals::dark());
-            Ok(Box::new(MacropadApp::new()))
+            let settings: AppSettings = cc.storage.and_then(|s| eframe::get_value(s, eframe::APP_KEY)).unwrap_or_default();
+            cc.egui_ctx.set_visuals(settings.visuals());
+            layout::set_host_layout(settings.host_layout);
+            Ok(Box::new(MacropadApp::new(settings)))
         }),
     );
 }
 
+/// Entries of the code reference legend, every key name listed here must validate
+const LEGEND: &[(&str, &str)] = &[
+    ("Modifiers:", "ctrl-, shift-, alt-, win-, rctrl-, rshift-, ralt-, rwin-"),
+    ("Media:", "play, stop, next, prev, mute, volup, voldown, brightnessup, brightnessdown"),
+    ("System:", "sleep, syspower, calculator, search, mycomputer, mail, webpagehome (not on 0x8890)"),
+    ("Mouse:", "click, rclick, mclick, wheelup, wheeldown, move:dx,dy"),
+    ("Other:", "space, enter, backspace, tab, esc, comma, dot, slash, a-z, 0-9, f1-f24"),
+    ("Numpad:", "num0-num9, numplus, numminus, numasterisk, numslash, numenter, numdot, numequal, numlock"),
+    ("Text:", "text:hello@example.com (types the text after the prefix)"),
+    ("Hold:", "hold:space (repeats the key while approximating a hold)"),
+    ("Wait:", "ctrl-c,wait:200,ctrl-v (the same pause between every press, not on 0x8890)"),
+];
+
+/// Editor preferences that are kept between runs
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+#[serde(default)]
+struct AppSettings {
+    dark_mode: bool,
+    /// keyboard layout of the computer, for key labels and text: mappings
+    host_layout: HostLayout,
+    autosave: bool,
+    /// configuration file open when the editor was closed
+    last_config: Option<String>,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self { Self { dark_mode: true, host_layout: HostLayout::Qwerty, autosave: false, last_config: None } }
+}
+
+impl AppSettings {
+    fn visuals(&self) -> egui::Visuals { if self.dark_mode { egui::Visuals::dark() } else { egui::Visuals::light() } }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum Selection {
     None,
@@ -33,16 +74,417 @@ enum Selection {
     Knob(usize, KnobPart),
 }
 
+/// Destructive actions that wait for the user to confirm them
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PendingAction {
+    ClearLayer(usize),
+    Program,
+    /// delete the profile named in the profile field
+    DeleteProfile,
+    /// replace the configuration with the template at this index
+    LoadTemplate(usize),
+    /// apply a layout that discards this many key and knob mappings
+    ApplyLayout(usize),
+    /// copy the key at this row and column across its row (true) or column (false)
+    Fill(usize, usize, bool),
+    /// resize the imported layer to the layout, discarding this many mappings
+    ImportLayer(usize),
+    /// unbind every key and knob action on the device
+    ClearDevice,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum KnobPart { Ccw, Press, Cw }
+
+impl KnobPart {
+    fn action(self) -> KnobAction {
+        match self { KnobPart::Ccw => KnobAction::RotateCCW, KnobPart::Press => KnobAction::Press, KnobPart::Cw => KnobAction::RotateCW }
+    }
+}
+
+/// Entries of the context menu of a grid key
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
-enum KnobPart { CCW, Press, CW }
+enum KeyMenuAction { Clear, Copy, Paste, Swap, FillRow, FillCol }
+
+/// What the text pasted from the clipboard replaces
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum PasteTarget {
+    /// mapping and delay copied with the Copy button
+    Button,
+    /// plain mapping text, eg. ctrl-c,ctrl-v,enter
+    Mapping,
+}
+
+impl Selection {
+    fn describe(&self) -> String {
+        match self {
+            Selection::Button(r, c) => format!("Key {},{}", r + 1, c + 1),
+            Selection::Knob(i, part) => format!("Knob {} {}", i + 1, match part { KnobPart::Ccw => "CCW", KnobPart::Press => "Press", KnobPart::Cw => "CW" }),
+            Selection::None => String::new(),
+        }
+    }
+}
+
+/// Returns the logical (row, col) of every button in the order it is drawn, so the
+/// grid on screen matches how the macropad is mounted
+///
+/// #Arguments
+/// `rows` - number of rows
+/// `cols` - number of columns
+/// `orientation` - orientation of the macropad
+///
+fn display_order(rows: usize, cols: usize, orientation: Orientation) -> Vec<Vec<(usize, usize)>> {
+    if rows == 0 || cols == 0 { return Vec::new(); }
+    let keys: Vec<Vec<u8>> = (0..rows).map(|r| (0..cols).map(|c| (r * cols + c) as u8).collect()).collect();
+    let keys = match orientation {
+        Orientation::Normal => keys,
+        Orientation::Clockwise => config::get_keys_clockwise(keys),
+        Orientation::CounterClockwise => config::get_keys_counter_clockwise(keys),
+        Orientation::UpsideDown => config::get_keys_upsidedown(keys),
+    };
+    keys.iter().map(|row| row.iter().map(|k| (*k as usize / cols, *k as usize % cols)).collect()).collect()
+}
+
+/// Returns the key code of an egui key. Only keys on a regular keyboard have one
+///
+/// #Arguments
+/// `key` - key pressed in the editor
+///
+fn key_code(key: egui::Key) -> Option<WellKnownCode> {
+    use egui::Key as K;
+    use WellKnownCode as W;
+    let code = match key {
+        K::A => W::A, K::B => W::B, K::C => W::C, K::D => W::D, K::E => W::E, K::F => W::F, K::G => W::G, K::H => W::H, K::I => W::I,
+        K::J => W::J, K::K => W::K, K::L => W::L, K::M => W::M, K::N => W::N, K::O => W::O, K::P => W::P, K::Q => W::Q, K::R => W::R,
+        K::S => W::S, K::T => W::T, K::U => W::U, K::V => W::V, K::W => W::W, K::X => W::X, K::Y => W::Y, K::Z => W::Z,
+        K::Num0 => W::N0, K::Num1 => W::N1, K::Num2 => W::N2, K::Num3 => W::N3, K::Num4 => W::N4,
+        K::Num5 => W::N5, K::Num6 => W::N6, K::Num7 => W::N7, K::Num8 => W::N8, K::Num9 => W::N9,
+        K::F1 => W::F1, K::F2 => W::F2, K::F3 => W::F3, K::F4 => W::F4, K::F5 => W::F5, K::F6 => W::F6, K::F7 => W::F7, K::F8 => W::F8,
+        K::F9 => W::F9, K::F10 => W::F10, K::F11 => W::F11, K::F12 => W::F12, K::F13 => W::F13, K::F14 => W::F14, K::F15 => W::F15, K::F16 => W::F16,
+        K::F17 => W::F17, K::F18 => W::F18, K::F19 => W::F19, K::F20 => W::F20, K::F21 => W::F21, K::F22 => W::F22, K::F23 => W::F23, K::F24 => W::F24,
+        K::ArrowUp => W::Up, K::ArrowDown => W::Down, K::ArrowLeft => W::Left, K::ArrowRight => W::Right,
+        K::Enter => W::Enter, K::Tab => W::Tab, K::Backspace => W::Backspace, K::Space => W::Space, K::Escape => W::Escape,
+        K::Insert => W::Insert, K::Delete => W::Delete, K::Home => W::Home, K::End => W::End, K::PageUp => W::PageUp, K::PageDown => W::PageDown,
+        K::Minus => W::Minus, K::Equals => W::Equal, K::OpenBracket => W::LeftBracket, K::CloseBracket => W::RightBracket, K::Backslash => W::Backslash,
+        K::Semicolon => W::Semicolon, K::Quote => W::Quote, K::Backtick => W::Grave, K::Comma => W::Comma, K::Period => W::Dot, K::Slash => W::Slash,
+        _ => return None,
+    };
+    Some(code)
+}
+
+/// Converts a key press into a mapping (eg. ctrl-shift-a)
+///
+/// #Arguments
+/// `key` - key pressed in the editor
+/// `modifiers` - modifiers held down with the key
+///
+fn key_press_mapping(key: egui::Key, modifiers: egui::Modifiers) -> Option<String> {
+    let code = key_code(key)?;
+    let mut parts = Vec::new();
+    if modifiers.ctrl { parts.push("ctrl".to_string()); }
+    if modifiers.shift { parts.push("shift".to_string()); }
+    if modifiers.alt { parts.push("alt".to_string()); }
+    if modifiers.mac_cmd { parts.push("win".to_string()); }
+    parts.push(code.to_string());
+    Some(parts.join("-"))
+}
+
+/// Returns the message shown when the macropad can't be opened: how to fix the
+/// common failures, the whole error chain otherwise
+///
+/// #Arguments
+/// `err` - error opening the macropad
+///
+fn usb_error_message(err: &anyhow::Error) -> String {
+    UsbFailure::find(err).map(|f| f.to_string()).unwrap_or_else(|| format!("{:#}", err))
+}
+
+/// Serializes the mapping and delay of a button for the clipboard, eg. (delay:0,mapping:"ctrl-c")
+///
+/// #Arguments
+/// `btn` - button to copy
+///
+fn clipboard_text(btn: &Button) -> String {
+    ron::to_string(&Button { delay: btn.delay, mapping: btn.mapping.clone(), led_color: None }).unwrap_or_default()
+}
+
+/// Parses a button copied with `clipboard_text` and validates it for the product id
+///
+/// #Arguments
+/// `text` - clipboard contents
+/// `pid` - product id to validate against
+/// `aliases` - aliases of the configuration it is pasted into
+///
+fn parse_clipboard(text: &str, pid: Option<u16>, aliases: &BTreeMap<String, String>) -> anyhow::Result<Button> {
+    let btn: Button = ron::from_str(text.trim()).map_err(|_| anyhow::anyhow!("clipboard doesn't hold a copied mapping"))?;
+    Mapping::validate_aliased_button(&btn, aliases, pid)?;
+    Ok(Button { led_color: None, ..btn })
+}
+
+/// Returns the mapping held by text pasted into the mapping field, once it validates for
+/// the product id along with the delay being edited
+///
+/// #Arguments
+/// `text` - clipboard contents
+/// `delay` - delay of the edited button
+/// `pid` - product id to validate against
+/// `aliases` - aliases of the configuration
+///
+fn parse_pasted_mapping(text: &str, delay: u16, pid: Option<u16>, aliases: &BTreeMap<String, String>) -> anyhow::Result<String> {
+    let mapping = text.trim();
+    anyhow::ensure!(!mapping.is_empty(), "the clipboard is empty");
+    anyhow::ensure!(!mapping.contains('\n'), "the clipboard holds several lines");
+    Mapping::validate_aliased_button(&Button { delay, mapping: mapping.to_string(), led_color: None }, aliases, pid)?;
+    Ok(mapping.to_string())
+}
+
+/// Copies layers into a grid of a new size. Returns the resized layers along with the
+/// number of key and knob mappings that fall outside of it
+///
+/// #Arguments
+/// `old` - layers to copy
+/// `rows` - rows of the new grid
+/// `cols` - columns of the new grid
+/// `knobs` - knobs of the new grid
+/// `layers` - number of layers of the new grid
+///
+fn resize_layers(old: &[Layer], rows: u8, cols: u8, knobs: u8, layers: u8) -> (Vec<Layer>, usize) {
+    let mut resized = vec![Layer::new(rows, cols, knobs); layers as usize];
+    let mut discarded = 0;
+    for (i, layer) in old.iter().enumerate() {
+        for (r, row) in layer.buttons.iter().enumerate() {
+            for (c, btn) in row.iter().enumerate() {
+                match resized.get_mut(i).and_then(|l| l.buttons.get_mut(r)).and_then(|row| row.get_mut(c)) {
+                    Some(slot) => *slot = btn.clone(),
+                    None => if !btn.mapping.is_empty() { discarded += 1; },
+                }
+            }
+        }
+        if let Some(l) = resized.get_mut(i) { l.name = layer.name.clone(); }
+        for (k, knob) in layer.knobs.iter().enumerate() {
+            match resized.get_mut(i).and_then(|l| l.knobs.get_mut(k)) {
+                Some(slot) => *slot = knob.clone(),
+                None => discarded += [&knob.ccw, &knob.press, &knob.cw].iter().filter(|b| !b.mapping.is_empty()).count(),
+            }
+        }
+    }
+    (resized, discarded)
+}
+
+/// Approximates what the LEDs of a row of keys show at a point in time for a mode, as
+/// a preview of the mode. A key press is simulated every `PRESS_PERIOD` seconds on a
+/// different key. `None` is an unlit LED
+///
+/// #Arguments
+/// `mode` - LED mode index as passed to the device
+/// `pid` - product id the mode index belongs to
+/// `color` - color of the mode
+/// `time` - seconds since the preview started
+/// `keys` - number of keys to preview
+///
+fn led_preview_colors(mode: u8, pid: u16, color: LedColor, time: f64, keys: usize) -> Vec<Option<egui::Color32>> {
+    const PRESS_PERIOD: f64 = 1.2;
+    let (r, g, b) = color.rgb();
+    let dim = |level: f64| (level > 0.05).then(|| { let l = level.clamp(0.0, 1.0); egui::Color32::from_rgb((r as f64 * l) as u8, (g as f64 * l) as u8, (b as f64 * l) as u8) });
+    let presses = (time / PRESS_PERIOD).floor() as usize;
+    let pressed = presses * 5 % keys.max(1);
+    let fade = 1.0 - (time / PRESS_PERIOD).fract() * 2.0;
+    (0..keys).map(|key| match (pid, mode) {
+        (_, 0) => None,
+        (0x8890, 1) => (key == pressed).then(|| dim(1.0)).flatten(),
+        (0x8890, 2) => Some(egui::ecolor::Hsva::new((time * 0.25 + key as f64 / keys as f64).fract() as f32, 1.0, 1.0, 1.0).into()),
+        (_, 1) => dim(1.0),
+        // the whole pad flashes on every press
+        (_, 2) => dim(fade),
+        // the flash spreads from the pressed key
+        (_, 3) => dim(fade - key.abs_diff(pressed) as f64 * 0.3),
+        (_, 4) => (key == pressed).then(|| dim(fade)).flatten(),
+        (_, 5) => Some(egui::Color32::WHITE),
+        _ => None,
+    }).collect()
+}
+
+/// Returns the messages of the findings at a position, one per line
+///
+/// #Arguments
+/// `findings` - findings of the current layer
+/// `position` - key or knob action
+///
+fn finding_notes(findings: &[Finding], position: Position) -> String {
+    findings.iter().filter(|f| f.position == position).map(|f| format!("⚠ {}", f.message)).collect::<Vec<_>>().join("\n")
+}
+
+/// Returns the tooltip of a key or knob action: its mapping in plain words (with the
+/// aliases expanded) and its delay
+///
+/// #Arguments
+/// `btn` - button to describe
+/// `aliases` - aliases of the configuration
+///
+fn button_tooltip(btn: &Button, aliases: &BTreeMap<String, String>) -> String {
+    let mapping = Mapping::expand_aliases(&btn.mapping, aliases).unwrap_or_else(|_| btn.mapping.clone());
+    let description = keyboard::describe_mapping(&mapping);
+    if btn.delay > 0 && !btn.mapping.trim().is_empty() { format!("{}\nDelay between presses: {} ms", description, btn.delay) } else { description }
+}
+
+/// Copies a button onto every key of a row
+///
+/// #Arguments
+/// `layer` - layer holding the row
+/// `row` - index of the row
+/// `btn` - button to copy
+///
+fn fill_row(layer: &mut Layer, row: usize, btn: &Button) {
+    if let Some(keys) = layer.buttons.get_mut(row) { keys.iter_mut().for_each(|key| *key = btn.clone()); }
+}
+
+/// Copies a button onto every key of a column
+///
+/// #Arguments
+/// `layer` - layer holding the column
+/// `col` - index of the column
+/// `btn` - button to copy
+///
+fn fill_col(layer: &mut Layer, col: usize, btn: &Button) {
+    for key in layer.buttons.iter_mut().filter_map(|keys| keys.get_mut(col)) { *key = btn.clone(); }
+}
+
+/// Finds every key and knob action whose mapping contains the query, ignoring case
+///
+/// #Arguments
+/// `config` - configuration to search
+/// `query` - text to look for
+///
+fn search_mappings(config: &Macropad, query: &str) -> Vec<(usize, Selection, String)> {
+    let query = query.to_lowercase();
+    let mut results = Vec::new();
+    for (layer_idx, layer) in config.layers.iter().enumerate() {
+        for (r, row) in layer.buttons.iter().enumerate() {
+            for (c, btn) in row.iter().enumerate() {
+                if btn.mapping.to_lowercase().contains(&query) { results.push((layer_idx, Selection::Button(r, c), btn.mapping.clone())); }
+            }
+        }
+        for (i, knob) in layer.knobs.iter().enumerate() {
+            for (part, btn) in [(KnobPart::Ccw, &knob.ccw), (KnobPart::Press, &knob.press), (KnobPart::Cw, &knob.cw)] {
+                if btn.mapping.to_lowercase().contains(&query) { results.push((layer_idx, Selection::Knob(i, part), btn.mapping.clone())); }
+            }
+        }
+    }
+    results
+}
 
 struct EditorData {
     current_layer_idx: usize,
     macropad_data: Macropad,
     selection: Selection,
     connected_pid: Option<u16>,
-    status_msg: String,
-    status_color: egui::Color32,
+    /// bus number, address and product id of every connected macropad
+    devices: Vec<(u8, u8, u16)>,
+    /// bus number and address of the macropad LED/Program commands target
+    selected_device: Option<(u8, u8)>,
+    device_info: Option<Result<DeviceInfo, String>>,
+    /// device the current `device_info` belongs to
+    info_device: Option<(u8, u8)>,
+    /// status messages, oldest first, with the time they were logged
+    status_log: VecDeque<(Instant, String, egui::Color32)>,
+    config_path: String,
+    /// name of the profile the configuration was loaded from or saved to
+    profile: Option<String>,
+    dirty: bool,
+    last_change: Option<Instant>,
+    /// keys and knob actions programmed so far and in total while programming
+    program_progress: Option<(usize, usize)>,
+    history: History,
+}
+
+/// What a run of edits changes, consecutive edits of the same thing are undone at once
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum EditTarget {
+    /// key or knob action of a layer
+    Button(usize, Selection),
+    Leds,
+    /// steps per detent of a knob of a layer
+    KnobSteps(usize, usize),
+    /// name of a layer
+    LayerName(usize),
+}
+
+/// Configurations before the last changes, for undo and redo
+#[derive(Default)]
+struct History {
+    undo: Vec<Macropad>,
+    redo: Vec<Macropad>,
+    last_edit: Option<EditTarget>,
+}
+
+impl History {
+    /// Remembers the configuration before a change, dropping the oldest one once full and
+    /// everything that was undone
+    fn push(&mut self, config: &Macropad) {
+        if self.undo.len() >= consts::UNDO_DEPTH { self.undo.remove(0); }
+        self.undo.push(config.clone());
+        self.redo.clear();
+        self.last_edit = None;
+    }
+
+    /// Remembers the configuration before an edit unless the previous change was an edit
+    /// of the same thing, so typing a mapping is undone as a whole
+    fn push_edit(&mut self, config: &Macropad, target: EditTarget) {
+        if self.last_edit != Some(target) { self.push(config); }
+        self.last_edit = Some(target);
+    }
+
+    /// Returns the configuration before the last change, `current` can then be redone
+    fn undo(&mut self, current: &Macropad) -> Option<Macropad> {
+        let previous = self.undo.pop()?;
+        self.redo.push(current.clone());
+        self.last_edit = None;
+        Some(previous)
+    }
+
+    /// Returns the configuration the last undo reverted, `current` can then be undone
+    fn redo(&mut self, current: &Macropad) -> Option<Macropad> {
+        let next = self.redo.pop()?;
+        self.undo.push(current.clone());
+        self.last_edit = None;
+        Some(next)
+    }
+}
+
+impl EditorData {
+    /// Adds a message to the status log, dropping the oldest once it is full
+    fn log(&mut self, msg: impl Into<String>, color: egui::Color32) {
+        if self.status_log.len() >= consts::STATUS_LOG_SIZE { self.status_log.pop_front(); }
+        self.status_log.push_back((Instant::now(), msg.into(), color));
+    }
+
+    /// Remembers the configuration so the change about to be made can be undone
+    fn snapshot(&mut self) {
+        self.history.push(&self.macropad_data);
+    }
+
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.last_change = Some(Instant::now());
+    }
+
+    fn button_at(&self, selection: Selection) -> Option<&Button> {
+        let layer = self.macropad_data.layers.get(self.current_layer_idx)?;
+        match selection {
+            Selection::Button(r, c) => layer.buttons.get(r)?.get(c),
+            Selection::Knob(idx, part) => { let knob = layer.knobs.get(idx)?; Some(match part { KnobPart::Ccw => &knob.ccw, KnobPart::Press => &knob.press, KnobPart::Cw => &knob.cw }) }
+            Selection::None => None,
+        }
+    }
+
+    fn button_at_mut(&mut self, selection: Selection) -> Option<&mut Button> {
+        let layer = self.macropad_data.layers.get_mut(self.current_layer_idx)?;
+        match selection {
+            Selection::Button(r, c) => layer.buttons.get_mut(r)?.get_mut(c),
+            Selection::Knob(idx, part) => { let knob = layer.knobs.get_mut(idx)?; Some(match part { KnobPart::Ccw => &mut knob.ccw, KnobPart::Press => &mut knob.press, KnobPart::Cw => &mut knob.cw }) }
+            Selection::None => None,
+        }
+    }
 }
 
 lazy_static::lazy_static! {
@@ -51,15 +493,86 @@ lazy_static::lazy_static! {
         macropad_data: Macropad::new(2, 3, 1),
         selection: Selection::None,
         connected_pid: None,
-        status_msg: "Welcome to Macropad Editor Pro".to_string(),
-        status_color: egui::Color32::LIGHT_GRAY,
+        devices: Vec::new(),
+        selected_device: None,
+        device_info: None,
+        info_device: None,
+        status_log: VecDeque::from([(Instant::now(), "Welcome to Macropad Editor Pro".to_string(), egui::Color32::LIGHT_GRAY)]),
+        config_path: consts::DEFAULT_CONFIG.to_string(),
+        profile: None,
+        dirty: false,
+        last_change: None,
+        program_progress: None,
+        history: History::default(),
     }));
 }
 
+/// Mapping, delay and product id last validated in the edit panel along with the outcome
+struct TempValidation {
+    mapping: String,
+    delay: String,
+    pid: Option<u16>,
+    result: Result<(), String>,
+}
+
+/// Result of the connection worker, drained by the UI in `update`
+enum ConnectionUpdate {
+    /// bus number, address and product id of every connected macropad
+    Devices(Vec<(u8, u8, u16)>),
+    /// identity read from the macropad at a bus number and address
+    Info((u8, u8), Result<DeviceInfo, String>),
+    /// key matrix (rows, cols, knobs, layers) read from the macropad
+    Layout(Result<(u8, u8, u8, u8), String>),
+}
+
+/// Request to the connection worker
+enum WorkerRequest {
+    /// read the identity of the macropad at a bus number and address
+    ReadInfo((u8, u8)),
+    /// ask the macropad at a bus number and address for its key matrix
+    QueryLayout((u8, u8)),
+    /// a device was attached or removed, look for macropads again
+    Rescan,
+}
+
+/// Wakes up the connection worker when a device of the macropad vendor is attached or removed
+struct HotplugNotifier(mpsc::Sender<WorkerRequest>);
+
+impl<T: rusb::UsbContext> rusb::Hotplug<T> for HotplugNotifier {
+    fn device_arrived(&mut self, _device: rusb::Device<T>) { let _ = self.0.send(WorkerRequest::Rescan); }
+    fn device_left(&mut self, _device: rusb::Device<T>) { let _ = self.0.send(WorkerRequest::Rescan); }
+}
+
 struct MacropadApp {
-    last_conn_check: Instant,
+    settings: AppSettings,
+    /// updates from the connection worker
+    connection: mpsc::Receiver<ConnectionUpdate>,
+    /// requests to the connection worker
+    worker_requests: mpsc::Sender<WorkerRequest>,
+    window_title: String,
+    profiles: Vec<String>,
+    profile_name: String,
+    pending_action: Option<PendingAction>,
+    search_query: String,
     temp_editor_val: String,
     temp_delay_val: String,
+    temp_led_color: Option<LedColor>,
+    temp_validation: Option<TempValidation>,
+    capturing: bool,
+    /// when a paste button asked for the clipboard contents, and what they replace
+    paste_requested: Option<(Instant, PasteTarget)>,
+    /// why the text pasted into the mapping field was rejected
+    paste_error: Option<String>,
+    /// key or knob action of the current layer waiting for the one to swap with
+    swap_source: Option<Selection>,
+    /// layer read from a file, waiting to be resized to the layout
+    imported_layer: Option<Layer>,
+    /// aliases being edited (name, mapping) while the alias dialog is open
+    alias_rows: Option<Vec<(String, String)>>,
+    /// build the packets and show them instead of programming the device
+    dry_run: bool,
+    /// product id and packets of the last dry run, shown until the window is closed
+    packet_dump: Option<(u16, Vec<PacketGroup>)>,
     
     ui_rows: u8,
     ui_cols: u8,
@@ -70,181 +583,962 @@ struct MacropadApp {
     led_mode: u8,
     led_layer: u8,
     led_color: LedColor,
+    /// in percent
+    led_brightness: u8,
 }
 
 impl MacropadApp {
-    fn new() -> Self {
-        let initial_data = Mapping::read("mapping.ron").unwrap_or_else(|_| Macropad::new(2, 3, 1));
+    fn new(settings: AppSettings) -> Self {
+        let config_path = settings.last_config.clone().filter(|p| std::path::Path::new(p).exists()).unwrap_or(consts::DEFAULT_CONFIG.to_string());
+        let initial_data = Mapping::read(&config_path).unwrap_or_else(|_| Macropad::new(2, 3, 1));
         
-        let (led_m, led_l, led_c) = if let Some(led) = &initial_data.led_settings {
-            (led.mode, led.layer, led.color)
+        let (led_m, led_l, led_c, led_b) = if let Some(led) = &initial_data.led_settings {
+            (led.mode, led.layer, led.color, led.brightness)
         } else {
-            (1, 1, LedColor::Cyan)
+            (1, 1, LedColor::Cyan, consts::MAX_LED_BRIGHTNESS)
         };
 
         let initial_rows = initial_data.device.rows;
         let initial_cols = initial_data.device.cols;
         let initial_knobs = initial_data.device.knobs;
-        let initial_layers = initial_data.device.layers;
+        let initial_layers = initial_data.device.layers.clamp(1, consts::max_layers(None));
         let initial_orient = initial_data.device.orientation;
 
         {
             let mut d = DATA.lock().unwrap();
             d.macropad_data = initial_data;
+            let path = std::path::Path::new(&config_path);
+            if path.parent() == Some(profiles::profiles_dir().as_path()) { d.profile = path.file_stem().map(|s| s.to_string_lossy().to_string()); }
+            d.config_path = config_path;
         }
         
+        let (connection, worker_requests) = Self::spawn_connection_worker();
         Self {
-            last_conn_check: Instant::now() - Duration::from_secs(10),
+            settings,
+            connection,
+            worker_requests,
+            window_title: String::new(),
+            profiles: profiles::list_profiles().unwrap_or_default(),
+            profile_name: String::new(),
+            pending_action: None,
+            search_query: String::new(),
             temp_editor_val: String::new(),
             temp_delay_val: String::new(),
+            temp_led_color: None,
+            temp_validation: None,
+            capturing: false,
+            paste_requested: None,
+            paste_error: None,
+            swap_source: None,
+            imported_layer: None,
+            alias_rows: None,
+            dry_run: false,
+            packet_dump: None,
             ui_rows: initial_rows,
             ui_cols: initial_cols,
             ui_knobs: initial_knobs,
             ui_layers: initial_layers,
             ui_orientation: initial_orient,
             led_mode: led_m,
-            led_layer: led_l,
+            led_layer: led_l.clamp(1, initial_layers),
             led_color: led_c,
+            led_brightness: led_b,
         }
     }
 
-    fn check_connection() {
-        thread::spawn(|| {
-            let pid = match find_device(VENDOR_ID, None) {
-                Ok((_, _, pid)) => Some(pid),
-                Err(_) => None,
-            };
-            if let Ok(mut data) = DATA.lock() {
-                data.connected_pid = pid;
+    /// Starts the thread looking for connected macropads. It looks again whenever a
+    /// hotplug event reports a device was attached or removed, or every
+    /// `CONNECTION_POLL_INTERVAL` where libusb has no hotplug support. In between it
+    /// reads the identity of the macropads the UI asks for, and stops once the UI is gone
+    fn spawn_connection_worker() -> (mpsc::Receiver<ConnectionUpdate>, mpsc::Sender<WorkerRequest>) {
+        let (update_tx, update_rx) = mpsc::channel();
+        let (request_tx, request_rx) = mpsc::channel();
+        let hotplug_tx = request_tx.clone();
+        thread::spawn(move || {
+            let hotplug = Self::watch_hotplug(hotplug_tx);
+            let mut next_poll = Instant::now();
+            loop {
+                if Instant::now() >= next_poll {
+                    let devices = find_all_devices(VENDOR_ID).unwrap_or_default();
+                    if update_tx.send(ConnectionUpdate::Devices(devices)).is_err() { return; }
+                    next_poll = if hotplug { Instant::now() + Duration::from_secs(24 * 3600) } else { Instant::now() + consts::CONNECTION_POLL_INTERVAL };
+                }
+                match request_rx.recv_timeout(next_poll.saturating_duration_since(Instant::now())) {
+                    Ok(WorkerRequest::ReadInfo(address)) => {
+                        let info = open_keyboard(&Self::usb_options(Some(address))).and_then(|mut kb| kb.read_device_info()).map_err(|e| if e.is::<Unsupported>() { "not supported by this device".to_string() } else { format!("{:#}", e) });
+                        if update_tx.send(ConnectionUpdate::Info(address, info)).is_err() { return; }
+                    }
+                    Ok(WorkerRequest::QueryLayout(address)) => {
+                        let layout = open_keyboard_retry(&Self::usb_options(Some(address))).and_then(|mut kb| kb.query_layout()).map_err(|e| usb_error_message(&e));
+                        if update_tx.send(ConnectionUpdate::Layout(layout)).is_err() { return; }
+                    }
+                    Ok(WorkerRequest::Rescan) | Err(mpsc::RecvTimeoutError::Timeout) => next_poll = Instant::now(),
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
             }
         });
+        (update_rx, request_tx)
+    }
+
+    /// Registers for hotplug events of the macropad vendor on a thread of its own,
+    /// returns false when libusb doesn't support hotplug on this platform
+    fn watch_hotplug(requests: mpsc::Sender<WorkerRequest>) -> bool {
+        if !rusb::has_hotplug() { return false; }
+        let (registered_tx, registered_rx) = mpsc::channel();
+        thread::spawn(move || {
+            let Ok(context) = rusb::Context::new() else { let _ = registered_tx.send(false); return; };
+            let registration = rusb::HotplugBuilder::new().vendor_id(VENDOR_ID).register::<rusb::Context, _>(&context, Box::new(HotplugNotifier(requests)));
+            let _ = registered_tx.send(registration.is_ok());
+            if registration.is_ok() { while context.handle_events(None).is_ok() {} }
+        });
+        registered_rx.recv().unwrap_or(false)
+    }
+
+    /// Applies the updates of the connection worker without waiting for new ones. When
+    /// the selected macropad is unplugged the selection is cleared and a warning logged.
+    /// A detected layout is applied like one picked by hand
+    fn drain_connection_updates(&mut self) {
+        let mut detected = None;
+        while let Ok(update) = self.connection.try_recv() {
+            let mut data = DATA.lock().unwrap();
+            match update {
+                ConnectionUpdate::Devices(devices) => {
+                    let present = |s: &(u8, u8)| devices.iter().any(|d| (d.0, d.1) == *s);
+                    let selected = match data.selected_device {
+                        Some(s) if present(&s) => Some(s),
+                        Some(s) => {
+                            let pid = data.devices.iter().find(|d| (d.0, d.1) == s).map(|d| d.2).unwrap_or_default();
+                            let hint = if devices.is_empty() { String::new() } else { ", select another one to program".to_string() };
+                            data.log(format!("⚠ The selected macropad (0x{:04x} @ {}:{}) was unplugged{}", pid, s.0, s.1, hint), egui::Color32::GOLD);
+                            None
+                        }
+                        // pick a pad on its own only when the choice is obvious
+                        None if devices.len() == 1 || data.devices.is_empty() => devices.first().map(|d| (d.0, d.1)),
+                        None => None,
+                    };
+                    data.connected_pid = selected.and_then(|s| devices.iter().find(|d| (d.0, d.1) == s)).map(|d| d.2);
+                    data.selected_device = selected; data.devices = devices;
+                    // only query a newly selected device, not on every poll
+                    if data.info_device != selected {
+                        data.info_device = selected; data.device_info = None;
+                        if let Some(address) = selected { let _ = self.worker_requests.send(WorkerRequest::ReadInfo(address)); }
+                    }
+                }
+                ConnectionUpdate::Info(address, info) => { if data.info_device == Some(address) { data.device_info = Some(info); } }
+                ConnectionUpdate::Layout(Ok(layout)) => detected = Some(layout),
+                ConnectionUpdate::Layout(Err(e)) => data.log(format!("❌ Layout detection failed: {}", e), egui::Color32::RED),
+            }
+        }
+        if let Some((rows, cols, knobs, layers)) = detected {
+            Self::set_status(&format!("🔎 Detected {} rows x {} cols, {} knob(s) and {} layers", rows, cols, knobs, layers), egui::Color32::KHAKI);
+            (self.ui_rows, self.ui_cols, self.ui_knobs, self.ui_layers) = (rows, cols, knobs, layers);
+            self.request_apply_layout();
+        }
+    }
+
+    /// Picks the macropad LED and Program commands target when several are connected
+    fn device_combo(ui: &mut egui::Ui, data: &mut EditorData) {
+        let describe = |d: &(u8, u8, u16)| format!("0x{:04x} @ {}:{}", d.2, d.0, d.1);
+        let selected_text = data.devices.iter().find(|d| Some((d.0, d.1)) == data.selected_device).map(describe).unwrap_or("Select...".to_string());
+        let mut selected = data.selected_device;
+        egui::ComboBox::from_id_salt("device_cb").selected_text(selected_text).show_ui(ui, |ui| {
+            for d in &data.devices { ui.selectable_value(&mut selected, Some((d.0, d.1)), describe(d)); }
+        }).response.on_hover_text("Macropad that LED and Program commands target");
+        if selected != data.selected_device {
+            data.connected_pid = data.devices.iter().find(|d| Some((d.0, d.1)) == selected).map(|d| d.2);
+            data.selected_device = selected;
+        }
+    }
+
+    fn usb_options(address: Option<(u8, u8)>) -> DevelOptions {
+        DevelOptions { vendor_id: VENDOR_ID, product_id: None, address, out_endpoint_address: None, in_endpoint_address: None, interface_number: None }
     }
 
     fn set_status(msg: &str, color: egui::Color32) {
         if let Ok(mut data) = DATA.lock() {
-            data.status_msg = msg.to_string();
-            data.status_color = color;
+            data.log(msg, color);
         }
     }
 
+    /// Applies the layout settings, asking first when mappings would fall outside the new grid
+    fn request_apply_layout(&mut self) {
+        let discarded = resize_layers(&DATA.lock().unwrap().macropad_data.layers, self.ui_rows, self.ui_cols, self.ui_knobs, self.ui_layers).1;
+        if discarded > 0 { self.pending_action = Some(PendingAction::ApplyLayout(discarded)); } else { self.apply_layout(); }
+    }
+
     fn apply_layout(&mut self) {
         let mut data = DATA.lock().unwrap();
+        data.snapshot();
         data.macropad_data.device.rows = self.ui_rows;
         data.macropad_data.device.cols = self.ui_cols;
         data.macropad_data.device.knobs = self.ui_knobs;
         data.macropad_data.device.layers = self.ui_layers;
         data.macropad_data.device.orientation = self.ui_orientation;
+
+        let (layers, discarded) = resize_layers(&data.macropad_data.layers, self.ui_rows, self.ui_cols, self.ui_knobs, self.ui_layers);
+        data.macropad_data.layers = layers;
         
-        let old_layers = data.macropad_data.layers.clone();
-        data.macropad_data.layers = vec![Layer::new(self.ui_rows, self.ui_cols, self.ui_knobs); self.ui_layers as usize];
-        
-        for i in 0..(self.ui_layers as usize) {
-            if i < old_layers.len() {
-                let mut new_layer = Layer::new(self.ui_rows, self.ui_cols, self.ui_knobs);
-                for row in 0..(self.ui_rows as usize) {
-                    for col in 0..(self.ui_cols as usize) {
-                        if row < old_layers[i].buttons.len() && col < old_layers[i].buttons[row].len() {
-                            new_layer.buttons[row][col] = old_layers[i].buttons[row][col].clone();
-                        }
-                    }
-                }
-                for knob in 0..(self.ui_knobs as usize) {
-                    if knob < old_layers[i].knobs.len() {
-                        new_layer.knobs[knob] = old_layers[i].knobs[knob].clone();
-                    }
-                }
-                data.macropad_data.layers[i] = new_layer;
-            }
-        }
-        
-        data.selection = Selection::None;
+        data.selection = Selection::None; data.mark_dirty();
         if data.current_layer_idx >= self.ui_layers as usize { data.current_layer_idx = 0; }
+        self.led_layer = self.led_layer.clamp(1, self.ui_layers);
         self.temp_editor_val = String::new();
         self.temp_delay_val = String::new();
-        data.status_msg = format!("Applied: {} layers, {}x{} grid.", self.ui_layers, self.ui_rows, self.ui_cols);
-        data.status_color = egui::Color32::KHAKI;
+        let lost = if discarded > 0 { format!(" {} mapping(s) outside the grid were discarded.", discarded) } else { String::new() };
+        data.log(format!("Applied: {} layers, {}x{} grid.{}", self.ui_layers, self.ui_rows, self.ui_cols, lost), egui::Color32::KHAKI);
+    }
+
+    /// Reverts the last change, or redoes the last undone one, including layout and LED
+    /// settings
+    fn undo(&mut self, redo: bool) {
+        let mut d = DATA.lock().unwrap();
+        self.sync_temp_to_data(&mut d);
+        let EditorData { history, macropad_data, .. } = &mut *d;
+        let Some(config) = (if redo { history.redo(macropad_data) } else { history.undo(macropad_data) }) else { return; };
+        self.reset_ui_from_config(&config);
+        d.macropad_data = config;
+        if d.current_layer_idx >= d.macropad_data.layers.len() { d.current_layer_idx = 0; }
+        if d.button_at(d.selection).is_none() { d.selection = Selection::None; }
+        self.swap_source = None; self.temp_validation = None;
+        self.sync_data_to_temp(&d);
+        d.mark_dirty();
+        d.log(if redo { "↷ Redone" } else { "↶ Undone" }, egui::Color32::KHAKI);
     }
 
     fn sync_temp_to_data(&self, data: &mut MutexGuard<EditorData>) {
-        let layer_idx = data.current_layer_idx;
         let delay = self.temp_delay_val.parse::<u16>().unwrap_or(0);
-        match data.selection {
-            Selection::Button(r, c) => {
-                if layer_idx < data.macropad_data.layers.len() {
-                    data.macropad_data.layers[layer_idx].buttons[r][c].mapping = self.temp_editor_val.clone();
-                    data.macropad_data.layers[layer_idx].buttons[r][c].delay = delay;
-                }
+        let mut changed = false;
+        let selection = data.selection;
+        // knobs have no LEDs of their own
+        let is_key = matches!(selection, Selection::Button(..));
+        let edited = data.button_at(selection).map(|btn| Button { delay, mapping: self.temp_editor_val.clone(), led_color: if is_key { self.temp_led_color } else { btn.led_color } });
+        if let Some(edited) = edited.filter(|e| data.button_at(selection).is_some_and(|btn| btn.mapping != e.mapping || btn.delay != e.delay || btn.led_color != e.led_color)) {
+            let target = EditTarget::Button(data.current_layer_idx, selection);
+            let EditorData { history, macropad_data, .. } = &mut **data; history.push_edit(macropad_data, target);
+            if let Some(btn) = data.button_at_mut(selection) { *btn = edited; }
+            changed = true;
+        }
+        let led_settings = Some(LedSettings { mode: self.led_mode, layer: self.led_layer, color: self.led_color, brightness: self.led_brightness });
+        if data.macropad_data.led_settings != led_settings {
+            // a configuration without LED settings only gets the defaults filled in
+            if data.macropad_data.led_settings.is_some() { let EditorData { history, macropad_data, .. } = &mut **data; history.push_edit(macropad_data, EditTarget::Leds); }
+            data.macropad_data.led_settings = led_settings; changed = true;
+        }
+        if changed { data.mark_dirty(); }
+    }
+
+    /// Validates the mapping being edited. The outcome is cached until the mapping, delay
+    /// or connected device changes, so warnings aren't repeated on every frame
+    fn validate_temp(&mut self, pid: Option<u16>, aliases: &BTreeMap<String, String>) -> Result<(), String> {
+        if let Some(v) = &self.temp_validation {
+            if v.mapping == self.temp_editor_val && v.delay == self.temp_delay_val && v.pid == pid { return v.result.clone(); }
+        }
+        let result = match self.temp_delay_val.parse::<u16>() {
+            Err(_) if !self.temp_delay_val.is_empty() => Err(format!("delay '{}' is not a number", self.temp_delay_val)),
+            delay => {
+                let btn = Button { delay: delay.unwrap_or(0), mapping: self.temp_editor_val.clone(), led_color: None };
+                Mapping::validate_aliased_button(&btn, aliases, pid).map_err(|e| format!("{:#}", e))
             }
-            Selection::Knob(idx, part) => {
-                if layer_idx < data.macropad_data.layers.len() {
-                    let knob = &mut data.macropad_data.layers[layer_idx].knobs[idx];
-                    match part {
-                        KnobPart::CCW => { knob.ccw.mapping = self.temp_editor_val.clone(); knob.ccw.delay = delay; }
-                        KnobPart::Press => { knob.press.mapping = self.temp_editor_val.clone(); knob.press.delay = delay; }
-                        KnobPart::CW => { knob.cw.mapping = self.temp_editor_val.clone(); knob.cw.delay = delay; }
-                    }
+        };
+        self.temp_validation = Some(TempValidation { mapping: self.temp_editor_val.clone(), delay: self.temp_delay_val.clone(), pid, result: result.clone() });
+        result
+    }
+
+    /// Returns false while the selected key or knob has an invalid mapping
+    fn selection_valid(&mut self) -> bool {
+        let (selection, pid, aliases) = { let d = DATA.lock().unwrap(); (d.selection, d.connected_pid, d.macropad_data.aliases.clone()) };
+        selection == Selection::None || self.validate_temp(pid, &aliases).is_ok()
+    }
+
+    /// Saves the configuration once it has been left unchanged for `consts::AUTOSAVE_DELAY`
+    fn autosave(&self) {
+        let due = { let d = DATA.lock().unwrap(); d.dirty && d.last_change.is_some_and(|t| t.elapsed() >= consts::AUTOSAVE_DELAY) };
+        if due { self.save_config(); }
+    }
+
+    fn sync_data_to_temp(&mut self, data: &EditorData) {
+        self.capturing = false;
+        self.paste_error = None;
+        match data.button_at(data.selection) {
+            Some(btn) => {
+                self.temp_editor_val = btn.mapping.clone();
+                self.temp_delay_val = btn.delay.to_string();
+                self.temp_led_color = if matches!(data.selection, Selection::Button(..)) { btn.led_color } else { None };
+            }
+            None => { self.temp_editor_val = String::new(); self.temp_delay_val = String::new(); self.temp_led_color = None; }
+        }
+    }
+
+    fn switch_layer(&mut self, data: &mut MutexGuard<EditorData>, layer_idx: usize) {
+        self.sync_temp_to_data(data);
+        self.swap_source = None;
+        data.current_layer_idx = layer_idx;
+        self.sync_data_to_temp(data);
+    }
+
+    fn save_config(&self) {
+        let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d);
+        match Mapping::save(&d.macropad_data, &d.config_path) {
+            Ok(_) => { d.dirty = false; let msg = format!("✅ Config saved to {}", d.config_path); d.log(msg, egui::Color32::GREEN); }
+            Err(e) => { d.log(format!("❌ Save error: {}", e), egui::Color32::RED); }
+        }
+    }
+
+    /// Updates the layout and LED controls to reflect a newly loaded configuration
+    fn reset_ui_from_config(&mut self, config: &Macropad) {
+        self.ui_rows = config.device.rows;
+        self.ui_cols = config.device.cols;
+        self.ui_knobs = config.device.knobs;
+        self.ui_layers = config.device.layers.clamp(1, consts::max_layers(None));
+        self.ui_orientation = config.device.orientation;
+        let led = config.led_settings.unwrap_or(LedSettings { mode: 1, layer: 1, color: LedColor::Cyan, brightness: consts::MAX_LED_BRIGHTNESS });
+        self.led_mode = led.mode; self.led_layer = led.layer.clamp(1, self.ui_layers); self.led_color = led.color; self.led_brightness = led.brightness;
+        self.temp_editor_val = String::new(); self.temp_delay_val = String::new(); self.temp_led_color = None;
+    }
+
+    fn open_config(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("RON config", &["ron"]).pick_file() else { return; };
+        let path = path.to_string_lossy().to_string();
+        match Mapping::read(&path) {
+            Ok(config) => {
+                self.reset_ui_from_config(&config);
+                let mut d = DATA.lock().unwrap();
+                d.macropad_data = config; d.config_path = path; d.profile = None; d.selection = Selection::None; d.current_layer_idx = 0; d.dirty = false; d.history = History::default();
+                let msg = format!("📂 Opened {}", d.config_path); d.log(msg, egui::Color32::GREEN);
+            }
+            Err(e) => Self::set_status(&format!("❌ Open error: {:#}", e), egui::Color32::RED),
+        }
+    }
+
+    fn save_config_as(&self) {
+        let file_name = std::path::Path::new(&DATA.lock().unwrap().config_path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or(consts::DEFAULT_CONFIG.to_string());
+        let Some(path) = rfd::FileDialog::new().add_filter("RON config", &["ron"]).set_file_name(file_name).save_file() else { return; };
+        { let mut d = DATA.lock().unwrap(); d.config_path = path.to_string_lossy().to_string(); d.profile = None; }
+        self.save_config();
+    }
+
+    /// Replaces the configuration with a starter template, warning when its size doesn't
+    /// match what the connected macropad reports
+    fn load_template(&mut self, idx: usize) {
+        let Some(template) = templates::templates().into_iter().nth(idx) else { return; };
+        let mut config = template.config;
+        let mut d = DATA.lock().unwrap();
+        config.device.layers = config.device.layers.min(consts::max_layers(d.connected_pid));
+        config.layers.truncate(config.device.layers as usize);
+        self.reset_ui_from_config(&config);
+        let (keys, knobs) = (config.device.rows * config.device.cols, config.device.knobs);
+        d.snapshot();
+        d.macropad_data = config; d.profile = None; d.selection = Selection::None; d.current_layer_idx = 0; d.mark_dirty();
+        d.log(format!("✨ Loaded the '{}' template", template.name), egui::Color32::GREEN);
+        if let Some(Ok(info)) = &d.device_info {
+            if info.num_keys.is_some_and(|k| k != keys) || info.num_encoders.is_some_and(|e| e != knobs) {
+                let msg = format!("⚠ The template has {} keys and {} knob(s) but the macropad reports {} keys and {} knob(s), adjust the layout before programming", keys, knobs, info.num_keys.unwrap_or(keys), info.num_encoders.unwrap_or(knobs));
+                d.log(msg, egui::Color32::GOLD);
+            }
+        }
+    }
+
+    fn refresh_profiles(&mut self) {
+        match profiles::list_profiles() {
+            Ok(names) => self.profiles = names,
+            Err(e) => Self::set_status(&format!("❌ Profiles error: {:#}", e), egui::Color32::RED),
+        }
+    }
+
+    /// Replaces the edited configuration with a saved profile
+    fn load_profile(&mut self, name: &str) {
+        match profiles::load_profile(name) {
+            Ok(config) => {
+                self.reset_ui_from_config(&config);
+                self.profile_name = name.to_string();
+                let mut d = DATA.lock().unwrap();
+                d.macropad_data = config; d.config_path = profiles::profiles_dir().join(format!("{}.ron", name)).to_string_lossy().to_string();
+                d.profile = Some(name.to_string()); d.selection = Selection::None; d.current_layer_idx = 0; d.dirty = false; d.history = History::default();
+                d.log(format!("📂 Loaded profile '{}'", name), egui::Color32::GREEN);
+            }
+            Err(e) => Self::set_status(&format!("❌ Profile error: {:#}", e), egui::Color32::RED),
+        }
+    }
+
+    fn save_profile(&mut self) {
+        let name = self.profile_name.trim().to_string();
+        let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d);
+        match profiles::save_profile(&name, &d.macropad_data) {
+            Ok(path) => { d.config_path = path.to_string_lossy().to_string(); d.profile = Some(name.clone()); d.dirty = false; d.log(format!("✅ Saved profile '{}'", name), egui::Color32::GREEN); }
+            Err(e) => { d.log(format!("❌ Profile error: {:#}", e), egui::Color32::RED); return; }
+        }
+        drop(d);
+        self.refresh_profiles();
+    }
+
+    /// Deletes the profile in the name field. A deleted active profile leaves the configuration
+    /// in the editor, unsaved and pointing at the default config file
+    fn delete_profile(&mut self) {
+        let name = self.profile_name.trim().to_string();
+        let mut d = DATA.lock().unwrap();
+        match profiles::delete_profile(&name) {
+            Ok(()) => {
+                if d.profile.as_deref() == Some(name.as_str()) { d.profile = None; d.config_path = consts::DEFAULT_CONFIG.to_string(); d.mark_dirty(); }
+                d.log(format!("🗑 Deleted profile '{}'", name), egui::Color32::KHAKI);
+            }
+            Err(e) => { d.log(format!("❌ Profile error: {:#}", e), egui::Color32::RED); return; }
+        }
+        drop(d);
+        self.profile_name.clear();
+        self.refresh_profiles();
+    }
+
+    fn clear_layer(&mut self, layer_idx: usize) {
+        let mut d = DATA.lock().unwrap();
+        if layer_idx >= d.macropad_data.layers.len() { return; }
+        let device = &d.macropad_data.device;
+        let mut empty = Layer::new(device.rows, device.cols, device.knobs);
+        d.snapshot();
+        empty.name = d.macropad_data.layers[layer_idx].name.take();
+        d.macropad_data.layers[layer_idx] = empty;
+        d.selection = Selection::None; d.mark_dirty();
+        self.sync_data_to_temp(&d);
+        d.log(format!("🗑 Cleared layer {}", layer_idx + 1), egui::Color32::KHAKI);
+    }
+
+    /// Mirrors the current layer and moves the selection along with the mirrored key
+    fn mirror_layer(&mut self, data: &mut MutexGuard<EditorData>, horizontal: bool) {
+        self.sync_temp_to_data(data);
+        let layer_idx = data.current_layer_idx;
+        let (rows, cols, knobs) = (data.macropad_data.device.rows as usize, data.macropad_data.device.cols as usize, data.macropad_data.device.knobs as usize);
+        data.snapshot();
+        let layer = &mut data.macropad_data.layers[layer_idx];
+        if horizontal { layer.mirror_horizontal(); } else { layer.mirror_vertical(); }
+        data.selection = match data.selection {
+            Selection::Button(r, c) if horizontal => Selection::Button(r, cols - 1 - c),
+            Selection::Button(r, c) => Selection::Button(rows - 1 - r, c),
+            Selection::Knob(k, part) if horizontal => Selection::Knob(knobs - 1 - k, part),
+            other => other,
+        };
+        self.swap_source = None;
+        data.mark_dirty();
+        data.log(format!("⇔ Mirrored layer {} {}", layer_idx + 1, if horizontal { "horizontally" } else { "vertically" }), egui::Color32::KHAKI);
+        self.sync_data_to_temp(data);
+    }
+
+    /// Saves the current layer to its own file
+    fn export_layer(&self) {
+        let mut d = DATA.lock().unwrap();
+        self.sync_temp_to_data(&mut d);
+        let layer_idx = d.current_layer_idx;
+        let Some(path) = rfd::FileDialog::new().add_filter("RON layer", &["ron"]).set_file_name(format!("layer{}.ron", layer_idx + 1)).save_file() else { return; };
+        let path = path.to_string_lossy().to_string();
+        match Mapping::export_layer(&d.macropad_data.layers[layer_idx], &path) {
+            Ok(()) => d.log(format!("💾 Exported layer {} to {}", layer_idx + 1, path), egui::Color32::GREEN),
+            Err(e) => d.log(format!("❌ Export error: {:#}", e), egui::Color32::RED),
+        }
+    }
+
+    /// Loads a layer file into the current layer, asking first when its size doesn't match the layout
+    fn import_layer(&mut self) {
+        let Some(path) = rfd::FileDialog::new().add_filter("RON layer", &["ron", "json"]).pick_file() else { return; };
+        match Mapping::import_layer(&path.to_string_lossy()) {
+            Ok(layer) => {
+                let device = DATA.lock().unwrap().macropad_data.device.clone();
+                let fits = layer.buttons.len() == device.rows as usize && layer.buttons.iter().all(|row| row.len() == device.cols as usize) && layer.knobs.len() == device.knobs as usize;
+                let (_, discarded) = resize_layers(std::slice::from_ref(&layer), device.rows, device.cols, device.knobs, 1);
+                self.imported_layer = Some(layer);
+                if fits { self.place_imported_layer(); } else { self.pending_action = Some(PendingAction::ImportLayer(discarded)); }
+            }
+            Err(e) => Self::set_status(&format!("❌ Import error: {:#}", e), egui::Color32::RED),
+        }
+    }
+
+    /// Replaces the current layer with the imported one, resized to the layout
+    fn place_imported_layer(&mut self) {
+        let Some(layer) = self.imported_layer.take() else { return; };
+        let mut d = DATA.lock().unwrap();
+        let device = &d.macropad_data.device;
+        let (mut resized, _) = resize_layers(&[layer], device.rows, device.cols, device.knobs, 1);
+        let layer_idx = d.current_layer_idx;
+        d.snapshot();
+        d.macropad_data.layers[layer_idx] = resized.remove(0);
+        d.selection = Selection::None; self.swap_source = None; d.mark_dirty();
+        self.sync_data_to_temp(&d);
+        d.log(format!("📂 Imported a layer into layer {}", layer_idx + 1), egui::Color32::GREEN);
+    }
+
+    /// Shows the dialog editing the aliases of the configuration, which are only stored
+    /// once they are all valid
+    fn show_alias_editor(&mut self, ctx: &egui::Context) {
+        let Some(rows) = self.alias_rows.as_mut() else { return; };
+        let (mut apply, mut close) = (false, false);
+        let aliases: BTreeMap<String, String> = rows.iter().map(|(n, m)| (n.trim().to_lowercase(), m.trim().to_string())).collect();
+        let mut errors = Mapping::check_aliases(&aliases);
+        if aliases.len() < rows.len() { errors.insert(0, "alias names must be unique".to_string()); }
+        egui::Window::new("Key Aliases").collapsible(false).resizable(false).show(ctx, |ui| {
+            ui.label(egui::RichText::new("An alias can be used wherever a key is expected, eg. ctl-c with ctl → ctrl").italics().size(11.0));
+            ui.add_space(6.0);
+            let mut remove = None;
+            egui::Grid::new("aliases_grid").show(ui, |ui| {
+                for (i, (name, mapping)) in rows.iter_mut().enumerate() {
+                    ui.add(egui::TextEdit::singleline(name).hint_text("name").desired_width(80.0));
+                    ui.label("→");
+                    ui.add(egui::TextEdit::singleline(mapping).hint_text("key or sequence").desired_width(160.0));
+                    if ui.button("🗑").clicked() { remove = Some(i); }
+                    ui.end_row();
                 }
+            });
+            if let Some(i) = remove { rows.remove(i); }
+            if ui.button("➕ Add alias").clicked() { rows.push((String::new(), String::new())); }
+            for e in &errors { ui.label(egui::RichText::new(format!("❌ {}", e)).size(11.0).color(egui::Color32::RED)); }
+            ui.add_space(8.0);
+            ui.horizontal(|ui| {
+                apply = ui.add_enabled(errors.is_empty(), egui::Button::new("Apply")).clicked();
+                close = ui.button("Cancel").clicked();
+            });
+        });
+        if apply {
+            let mut d = DATA.lock().unwrap();
+            if d.macropad_data.aliases != aliases { d.snapshot(); d.macropad_data.aliases = aliases; d.mark_dirty(); }
+            let count = d.macropad_data.aliases.len();
+            d.log(format!("🔤 {} key alias(es) defined", count), egui::Color32::KHAKI);
+            self.temp_validation = None;
+        }
+        if apply || close { self.alias_rows = None; }
+    }
+
+    /// Shows a modal asking to confirm the pending destructive action and runs it on confirm
+    fn show_confirmation(&mut self, ctx: &egui::Context) {
+        let Some(action) = self.pending_action else { return; };
+        let message = match action {
+            PendingAction::ClearLayer(idx) => format!("Clear every key and knob mapping on layer {}?", idx + 1),
+            PendingAction::DeleteProfile => format!("Delete the profile '{}'?", self.profile_name.trim()),
+            PendingAction::LoadTemplate(idx) => format!("Replace the current configuration with the '{}' template?", templates::templates().get(idx).map(|t| t.name).unwrap_or_default()),
+            PendingAction::Fill(row, col, whole_row) => format!("Overwrite the mapped keys of {} with key {},{}?", if whole_row { format!("row {}", row + 1) } else { format!("column {}", col + 1) }, row + 1, col + 1),
+            PendingAction::ApplyLayout(discarded) => format!("{} key/knob mapping(s) fall outside the new layout and will be discarded. Apply the layout anyway?", discarded),
+            PendingAction::ImportLayer(discarded) => {
+                let d = DATA.lock().unwrap();
+                let device = &d.macropad_data.device;
+                let (rows, cols, knobs) = self.imported_layer.as_ref().map(|l| (l.buttons.len(), l.buttons.iter().map(Vec::len).max().unwrap_or(0), l.knobs.len())).unwrap_or_default();
+                let lost = if discarded > 0 { format!(", discarding {} key/knob mapping(s)", discarded) } else { String::new() };
+                format!("The layer has {} rows x {} cols and {} knob(s) but the layout has {} rows x {} cols and {} knob(s). Resize it to the layout{} and import it anyway?", rows, cols, knobs, device.rows, device.cols, device.knobs, lost)
             }
-            Selection::None => {}
+            PendingAction::ClearDevice => {
+                let pid = DATA.lock().unwrap().connected_pid.map(|p| format!("0x{:04x}", p)).unwrap_or("no device connected".to_string());
+                format!("Unbind every key and knob action on every layer of the device?\n\nDevice: {}\n\nThe layout is read from the device, the configuration in the editor is left as it is.", pid)
+            }
+            PendingAction::Program => {
+                let d = DATA.lock().unwrap();
+                let device = &d.macropad_data.device;
+                let pid = d.connected_pid.map(|p| format!("0x{:04x}", p)).unwrap_or("no device connected".to_string());
+                let mapped = Mapping::count_mappings(&d.macropad_data);
+                let empty = if mapped < consts::MIN_MAPPED_ACTIONS { format!("\n\n⚠ Only {} key/knob action(s) are mapped, programming will wipe the layout on the device!", mapped) } else { String::new() };
+                format!("Write this configuration to the device?\n\nDevice: {}\nLayers: {}\nGrid: {} rows x {} cols, {} knob(s){}", pid, d.macropad_data.layers.len(), device.rows, device.cols, device.knobs, empty)
+            }
+        };
+        let mut confirmed = false;
+        let mut cancelled = false;
+        egui::Modal::new(egui::Id::new("confirm_modal")).show(ctx, |ui| {
+            ui.heading("Please confirm");
+            ui.add_space(8.0);
+            ui.label(message);
+            ui.add_space(12.0);
+            ui.horizontal(|ui| {
+                if ui.button(egui::RichText::new("Confirm").color(egui::Color32::GOLD)).clicked() { confirmed = true; }
+                if ui.button("Cancel").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) { cancelled = true; }
+            });
+        });
+        if confirmed {
+            self.pending_action = None;
+            match action {
+                PendingAction::ClearLayer(idx) => self.clear_layer(idx),
+                PendingAction::Program => self.program_device(),
+                PendingAction::DeleteProfile => self.delete_profile(),
+                PendingAction::LoadTemplate(idx) => self.load_template(idx),
+                PendingAction::ApplyLayout(_) => self.apply_layout(),
+                PendingAction::Fill(row, col, whole_row) => { let mut d = DATA.lock().unwrap(); self.fill(&mut d, row, col, whole_row); }
+                PendingAction::ImportLayer(_) => self.place_imported_layer(),
+                PendingAction::ClearDevice => self.clear_device(),
+            }
+        } else if cancelled {
+            self.pending_action = None;
+            self.imported_layer = None;
         }
-        data.macropad_data.led_settings = Some(LedSettings { mode: self.led_mode, layer: self.led_layer, color: self.led_color });
     }
 
-    fn sync_data_to_temp(&mut self, data: &EditorData) {
+    /// Copies the mapping and delay of one button onto another. LED colors are only
+    /// copied between grid keys since knobs have no LEDs of their own
+    fn copy_button(&mut self, data: &mut MutexGuard<EditorData>, from: Selection, to: Selection) {
+        if from == to { return; }
+        self.sync_temp_to_data(data);
+        let Some(src) = data.button_at(from).cloned() else { return; };
+        if data.button_at(to).is_none() { return; }
+        data.snapshot();
+        let Some(dst) = data.button_at_mut(to) else { return; };
+        dst.mapping = src.mapping; dst.delay = src.delay;
+        if matches!((from, to), (Selection::Button(..), Selection::Button(..))) { dst.led_color = src.led_color; }
+        data.mark_dirty();
+        self.sync_data_to_temp(data);
+        data.log("📋 Copied button mapping".to_string(), egui::Color32::KHAKI);
+    }
+
+    /// Shows the right-click menu of a grid key and performs the picked entry
+    fn key_context_menu(&mut self, data: &mut MutexGuard<EditorData>, response: &egui::Response, row: usize, col: usize) {
+        let mut picked = None;
+        response.context_menu(|ui| {
+            for (action, label) in [(KeyMenuAction::Clear, "🗑 Clear"), (KeyMenuAction::Copy, "📋 Copy"), (KeyMenuAction::Paste, "📥 Paste"), (KeyMenuAction::Swap, "⇄ Swap with…"), (KeyMenuAction::FillRow, "➡ Fill row with this"), (KeyMenuAction::FillCol, "⬇ Fill column with this")] {
+                if ui.button(label).clicked() { picked = Some(action); ui.close(); }
+            }
+        });
+        let Some(action) = picked else { return; };
+        self.sync_temp_to_data(data);
+        let target = Selection::Button(row, col);
+        let Some(btn) = data.button_at(target).cloned() else { return; };
+        match action {
+            KeyMenuAction::Clear => {
+                data.snapshot();
+                if let Some(dst) = data.button_at_mut(target) { dst.mapping.clear(); dst.delay = 0; dst.led_color = None; }
+                data.mark_dirty();
+                data.log(format!("🗑 Cleared key {},{}", row + 1, col + 1), egui::Color32::KHAKI);
+            }
+            KeyMenuAction::Copy => {
+                response.ctx.copy_text(clipboard_text(&btn));
+                data.log(format!("📋 Copied '{}'", btn.mapping), egui::Color32::LIGHT_GRAY);
+            }
+            KeyMenuAction::Paste => {
+                // the pasted text arrives as an event, handled by the edit panel of the selection
+                data.selection = target;
+                self.paste_requested = Some((Instant::now(), PasteTarget::Button));
+                response.ctx.send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+            }
+            KeyMenuAction::Swap => self.start_swap(data, target),
+            KeyMenuAction::FillRow => self.request_fill(data, row, col, true),
+            KeyMenuAction::FillCol => self.request_fill(data, row, col, false),
+        }
+        self.sync_data_to_temp(data);
+    }
+
+    /// Copies a key across its row or column, asking first when that overwrites other mapped keys
+    fn request_fill(&mut self, data: &mut MutexGuard<EditorData>, row: usize, col: usize, whole_row: bool) {
+        let layer = &data.macropad_data.layers[data.current_layer_idx];
+        let Some(btn) = layer.buttons.get(row).and_then(|keys| keys.get(col)) else { return; };
+        let overwritten = layer.buttons.iter().enumerate().flat_map(|(r, keys)| keys.iter().enumerate().map(move |(c, key)| (r, c, key)))
+            .filter(|(r, c, key)| (if whole_row { *r == row } else { *c == col }) && (*r, *c) != (row, col) && !key.mapping.is_empty() && key.mapping != btn.mapping)
+            .count();
+        if overwritten > 0 { self.pending_action = Some(PendingAction::Fill(row, col, whole_row)); } else { self.fill(data, row, col, whole_row); }
+    }
+
+    /// Copies a key across its row or column
+    fn fill(&mut self, data: &mut MutexGuard<EditorData>, row: usize, col: usize, whole_row: bool) {
+        self.sync_temp_to_data(data);
         let layer_idx = data.current_layer_idx;
-        if layer_idx >= data.macropad_data.layers.len() { return; }
-        match data.selection {
-            Selection::Button(r, c) => {
-                let btn = &data.macropad_data.layers[layer_idx].buttons[r][c];
-                self.temp_editor_val = btn.mapping.clone();
-                self.temp_delay_val = btn.delay.to_string();
+        let Some(btn) = data.button_at(Selection::Button(row, col)).cloned() else { return; };
+        data.snapshot();
+        let layer = &mut data.macropad_data.layers[layer_idx];
+        if whole_row { fill_row(layer, row, &btn); } else { fill_col(layer, col, &btn); }
+        data.mark_dirty();
+        let line = if whole_row { format!("row {}", row + 1) } else { format!("column {}", col + 1) };
+        data.log(format!("📋 Filled {} with '{}'", line, btn.mapping), egui::Color32::KHAKI);
+        self.sync_data_to_temp(data);
+    }
+
+    /// Remembers the first key or knob action of a swap, the next one clicked is swapped with it
+    fn start_swap(&mut self, data: &mut MutexGuard<EditorData>, source: Selection) {
+        self.swap_source = Some(source);
+        data.log(format!("⇄ Click the key or knob action to swap {} with (Esc cancels)", source.describe()), egui::Color32::LIGHT_BLUE);
+    }
+
+    /// Selects a clicked key or knob action, or completes a pending swap with it
+    fn click_button(&mut self, data: &mut MutexGuard<EditorData>, target: Selection) {
+        self.sync_temp_to_data(data);
+        if let Some(source) = self.swap_source.take() {
+            if source != target {
+                let (Some(a), Some(b)) = (data.button_at(source).cloned(), data.button_at(target).cloned()) else { return; };
+                data.snapshot();
+                if let Some(dst) = data.button_at_mut(source) { *dst = b; }
+                if let Some(dst) = data.button_at_mut(target) { *dst = a; }
+                data.mark_dirty();
+                data.log(format!("⇄ Swapped {} and {}", source.describe(), target.describe()), egui::Color32::KHAKI);
             }
-            Selection::Knob(idx, part) => {
-                let btn = match part {
-                    KnobPart::CCW => &data.macropad_data.layers[layer_idx].knobs[idx].ccw,
-                    KnobPart::Press => &data.macropad_data.layers[layer_idx].knobs[idx].press,
-                    KnobPart::CW => &data.macropad_data.layers[layer_idx].knobs[idx].cw,
-                };
-                self.temp_editor_val = btn.mapping.clone();
-                self.temp_delay_val = btn.delay.to_string();
+        }
+        data.selection = target;
+        self.sync_data_to_temp(data);
+    }
+
+    /// Makes a grid or knob button draggable and accepts other buttons dropped onto it
+    fn handle_drag_drop(&mut self, ui: &egui::Ui, data: &mut MutexGuard<EditorData>, response: &egui::Response, target: Selection) {
+        response.dnd_set_drag_payload(target);
+        if response.dnd_hover_payload::<Selection>().is_some_and(|src| *src != target) {
+            ui.painter().rect_stroke(response.rect.expand(2.0), 4.0, egui::Stroke::new(2.0, egui::Color32::GOLD), egui::StrokeKind::Outside);
+        }
+        if let Some(src) = response.dnd_release_payload::<Selection>() { self.copy_button(data, *src, target); }
+    }
+
+    /// Draws a knob with a CCW zone on the left, a press zone in the center and a CW
+    /// zone on the right. Each zone selects (and accepts drops for) its `KnobPart`
+    fn knob_widget(&mut self, ui: &mut egui::Ui, data: &mut MutexGuard<EditorData>, knob_idx: usize, findings: &[Finding], finding_fill: egui::Color32) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(96.0, 96.0), egui::Sense::hover());
+        let center = rect.center();
+        let (radius, inner) = (44.0, 18.0);
+        let zones = [
+            (KnobPart::Ccw, egui::Rect::from_min_max(rect.min, egui::pos2(center.x, rect.max.y))),
+            (KnobPart::Cw, egui::Rect::from_min_max(egui::pos2(center.x, rect.min.y), rect.max)),
+            // registered last so it wins over the halves it overlaps
+            (KnobPart::Press, egui::Rect::from_center_size(center, egui::vec2(inner * 2.0, inner * 2.0))),
+        ];
+        let half = |from: f32| -> Vec<egui::Pos2> { (0..=32).map(|i| { let a = (from + 180.0 * i as f32 / 32.0).to_radians(); center + radius * egui::vec2(a.cos(), a.sin()) }).chain(std::iter::once(center)).collect() };
+        for (part, zone) in zones {
+            let target = Selection::Knob(knob_idx, part);
+            let response = ui.interact(zone, ui.id().with(("knob", knob_idx, part as u8)), egui::Sense::click_and_drag());
+            let Some(btn) = data.button_at(target) else { return; };
+            let tooltip = button_tooltip(btn, &data.macropad_data.aliases);
+            let notes = finding_notes(findings, Position::Knob(knob_idx as u8 + 1, part.action()));
+            let visuals = ui.visuals();
+            let fill = if data.selection == target { visuals.selection.bg_fill } else if response.hovered() { visuals.widgets.hovered.bg_fill } else if !notes.is_empty() { finding_fill } else { visuals.widgets.inactive.bg_fill };
+            let stroke = if self.swap_source == Some(target) { egui::Stroke::new(2.0, egui::Color32::GOLD) } else { visuals.widgets.noninteractive.bg_stroke };
+            let text_color = visuals.text_color();
+            let painter = ui.painter();
+            match part {
+                KnobPart::Ccw => { painter.add(egui::Shape::convex_polygon(half(90.0), fill, stroke)); painter.text(center - egui::vec2(radius * 0.6, 0.0), egui::Align2::CENTER_CENTER, "↺", egui::FontId::proportional(18.0), text_color); }
+                KnobPart::Cw => { painter.add(egui::Shape::convex_polygon(half(-90.0), fill, stroke)); painter.text(center + egui::vec2(radius * 0.6, 0.0), egui::Align2::CENTER_CENTER, "↻", egui::FontId::proportional(18.0), text_color); }
+                KnobPart::Press => { painter.circle(center, inner, fill, stroke); painter.text(center, egui::Align2::CENTER_CENTER, "●", egui::FontId::proportional(12.0), text_color); }
             }
-            Selection::None => { self.temp_editor_val = String::new(); self.temp_delay_val = String::new(); }
+            let name = match part { KnobPart::Ccw => "CCW", KnobPart::Press => "Press", KnobPart::Cw => "CW" };
+            let response = response.on_hover_text(format!("{}: {}", name, tooltip));
+            let response = if notes.is_empty() { response } else { response.on_hover_text(notes) };
+            if response.clicked() { self.click_button(data, target); }
+            self.handle_drag_drop(ui, data, &response, target);
         }
     }
 
+    fn time_ago(elapsed: Duration) -> String {
+        match elapsed.as_secs() { s if s < 60 => format!("{:>3}s ago", s), s if s < 3600 => format!("{:>3}m ago", s / 60), s => format!("{:>3}h ago", s / 3600) }
+    }
+
+    fn title(data: &EditorData) -> String {
+        let file_name = std::path::Path::new(&data.config_path).file_name().map(|f| f.to_string_lossy().to_string()).unwrap_or_default();
+        let profile = data.profile.as_ref().map(|p| format!("[{}] ", p)).unwrap_or_default();
+        format!("Macropad Editor Pro - {}{}{}", profile, file_name, if data.dirty { " *" } else { "" })
+    }
+
+    fn program_device(&self) {
+        let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d);
+        let config = match Mapping::resolve_aliases(&d.macropad_data) {
+            Ok(config) => config,
+            Err(e) => { d.log(format!("❌ Not programming: {:#}", e), egui::Color32::RED); return; }
+        };
+        let backup = match Mapping::backup(&config, &d.config_path) {
+            Ok(path) => path.display().to_string(),
+            Err(e) => { d.log(format!("❌ Backup failed, not programming: {:#}", e), egui::Color32::RED); return; }
+        };
+        d.log(format!("🚀 Programming... (backup: {})", backup), egui::Color32::GOLD);
+        d.program_progress = Some((0, program_steps(&config)));
+        let address = d.selected_device;
+        thread::spawn(move || {
+            let mut progress = |done, total| { if let Ok(mut d) = DATA.lock() { d.program_progress = Some((done, total)); } };
+            let result = open_keyboard_retry(&Self::usb_options(address)).map(|mut kb| kb.program(&config, Some(&mut progress)));
+            if let Ok(mut d) = DATA.lock() { d.program_progress = None; }
+            match result {
+                Ok(result) => { match result { Ok(_) => Self::set_status(&format!("✅ Programmed successfully! Backup saved to {}", backup), egui::Color32::GREEN), Err(e) => Self::set_status(&format!("❌ Error: {}", e), egui::Color32::RED) } }
+                Err(e) => Self::set_status(&format!("❌ USB error: {}", usb_error_message(&e)), egui::Color32::RED),
+            }
+        });
+    }
+
+    /// Programs an empty configuration of the layout the device reports, leaving the
+    /// edited configuration alone
+    fn clear_device(&self) {
+        let mut d = DATA.lock().unwrap();
+        d.log("🧹 Clearing the device...", egui::Color32::GOLD);
+        let address = d.selected_device;
+        thread::spawn(move || {
+            let mut progress = |done, total| { if let Ok(mut d) = DATA.lock() { d.program_progress = Some((done, total)); } };
+            let result = open_keyboard_retry(&Self::usb_options(address)).map(|mut kb| kb.query_layout().and_then(|(rows, cols, knobs, layers)| kb.program(&Macropad::empty(rows, cols, knobs, layers), Some(&mut progress)).map(|_| (rows, cols, knobs, layers))));
+            if let Ok(mut d) = DATA.lock() { d.program_progress = None; }
+            match result {
+                Ok(Ok((rows, cols, knobs, layers))) => Self::set_status(&format!("✅ Cleared {} layer(s) of {}x{} keys and {} knob(s)", layers, rows, cols, knobs), egui::Color32::GREEN),
+                Ok(Err(e)) => Self::set_status(&format!("❌ Clear failed: {:#}", e), egui::Color32::RED),
+                Err(e) => Self::set_status(&format!("❌ USB error: {}", usb_error_message(&e)), egui::Color32::RED),
+            }
+        });
+    }
+
+    /// Programs the device after confirmation, or shows the packets right away in dry run mode
+    fn request_program(&mut self) {
+        if self.dry_run { self.dry_run_program(); } else { self.pending_action = Some(PendingAction::Program); }
+    }
+
+    /// Builds the packets programming would send to the connected device (or the first
+    /// supported product id when none is connected) and shows them, nothing is written
+    fn dry_run_program(&mut self) {
+        let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d);
+        let pid = d.connected_pid.unwrap_or(consts::PRODUCT_IDS[0]);
+        let packets = Mapping::validate(&d.macropad_data, Some(pid)).and_then(|_| Mapping::resolve_aliases(&d.macropad_data)).and_then(|config| keyboard::build_packets(pid, &config));
+        match packets {
+            Ok(groups) => {
+                let count: usize = groups.iter().map(|g| g.packets.len()).sum();
+                d.log(format!("🔍 DRY RUN — nothing was written ({} packets for 0x{:04x})", count, pid), egui::Color32::LIGHT_BLUE);
+                self.packet_dump = Some((pid, groups));
+            }
+            Err(e) => d.log(format!("❌ DRY RUN failed for 0x{:04x}: {:#}", pid, e), egui::Color32::RED),
+        }
+    }
+
+    /// Shows the packets of the last dry run in a scrollable window
+    fn show_packet_dump(&mut self, ctx: &egui::Context) {
+        let Some((pid, groups)) = &self.packet_dump else { return; };
+        let mut open = true;
+        egui::Window::new("Dry Run Packets").open(&mut open).default_size([640.0, 420.0]).show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new(format!("DRY RUN — nothing was written. Packets programming would send to 0x{:04x}:", pid)).strong());
+                if ui.button("📋 Copy").clicked() { ui.ctx().copy_text(groups.iter().map(|g| g.to_string()).collect::<Vec<_>>().join("\n")); }
+            });
+            ui.separator();
+            egui::ScrollArea::both().auto_shrink([false, false]).show(ui, |ui| {
+                for group in groups {
+                    ui.label(egui::RichText::new(&group.label).strong());
+                    for packet in &group.packets { ui.label(egui::RichText::new(packet.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")).monospace().size(11.0)); }
+                }
+            });
+        });
+        if !open { self.packet_dump = None; }
+    }
+
+    /// Ctrl+S saves, Ctrl+Enter programs, Ctrl+Z undoes, Ctrl+Y or Ctrl+Shift+Z redoes
+    /// and the number keys switch layers. Shortcuts
+    /// are ignored while a text field has focus so typing a mapping can't trigger them
+    fn handle_shortcuts(&mut self, ctx: &egui::Context) {
+        if ctx.wants_keyboard_input() || self.capturing { return; }
+        let save = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::S);
+        let program = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Enter);
+        if ctx.input_mut(|i| i.consume_shortcut(&save)) { self.save_config(); }
+        if ctx.input_mut(|i| i.consume_shortcut(&program)) && self.selection_valid() { self.request_program(); }
+        let redo = [egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Y), egui::KeyboardShortcut::new(egui::Modifiers::COMMAND | egui::Modifiers::SHIFT, egui::Key::Z)];
+        let undo = egui::KeyboardShortcut::new(egui::Modifiers::COMMAND, egui::Key::Z);
+        if ctx.input_mut(|i| redo.iter().any(|s| i.consume_shortcut(s))) { self.undo(true); }
+        if ctx.input_mut(|i| i.consume_shortcut(&undo)) { self.undo(false); }
+
+        let number_keys = [egui::Key::Num1, egui::Key::Num2, egui::Key::Num3, egui::Key::Num4, egui::Key::Num5, egui::Key::Num6, egui::Key::Num7, egui::Key::Num8, egui::Key::Num9];
+        let pressed = ctx.input(|i| if i.modifiers.any() { None } else { number_keys.iter().position(|k| i.key_pressed(*k)) });
+        if let Some(layer_idx) = pressed {
+            let mut d = DATA.lock().unwrap();
+            if layer_idx < d.macropad_data.layers.len() { self.switch_layer(&mut d, layer_idx); }
+        }
+    }
+
+    fn led_color32(color: LedColor) -> egui::Color32 {
+        let (r, g, b) = color.rgb();
+        egui::Color32::from_rgb(r, g, b)
+    }
+
+    /// Draws a small filled rectangle previewing an LED color; `None` draws an unlit LED
+    fn color_swatch(ui: &mut egui::Ui, color: Option<egui::Color32>) {
+        let (rect, _) = ui.allocate_exact_size(egui::vec2(28.0, 16.0), egui::Sense::hover());
+        let fill = color.unwrap_or(egui::Color32::from_gray(20));
+        ui.painter().rect_filled(rect, 3.0, fill);
+        ui.painter().rect_stroke(rect, 3.0, egui::Stroke::new(1.0, egui::Color32::GRAY), egui::StrokeKind::Inside);
+    }
+
     fn get_led_modes(pid: u16) -> Vec<(u8, &'static str)> {
-        if pid == 0x8890 { vec![ (0, "Off"), (1, "Last Pushed"), (2, "Cycle Colors") ] }
-        else { vec![ (0, "Off"), (1, "Always On (Color)"), (2, "Shock (Color)"), (3, "Shock2 (Color)"), (4, "Light Key (Color)"), (5, "White Always On") ] }
+        match pid {
+            0x8890 => vec![ (0, "Off"), (1, "Last Pushed"), (2, "Cycle Colors") ],
+            // 0x8840, 0x8842 and 0x8850
+            _ => vec![ (0, "Off"), (1, "Always On (Color)"), (2, "Shock (Color)"), (3, "Shock2 (Color)"), (4, "Light Key (Color)"), (5, "White Always On") ],
+        }
     }
 }
 
 impl eframe::App for MacropadApp {
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        self.settings.last_config = Some(DATA.lock().unwrap().config_path.clone());
+        eframe::set_value(storage, eframe::APP_KEY, &self.settings);
+    }
+
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        if self.last_conn_check.elapsed() > Duration::from_secs(2) { Self::check_connection(); self.last_conn_check = Instant::now(); }
+        self.drain_connection_updates();
+        if self.swap_source.is_some() && ctx.input(|i| i.key_pressed(egui::Key::Escape)) { self.swap_source = None; DATA.lock().unwrap().log("⇄ Swap cancelled", egui::Color32::LIGHT_GRAY); }
+        self.handle_shortcuts(ctx);
+        if self.settings.autosave { self.autosave(); }
+        let title = Self::title(&DATA.lock().unwrap());
+        if title != self.window_title { ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone())); self.window_title = title; }
 
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
-            let data = DATA.lock().unwrap();
+            let mut data = DATA.lock().unwrap();
             ui.horizontal(|ui| {
                 ui.heading("⌨ Macropad Editor Pro");
                 ui.separator();
                 if let Some(pid) = data.connected_pid {
                     ui.label(egui::RichText::new(format!("CONNECTED (0x{:04x}) ✅", pid)).color(egui::Color32::GREEN));
+                    if data.devices.len() > 1 { Self::device_combo(ui, &mut data); }
+                    match &data.device_info {
+                        Some(Ok(info)) => {
+                            let name = info.product.clone().unwrap_or("Unknown product".to_string());
+                            let keys = match (info.num_keys, info.num_encoders) { (Some(k), Some(e)) => format!("{} keys, {} knobs", k, e), _ => "not reported".to_string() };
+                            let details = format!("Manufacturer: {}\nProduct: {}\nSerial: {}\nFirmware: {}\nLayout: {}", info.manufacturer.as_deref().unwrap_or("-"), name, info.serial.as_deref().unwrap_or("-"), info.version, keys);
+                            ui.label(egui::RichText::new(format!("ℹ {} v{}", name, info.version)).size(12.0)).on_hover_text(details);
+                        }
+                        Some(Err(e)) => { ui.label(egui::RichText::new("ℹ device info unavailable").weak().size(12.0)).on_hover_text(e); }
+                        None => { ui.spinner(); }
+                    }
                     ui.separator();
                     let hint = if pid == 0x8890 { "ℹ Single-layer device detected." } else { "ℹ Multi-layer device detected." };
                     ui.label(egui::RichText::new(hint).italics().size(12.0).color(egui::Color32::LIGHT_BLUE));
+                } else if !data.devices.is_empty() {
+                    ui.label(egui::RichText::new("NO MACROPAD SELECTED").color(egui::Color32::GOLD));
+                    Self::device_combo(ui, &mut data);
                 } else { ui.label(egui::RichText::new("DISCONNECTED ❌").color(egui::Color32::RED)); }
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    let theme_icon = if self.settings.dark_mode { "☀" } else { "🌙" };
+                    if ui.button(theme_icon).on_hover_text("Toggle light/dark theme").clicked() {
+                        self.settings.dark_mode = !self.settings.dark_mode;
+                        ctx.set_visuals(self.settings.visuals());
+                    }
+                    let host_layout = self.settings.host_layout;
+                    egui::ComboBox::from_id_salt("host_layout_cb").selected_text(host_layout.to_string()).show_ui(ui, |ui| {
+                        for l in HostLayout::iter() { ui.selectable_value(&mut self.settings.host_layout, l, l.to_string()); }
+                    }).response.on_hover_text("Keyboard layout of this computer, for key labels and text: mappings");
+                    if self.settings.host_layout != host_layout { layout::set_host_layout(self.settings.host_layout); self.temp_validation = None; }
+                    let search = ui.add(egui::TextEdit::singleline(&mut self.search_query).hint_text("🔍 Search mappings").desired_width(180.0));
+                    if self.search_query.trim().is_empty() { return; }
+                    let results = search_mappings(&data.macropad_data, self.search_query.trim());
+                    let mut picked = None;
+                    egui::Area::new(egui::Id::new("search_results")).order(egui::Order::Foreground).fixed_pos(search.rect.left_bottom()).show(ctx, |ui| {
+                        egui::Frame::popup(ui.style()).show(ui, |ui| {
+                            ui.set_min_width(search.rect.width());
+                            if results.is_empty() { ui.label(egui::RichText::new("No matches").italics()); }
+                            egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                                for (layer_idx, sel, mapping) in &results {
+                                    let title = data.macropad_data.layers.get(*layer_idx).map(|l| l.title(*layer_idx)).unwrap_or_default();
+                                    if ui.selectable_label(false, format!("{} · {} · {}", title, sel.describe(), mapping)).clicked() { picked = Some((*layer_idx, *sel)); }
+                                }
+                            });
+                        });
+                    });
+                    if let Some((layer_idx, sel)) = picked {
+                        self.sync_temp_to_data(&mut data);
+                        data.current_layer_idx = layer_idx; data.selection = sel;
+                        self.sync_data_to_temp(&data);
+                        self.search_query.clear();
+                    }
+                });
             });
         });
 
         egui::TopBottomPanel::bottom("status_bar").show(ctx, |ui| {
             let data = DATA.lock().unwrap();
-            ui.horizontal(|ui| { ui.label(egui::RichText::new(&data.status_msg).color(data.status_color)); });
+            ui.horizontal(|ui| {
+                ui.label(egui::RichText::new("Status log").strong().size(11.0));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| { ui.label(egui::RichText::new(format!("📄 {}", data.config_path)).weak()); });
+            });
+            egui::ScrollArea::vertical().max_height(80.0).auto_shrink([false, true]).stick_to_bottom(true).show(ui, |ui| {
+                for (time, msg, color) in &data.status_log {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(Self::time_ago(time.elapsed())).weak().monospace().size(11.0));
+                        ui.label(egui::RichText::new(msg).color(*color));
+                    });
+                }
+            });
         });
 
         egui::SidePanel::left("side_panel").width_range(200.0..=250.0).show(ctx, |ui| {
-            let (rows, cols, knobs, layers, orientation, pid) = {
+            let (rows, cols, knobs, layers, orientation, pid, max_layers) = {
                 let d = DATA.lock().unwrap();
-                (d.macropad_data.device.rows, d.macropad_data.device.cols, d.macropad_data.device.knobs, d.macropad_data.device.layers, d.macropad_data.device.orientation, d.connected_pid.unwrap_or(0x8840))
+                (d.macropad_data.device.rows, d.macropad_data.device.cols, d.macropad_data.device.knobs, d.macropad_data.device.layers, d.macropad_data.device.orientation, d.connected_pid.unwrap_or(0x8840), consts::max_layers(d.connected_pid))
             };
 
             ui.heading("Device Config");
@@ -253,7 +1547,7 @@ impl eframe::App for MacropadApp {
             ui.horizontal(|ui| {
                 ui.label(egui::RichText::new("Layers:").strong());
                 egui::ComboBox::from_id_salt("layers_cb").selected_text(self.ui_layers.to_string()).show_ui(ui, |ui| {
-                    for i in 1..=3 { ui.selectable_value(&mut self.ui_layers, i, i.to_string()); }
+                    for i in 1..=max_layers { ui.selectable_value(&mut self.ui_layers, i, i.to_string()); }
                 });
             });
             ui.add_space(4.0);
@@ -288,10 +1582,13 @@ impl eframe::App for MacropadApp {
                 });
             });
             
+            let address = DATA.lock().unwrap().selected_device;
+            let detect = ui.add_enabled(address.is_some(), egui::Button::new("🔎 Detect from device")).on_hover_text("Ask the macropad for its rows, cols and knobs and apply them").on_disabled_hover_text("Connect a macropad first");
+            if let (true, Some(address)) = (detect.clicked(), address) { let _ = self.worker_requests.send(WorkerRequest::QueryLayout(address)); }
             let changed = self.ui_rows != rows || self.ui_cols != cols || self.ui_knobs != knobs || self.ui_layers != layers || self.ui_orientation != orientation;
             if changed {
                 ui.add_space(10.0);
-                if ui.button(egui::RichText::new("Apply Layout").color(egui::Color32::GOLD)).clicked() { self.apply_layout(); }
+                if ui.button(egui::RichText::new("Apply Layout").color(egui::Color32::GOLD)).clicked() { self.request_apply_layout(); }
             }
             
             ui.add_space(20.0); ui.separator(); ui.add_space(10.0);
@@ -307,92 +1604,182 @@ impl eframe::App for MacropadApp {
             ui.horizontal(|ui| {
                 ui.label("Layer:");
                 egui::ComboBox::from_id_salt("led_layer_cb").selected_text(format!("Layer {}", self.led_layer)).show_ui(ui, |ui| {
-                    for i in 1..=3 { ui.selectable_value(&mut self.led_layer, i, format!("Layer {}", i)); }
+                    for i in 1..=layers.min(max_layers) { ui.selectable_value(&mut self.led_layer, i, format!("Layer {}", i)); }
                 });
             });
             ui.horizontal(|ui| {
                 ui.label("Color:");
                 egui::ComboBox::from_id_salt("led_color_cb").selected_text(format!("{:?}", self.led_color)).show_ui(ui, |ui| {
-                    for color in [LedColor::Red, LedColor::Orange, LedColor::Yellow, LedColor::Green, LedColor::Cyan, LedColor::Blue, LedColor::Purple] {
+                    for color in LedColor::iter() {
                         ui.selectable_value(&mut self.led_color, color, format!("{:?}", color));
                     }
                 });
+                let preview = match self.led_mode { 0 => None, 5 if pid != 0x8890 => Some(egui::Color32::WHITE), _ => Some(Self::led_color32(self.led_color)) };
+                Self::color_swatch(ui, preview);
             });
             if pid == 0x8890 { ui.label(egui::RichText::new("Note: Color might not work on 8890").italics().size(10.0).color(egui::Color32::KHAKI)); }
+            let dims = consts::LED_BRIGHTNESS_PIDS.contains(&pid);
+            ui.horizontal(|ui| {
+                ui.label("Brightness:");
+                ui.add_enabled(dims, egui::Slider::new(&mut self.led_brightness, 0..=consts::MAX_LED_BRIGHTNESS).suffix("%")).on_disabled_hover_text(format!("0x{:04x} can't dim its LEDs yet", pid));
+            });
+            if !dims { ui.label(egui::RichText::new(format!("Note: Brightness isn't supported on 0x{:04x} yet", pid)).italics().size(10.0).color(egui::Color32::KHAKI)); }
+            ui.horizontal(|ui| {
+                ui.label("Preview:");
+                for color in led_preview_colors(self.led_mode, pid, self.led_color, ui.input(|i| i.time), 6) { Self::color_swatch(ui, color); }
+            }).response.on_hover_text("Rough simulation of the mode, a key press is simulated every second or so");
+            // static modes don't need the faster repaint
+            let animated = self.led_mode != 0 && (pid == 0x8890 || !matches!(self.led_mode, 1 | 5));
+            if animated { ctx.request_repaint_after(consts::LED_PREVIEW_FRAME); }
 
             if ui.button("Apply LED").clicked() {
-                let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d); let _ = Mapping::save(&d.macropad_data, "mapping.ron");
-                let mode = self.led_mode; let color = self.led_color; let layer = self.led_layer;
+                let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d); if Mapping::save(&d.macropad_data, &d.config_path).is_ok() { d.dirty = false; }
+                let mode = self.led_mode; let color = self.led_color; let layer = self.led_layer; let brightness = self.led_brightness; let address = d.selected_device;
                 thread::spawn(move || {
-                    let options = Options { command: Command::ShowGui, devel_options: DevelOptions { vendor_id: VENDOR_ID, product_id: None, address: None, out_endpoint_address: None, in_endpoint_address: None, interface_number: None } };
-                    match open_keyboard(&options) {
-                        Ok(mut kb) => { if let Err(e) = kb.set_led(mode, layer, color) { Self::set_status(&format!("❌ LED Error: {}", e), egui::Color32::RED); } else { Self::set_status("✅ LED updated!", egui::Color32::GREEN); } }
-                        Err(e) => Self::set_status(&format!("❌ USB error: {}", e), egui::Color32::RED),
+                    match open_keyboard_retry(&Self::usb_options(address)) {
+                        Ok(mut kb) => { if let Err(e) = kb.set_led(mode, layer, color, brightness) { Self::set_status(&format!("❌ LED Error: {}", e), egui::Color32::RED); } else { Self::set_status("✅ LED updated!", egui::Color32::GREEN); } }
+                        Err(e) => Self::set_status(&format!("❌ USB error: {}", usb_error_message(&e)), egui::Color32::RED),
                     }
                 });
             }
 
+            ui.add_space(20.0); ui.separator(); ui.add_space(10.0);
+            ui.heading("Profiles");
+            let active = DATA.lock().unwrap().profile.clone();
+            let mut picked = None;
+            ui.horizontal(|ui| {
+                ui.label("Load:");
+                egui::ComboBox::from_id_salt("profile_cb").selected_text(active.clone().unwrap_or("None".to_string())).show_ui(ui, |ui| {
+                    if self.profiles.is_empty() { ui.label(egui::RichText::new("No saved profiles").italics()); }
+                    for name in &self.profiles { if ui.selectable_label(active.as_ref() == Some(name), name).clicked() { picked = Some(name.clone()); } }
+                });
+                if ui.button("🔄").on_hover_text(format!("Reload the list from {}", profiles::profiles_dir().display())).clicked() { self.refresh_profiles(); }
+            });
+            if let Some(name) = picked { self.load_profile(&name); }
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.profile_name).hint_text("profile name").desired_width(110.0));
+                let named = !self.profile_name.trim().is_empty();
+                if ui.add_enabled(named, egui::Button::new("💾 Save")).on_hover_text("Save the configuration as a profile").clicked() { self.save_profile(); }
+                let exists = self.profiles.iter().any(|p| p == self.profile_name.trim());
+                if ui.add_enabled(exists, egui::Button::new("🗑")).on_hover_text("Delete the profile").clicked() { self.pending_action = Some(PendingAction::DeleteProfile); }
+            });
+
             ui.add_space(20.0); ui.separator(); ui.add_space(20.0);
-            if ui.add_sized([ui.available_width(), 40.0], egui::Button::new("💾 Save Config")).clicked() {
-                let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d); let _ = Mapping::save(&d.macropad_data, "mapping.ron");
-                d.status_msg = format!("✅ Config saved to mapping.ron"); d.status_color = egui::Color32::GREEN;
+            ui.horizontal(|ui| {
+                let width = (ui.available_width() - ui.spacing().item_spacing.x) / 2.0;
+                if ui.add_sized([width, 28.0], egui::Button::new("📂 Open…")).clicked() { self.open_config(); }
+                if ui.add_sized([width, 28.0], egui::Button::new("💾 Save As…")).clicked() { self.save_config_as(); }
+            });
+            ui.menu_button("✨ New from Template", |ui| {
+                for (idx, template) in templates::templates().iter().enumerate() {
+                    if ui.button(template.name).on_hover_text(template.description).clicked() { self.pending_action = Some(PendingAction::LoadTemplate(idx)); ui.close(); }
+                }
+            });
+            if ui.button("🔤 Key Aliases…").on_hover_text("Name keys or sequences your own way, eg. ctl for ctrl").clicked() {
+                self.alias_rows = Some(DATA.lock().unwrap().macropad_data.aliases.iter().map(|(n, m)| (n.clone(), m.clone())).collect());
             }
             ui.add_space(10.0);
-            if ui.add_sized([ui.available_width(), 40.0], egui::Button::new("🚀 Program Device").fill(egui::Color32::from_rgb(0, 80, 0))).clicked() {
-                let mut d = DATA.lock().unwrap(); self.sync_temp_to_data(&mut d); let config = d.macropad_data.clone();
-                d.status_msg = "🚀 Programming...".to_string(); d.status_color = egui::Color32::GOLD;
-                thread::spawn(move || {
-                    let options = Options { command: Command::ShowGui, devel_options: DevelOptions { vendor_id: VENDOR_ID, product_id: None, address: None, out_endpoint_address: None, in_endpoint_address: None, interface_number: None } };
-                    match open_keyboard(&options) {
-                        Ok(mut kb) => { match kb.program(&config) { Ok(_) => Self::set_status("✅ Programmed successfully!", egui::Color32::GREEN), Err(e) => Self::set_status(&format!("❌ Error: {}", e), egui::Color32::RED) } }
-                        Err(e) => Self::set_status(&format!("❌ USB error: {}", e), egui::Color32::RED),
-                    }
-                });
+            ui.horizontal(|ui| {
+                let width = (ui.available_width() - ui.spacing().item_spacing.x) / 2.0;
+                let (can_undo, can_redo) = { let d = DATA.lock().unwrap(); (!d.history.undo.is_empty(), !d.history.redo.is_empty()) };
+                if ui.add_enabled(can_undo, egui::Button::new("↶ Undo").shortcut_text("Ctrl+Z").min_size(egui::vec2(width, 24.0))).on_hover_text("Revert the last change, including layout and LED changes").clicked() { self.undo(false); }
+                if ui.add_enabled(can_redo, egui::Button::new("↷ Redo").shortcut_text("Ctrl+Y").min_size(egui::vec2(width, 24.0))).clicked() { self.undo(true); }
+            });
+            ui.add_space(10.0);
+            if ui.add_sized([ui.available_width(), 40.0], egui::Button::new("💾 Save Config").shortcut_text("Ctrl+S")).clicked() { self.save_config(); }
+            ui.checkbox(&mut self.settings.autosave, "Autosave").on_hover_text(format!("Save automatically {}s after the last edit", consts::AUTOSAVE_DELAY.as_secs()));
+            ui.add_space(10.0);
+            ui.checkbox(&mut self.dry_run, "Dry run").on_hover_text("Show the packets programming would send instead of writing them to the device");
+            let can_program = self.selection_valid();
+            let (label, fill) = if self.dry_run { ("🔍 Program Device (dry run)", egui::Color32::from_rgb(0, 50, 90)) } else { ("🚀 Program Device", egui::Color32::from_rgb(0, 80, 0)) };
+            let program = ui.add_enabled_ui(can_program, |ui| ui.add_sized([ui.available_width(), 40.0], egui::Button::new(label).shortcut_text("Ctrl+Enter").fill(fill))).inner;
+            if program.on_disabled_hover_text("Fix the mapping of the selected key first").clicked() { self.request_program(); }
+            let connected = DATA.lock().unwrap().selected_device.is_some();
+            if ui.add_enabled(connected, egui::Button::new("🧹 Clear Device")).on_hover_text("Unbind every key and knob on the device, eg. before selling it").on_disabled_hover_text("Connect a macropad first").clicked() { self.pending_action = Some(PendingAction::ClearDevice); }
+            if let Some((done, total)) = DATA.lock().unwrap().program_progress {
+                ui.add(egui::ProgressBar::new(done as f32 / total.max(1) as f32).text(format!("Programming {}/{}", done, total)).animate(true));
+                ctx.request_repaint_after(Duration::from_millis(50));
             }
         });
 
         egui::CentralPanel::default().show(ctx, |ui| {
+            let (mut export, mut import) = (false, false);
             ui.horizontal(|ui| {
                 let mut d = DATA.lock().unwrap();
                 let num_layers = d.macropad_data.device.layers as usize;
-                for i in 0..num_layers { if ui.selectable_label(d.current_layer_idx == i, format!("Layer {}", i + 1)).clicked() { self.sync_temp_to_data(&mut d); d.current_layer_idx = i; self.sync_data_to_temp(&d); } }
+                for i in 0..num_layers {
+                    let title = d.macropad_data.layers.get(i).map(|l| l.title(i)).unwrap_or(format!("Layer {}", i + 1));
+                    if ui.selectable_label(d.current_layer_idx == i, title).on_hover_text(format!("Layer {}", i + 1)).clicked() { self.switch_layer(&mut d, i); }
+                }
+                ui.label(egui::RichText::new(format!("(keys 1-{} switch layers)", num_layers.min(9))).weak().size(11.0));
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    if ui.button("🗑 Clear Layer").clicked() { self.pending_action = Some(PendingAction::ClearLayer(d.current_layer_idx)); }
+                    if ui.button("⇕ Mirror").on_hover_text("Reverse the order of the rows").clicked() { self.mirror_layer(&mut d, false); }
+                    if ui.button("⇔ Mirror").on_hover_text("Reverse the order of the columns and knobs").clicked() { self.mirror_layer(&mut d, true); }
+                    import = ui.button("📂 Import Layer").on_hover_text("Load a layer file into this layer").clicked();
+                    export = ui.button("💾 Export Layer").on_hover_text("Save this layer to its own file").clicked();
+                });
             });
+            if export { self.export_layer(); }
+            if import { self.import_layer(); }
             ui.separator();
 
             egui::ScrollArea::vertical().show(ui, |ui| {
                 let mut d = DATA.lock().unwrap();
                 let layer_idx = d.current_layer_idx;
                 if layer_idx >= d.macropad_data.layers.len() { return; }
-                ui.heading(format!("Layer {} Matrix", layer_idx + 1));
+                let orientation = d.macropad_data.device.orientation;
+                ui.horizontal(|ui| {
+                    let title = d.macropad_data.layers[layer_idx].title(layer_idx);
+                    if orientation == Orientation::Normal { ui.heading(format!("{} Matrix", title)); } else { ui.heading(format!("{} Matrix ({:?})", title, orientation)); }
+                    ui.add_space(10.0);
+                    ui.label("Name:");
+                    let mut name = d.macropad_data.layers[layer_idx].name.clone().unwrap_or_default();
+                    if ui.add(egui::TextEdit::singleline(&mut name).hint_text(format!("Layer {}", layer_idx + 1)).desired_width(120.0)).on_hover_text("What the layer is for, eg. Media. Only shown here, the macropad doesn't store it").changed() {
+                        let EditorData { history, macropad_data, .. } = &mut *d; history.push_edit(macropad_data, EditTarget::LayerName(layer_idx));
+                        d.macropad_data.layers[layer_idx].name = (!name.trim().is_empty()).then_some(name);
+                        d.mark_dirty();
+                    }
+                });
                 let r = d.macropad_data.device.rows as usize;
                 let c = d.macropad_data.device.cols as usize;
                 let k = d.macropad_data.device.knobs as usize;
+                let findings: Vec<Finding> = d.macropad_data.analyze().into_iter().filter(|f| f.layer as usize == layer_idx + 1).collect();
+                let finding_fill = egui::Color32::from_rgb(110, 70, 0);
 
                 egui::Grid::new("grid").spacing([10.0, 10.0]).show(ui, |ui| {
-                    for row in 0..r {
-                        for col in 0..c {
-                            let val = &d.macropad_data.layers[layer_idx].buttons[row][col].mapping;
+                    for display_row in display_order(r, c, orientation) {
+                        for (row, col) in display_row {
+                            let Some(btn) = d.button_at(Selection::Button(row, col)) else { continue; };
+                            let (val, tooltip) = (&btn.mapping, button_tooltip(btn, &d.macropad_data.aliases));
                             let is_selected = d.selection == Selection::Button(row, col);
-                            let btn_text = if val.is_empty() { format!("[{},{}]", row+1, col+1) } else { val.clone() };
-                            if ui.add_sized([100.0, 40.0], egui::Button::new(btn_text).selected(is_selected)).clicked() { self.sync_temp_to_data(&mut d); d.selection = Selection::Button(row, col); self.sync_data_to_temp(&d); }
+                            let btn_text = if val.is_empty() { format!("[{},{}]", row+1, col+1) } else { self.settings.host_layout.display_mapping(val) };
+                            let mut button = egui::Button::new(btn_text).selected(is_selected).sense(egui::Sense::click_and_drag());
+                            let notes = finding_notes(&findings, Position::Key(row as u8 + 1, col as u8 + 1));
+                            if !notes.is_empty() { button = button.fill(finding_fill); }
+                            let response = ui.add_sized([100.0, 40.0], button).on_hover_text(tooltip);
+                            let response = if notes.is_empty() { response } else { response.on_hover_text(notes) };
+                            if response.clicked() { self.click_button(&mut d, Selection::Button(row, col)); }
+                            if self.swap_source == Some(Selection::Button(row, col)) { ui.painter().rect_stroke(response.rect.expand(2.0), 4.0, egui::Stroke::new(2.0, egui::Color32::GOLD), egui::StrokeKind::Outside); }
+                            self.handle_drag_drop(ui, &mut d, &response, Selection::Button(row, col));
+                            self.key_context_menu(&mut d, &response, row, col);
                         }
                         ui.end_row();
                     }
                 });
+                if !findings.is_empty() { ui.label(egui::RichText::new("⚠ Highlighted keys are mapped twice on this layer or moved from layer 1, hover them for details").size(11.0).color(egui::Color32::KHAKI)); }
 
                 if k > 0 {
                     ui.add_space(20.0); ui.heading("Rotary Encoders");
-                    for i in 0..k {
-                        ui.horizontal(|ui| {
-                            ui.label(format!("Knob {}:", i+1));
-                            for (part, label) in [(KnobPart::CCW, "CCW"), (KnobPart::Press, "Press"), (KnobPart::CW, "CW")] {
-                                let val = match part { KnobPart::CCW => &d.macropad_data.layers[layer_idx].knobs[i].ccw.mapping, KnobPart::Press => &d.macropad_data.layers[layer_idx].knobs[i].press.mapping, KnobPart::CW => &d.macropad_data.layers[layer_idx].knobs[i].cw.mapping };
-                                let is_selected = d.selection == Selection::Knob(i, part);
-                                let btn_text = if val.is_empty() { label } else { val };
-                                if ui.add(egui::Button::new(btn_text).selected(is_selected)).clicked() { self.sync_temp_to_data(&mut d); d.selection = Selection::Knob(i, part); self.sync_data_to_temp(&d); }
-                            }
-                        });
-                    }
+                    ui.horizontal_wrapped(|ui| {
+                        for i in 0..k {
+                            ui.vertical(|ui| {
+                                self.knob_widget(ui, &mut d, i, &findings, finding_fill);
+                                ui.label(egui::RichText::new(format!("Knob {}", i + 1)).size(11.0));
+                            });
+                            ui.add_space(10.0);
+                        }
+                    });
                 }
 
                 ui.add_space(20.0); ui.separator();
@@ -400,20 +1787,255 @@ impl eframe::App for MacropadApp {
                     ui.heading("Edit Selection");
                     ui.horizontal(|ui| {
                         ui.label("Delay (ms):"); if ui.text_edit_singleline(&mut self.temp_delay_val).changed() { self.sync_temp_to_data(&mut d); }
-                        ui.add_space(20.0); ui.label("Mapping:"); if ui.text_edit_singleline(&mut self.temp_editor_val).changed() { self.sync_temp_to_data(&mut d); }
+                        ui.add_space(20.0); ui.label("Mapping:"); if ui.text_edit_singleline(&mut self.temp_editor_val).changed() { self.paste_error = None; self.sync_temp_to_data(&mut d); }
+                        if ui.button("📥").on_hover_text("Paste a mapping such as ctrl-c,ctrl-v,enter from the clipboard, it is only used when valid").clicked() {
+                            self.paste_requested = Some((Instant::now(), PasteTarget::Mapping));
+                            ui.ctx().send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+                        }
+                    });
+                    ui.horizontal(|ui| {
+                        let label = if self.capturing { "⏺ Press a key… (Esc cancels)" } else { "⌨ Capture" };
+                        if ui.selectable_label(self.capturing, label).on_hover_text("Bind the next key pressed").clicked() { self.capturing = !self.capturing; }
+                        // a second click cancels the pending swap
+                        if ui.selectable_label(self.swap_source.is_some(), "⇄ Swap").on_hover_text("Swap with the next key or knob action clicked").clicked() && self.swap_source.take().is_none() { let selection = d.selection; self.start_swap(&mut d, selection); }
+                        if let Selection::Button(row, col) = d.selection {
+                            if ui.button("➡ Fill row").on_hover_text("Copy this key across its row").clicked() { self.request_fill(&mut d, row, col, true); }
+                            if ui.button("⬇ Fill column").on_hover_text("Copy this key across its column").clicked() { self.request_fill(&mut d, row, col, false); }
+                        }
+                        if ui.button("📋 Copy").on_hover_text("Copy the mapping and delay to the clipboard").clicked() {
+                            let btn = Button { delay: self.temp_delay_val.parse().unwrap_or(0), mapping: self.temp_editor_val.clone(), led_color: None };
+                            ui.ctx().copy_text(clipboard_text(&btn));
+                            d.log(format!("📋 Copied '{}'", btn.mapping), egui::Color32::LIGHT_GRAY);
+                        }
+                        let test_hint = format!("Perform the mapping on this computer in {}s, focus the target window meanwhile. Nothing is sent to the macropad", consts::SIMULATE_COUNTDOWN);
+                        if ui.button("🧪 Test here").on_hover_text(test_hint).clicked() {
+                            let btn = Button { delay: self.temp_delay_val.parse().unwrap_or(0), mapping: self.temp_editor_val.clone(), led_color: None };
+                            let expanded = Mapping::expand_aliases(&btn.mapping, &d.macropad_data.aliases).map(|mapping| Button { mapping, ..btn.clone() });
+                            match expanded.and_then(|expanded| simulate::host_actions(&expanded)) {
+                                Ok(actions) => {
+                                    d.log(format!("🧪 Simulating '{}' on this computer in {}s…", btn.mapping, consts::SIMULATE_COUNTDOWN), egui::Color32::LIGHT_BLUE);
+                                    thread::spawn(move || {
+                                        thread::sleep(Duration::from_secs(consts::SIMULATE_COUNTDOWN));
+                                        match simulate::perform(&actions) { Ok(_) => Self::set_status(&format!("🧪 Simulated '{}'", btn.mapping), egui::Color32::LIGHT_BLUE), Err(e) => Self::set_status(&format!("❌ Simulation failed: {:#}", e), egui::Color32::RED) }
+                                    });
+                                }
+                                Err(e) => d.log(format!("❌ Can't simulate: {:#}", e), egui::Color32::RED),
+                            }
+                        }
+                        if ui.button("📥 Paste").on_hover_text("Paste a mapping copied with the Copy button").clicked() {
+                            self.paste_requested = Some((Instant::now(), PasteTarget::Button));
+                            ui.ctx().send_viewport_cmd(egui::ViewportCommand::RequestPaste);
+                        }
                     });
+                    if self.paste_requested.is_some_and(|(t, _)| t.elapsed() > Duration::from_secs(1)) { self.paste_requested = None; }
+                    if let Some((_, target)) = self.paste_requested {
+                        if let Some(text) = ui.input(|i| i.events.iter().find_map(|e| match e { egui::Event::Paste(text) => Some(text.clone()), _ => None })) {
+                            self.paste_requested = None;
+                            match target {
+                                PasteTarget::Button => match parse_clipboard(&text, d.connected_pid, &d.macropad_data.aliases) {
+                                    Ok(btn) => { self.temp_editor_val = btn.mapping; self.temp_delay_val = btn.delay.to_string(); self.sync_temp_to_data(&mut d); d.log("📥 Pasted mapping", egui::Color32::GREEN); }
+                                    Err(e) => d.log(format!("❌ Paste rejected: {:#}", e), egui::Color32::RED),
+                                },
+                                PasteTarget::Mapping => match parse_pasted_mapping(&text, self.temp_delay_val.parse().unwrap_or(0), d.connected_pid, &d.macropad_data.aliases) {
+                                    Ok(mapping) => { self.temp_editor_val = mapping; self.paste_error = None; self.sync_temp_to_data(&mut d); d.log(format!("📥 Pasted '{}'", self.temp_editor_val), egui::Color32::GREEN); }
+                                    Err(e) => self.paste_error = Some(format!("{:#}", e)),
+                                },
+                            }
+                        }
+                    }
+                    if let Some(e) = &self.paste_error { ui.label(egui::RichText::new(format!("❌ Not pasted: {}", e)).size(11.0).color(egui::Color32::RED)); }
+                    if self.capturing {
+                        let pressed = ui.input(|i| i.events.iter().find_map(|e| match e { egui::Event::Key { key, physical_key, pressed: true, repeat: false, modifiers } => Some((physical_key.unwrap_or(*key), *modifiers)), _ => None }));
+                        if let Some((key, modifiers)) = pressed {
+                            self.capturing = false;
+                            if key != egui::Key::Escape {
+                                match key_press_mapping(key, modifiers) {
+                                    Some(mapping) => { self.temp_editor_val = mapping; self.sync_temp_to_data(&mut d); }
+                                    None => d.log(format!("❌ No key code for {}", key.name()), egui::Color32::RED),
+                                }
+                            }
+                        }
+                    }
+                    let aliases = d.macropad_data.aliases.clone();
+                    match self.validate_temp(d.connected_pid, &aliases) {
+                        Ok(_) if self.temp_editor_val.is_empty() => { ui.label(egui::RichText::new("Unassigned").italics().size(11.0)); }
+                        Ok(_) => { ui.label(egui::RichText::new("✔ Valid mapping").size(11.0).color(egui::Color32::GREEN)); }
+                        Err(e) => { ui.label(egui::RichText::new(format!("❌ {}", e)).size(11.0).color(egui::Color32::RED)); }
+                    }
+                    if let Selection::Knob(idx, _) = d.selection {
+                        let layer_idx = d.current_layer_idx;
+                        let mut steps = d.macropad_data.layers[layer_idx].knobs[idx].steps_per_detent;
+                        ui.horizontal(|ui| {
+                            ui.label("Steps per detent:");
+                            let hint = "Send the rotation actions this many times per click of the knob. They are repeated in the sequence, so each repeat counts against the key press limit";
+                            if ui.add(egui::DragValue::new(&mut steps).range(1..=consts::MAX_STEPS_PER_DETENT)).on_hover_text(hint).changed() {
+                                let EditorData { history, macropad_data, .. } = &mut *d; history.push_edit(macropad_data, EditTarget::KnobSteps(layer_idx, idx));
+                                d.macropad_data.layers[layer_idx].knobs[idx].steps_per_detent = steps;
+                                d.mark_dirty();
+                            }
+                        });
+                    }
+                    if let Selection::Button(_, _) = d.selection {
+                        ui.horizontal(|ui| {
+                            ui.label("LED Color:");
+                            let selected_text = self.temp_led_color.map(|c| format!("{:?}", c)).unwrap_or("Default".to_string());
+                            let before = self.temp_led_color;
+                            egui::ComboBox::from_id_salt("key_led_color_cb").selected_text(selected_text).show_ui(ui, |ui| {
+                                ui.selectable_value(&mut self.temp_led_color, None, "Default");
+                                for color in LedColor::iter() {
+                                    ui.selectable_value(&mut self.temp_led_color, Some(color), format!("{:?}", color));
+                                }
+                            });
+                            if before != self.temp_led_color { self.sync_temp_to_data(&mut d); }
+                            Self::color_swatch(ui, self.temp_led_color.or(Some(self.led_color)).map(Self::led_color32));
+                            if let Some(pid) = d.connected_pid {
+                                if !consts::PER_KEY_LED_PIDS.contains(&pid) { ui.label(egui::RichText::new(format!("Note: per key colors aren't sent to 0x{:04x} yet", pid)).italics().size(10.0).color(egui::Color32::KHAKI)); }
+                            }
+                        });
+                    }
                     ui.add_space(10.0);
                     ui.heading("Code Reference Legend");
                     ui.group(|ui| {
-                        ui.horizontal(|ui| { ui.label(egui::RichText::new("Modifiers:").strong()); ui.label("ctrl-, shift-, alt-, win-, rctrl-, rshift-, ralt-, rwin-"); });
-                        ui.horizontal(|ui| { ui.label(egui::RichText::new("Media:").strong()); ui.label("play, stop, next, prev, mute, volup, voldown, brightnessup, brightnessdown"); });
-                        ui.horizontal(|ui| { ui.label(egui::RichText::new("Mouse:").strong()); ui.label("click, rclick, mclick, wheelup, wheeldown"); });
-                        ui.horizontal(|ui| { ui.label(egui::RichText::new("Other:").strong()); ui.label("space, enter, backspace, tab, esc, comma, dot, slash, a-z, 0-9, f1-f24"); });
+                        for (label, text) in LEGEND { ui.horizontal(|ui| { ui.label(egui::RichText::new(*label).strong()); ui.label(*text); }); }
                         ui.label(egui::RichText::new("Hint: Use commas to sequence commands (e.g. ctrl-c,ctrl-v) and dashes for combos (e.g. shift-a)").italics().size(11.0));
                     });
-                } else { ui.label(egui::RichText::new("Click a button in the grid above to edit its configuration").italics()); }
+                } else { ui.label(egui::RichText::new("Click a button in the grid above to edit its configuration, or drag it onto another button to copy it").italics()); }
             });
         });
+        self.show_alias_editor(ctx);
+        self.show_packet_dump(ctx);
+        self.show_confirmation(ctx);
         ctx.request_repaint_after(Duration::from_millis(500));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{clipboard_text, fill_col, fill_row, led_preview_colors, parse_clipboard, parse_pasted_mapping, resize_layers, usb_error_message, BTreeMap, EditTarget, History, Selection, LEGEND};
+    use crate::keyboard::UsbFailure;
+    use crate::mapping::{Button, Layer, Macropad, Mapping};
+
+    #[test]
+    fn legend_keys_validate() {
+        for (label, keys) in LEGEND.iter().filter(|(l, _)| matches!(*l, "Modifiers:" | "Media:" | "Mouse:" | "Other:")) {
+            for key in keys.split(", ") {
+                let mapping = match key.strip_suffix('-') { Some(modifier) => format!("{modifier}-a"), None => key.replace("dx,dy", "1,1") };
+                // ranges like a-z are checked at both ends
+                let ends: Vec<String> = if *label == "Other:" { mapping.split('-').map(String::from).collect() } else { vec![mapping] };
+                for end in ends {
+                    let btn = Button { delay: 0, mapping: end.clone(), led_color: None };
+                    assert!(Mapping::validate_button(&btn, Some(0x8840)).is_ok(), "{label} {end}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clipboard_round_trip() {
+        let btn = Button { delay: 20, mapping: "ctrl-c,ctrl-v".to_string(), led_color: Some(crate::keyboard::LedColor::Red) };
+        let text = clipboard_text(&btn);
+        assert_eq!(text, r#"(delay:20,mapping:"ctrl-c,ctrl-v")"#);
+        let pasted = parse_clipboard(&format!(" {text}\n"), Some(0x8840), &BTreeMap::new()).unwrap();
+        assert_eq!((pasted.delay, pasted.mapping.as_str(), pasted.led_color), (20, "ctrl-c,ctrl-v", None));
+        assert!(parse_clipboard("hello world", None, &BTreeMap::new()).is_err());
+        assert!(parse_clipboard(r#"(delay:0,mapping:"ctrl-frob")"#, None, &BTreeMap::new()).is_err());
+    }
+
+    #[test]
+    fn paste_into_mapping() {
+        assert_eq!(parse_pasted_mapping(" ctrl-c,ctrl-v,enter\n", 0, Some(0x8840), &BTreeMap::new()).unwrap(), "ctrl-c,ctrl-v,enter");
+        let err = format!("{:#}", parse_pasted_mapping("ctrl-c,frob", 0, Some(0x8840), &BTreeMap::new()).unwrap_err());
+        assert!(err.contains("frob"), "{err}");
+        assert!(parse_pasted_mapping("a\nb", 0, None, &BTreeMap::new()).is_err());
+        assert!(parse_pasted_mapping("  ", 0, None, &BTreeMap::new()).is_err());
+        let aliases = BTreeMap::from([("copy".to_string(), "ctrl-c".to_string())]);
+        assert_eq!(parse_pasted_mapping("copy", 0, None, &aliases).unwrap(), "copy");
+    }
+
+    #[test]
+    fn usb_errors_are_classified() {
+        let busy = anyhow::Error::new(rusb::Error::Busy).context("claim interface");
+        assert_eq!(UsbFailure::find(&busy), Some(UsbFailure::InUse));
+        assert_eq!(usb_error_message(&busy), UsbFailure::InUse.to_string());
+        let missing = anyhow::anyhow!(UsbFailure::NotFound).context("find USB device").context("opening keyboard");
+        assert_eq!(UsbFailure::find(&missing), Some(UsbFailure::NotFound));
+        let other = anyhow::Error::new(rusb::Error::Overflow).context("claim interface");
+        assert_eq!(UsbFailure::find(&other), None);
+        assert_eq!(usb_error_message(&other), format!("{:#}", other));
+    }
+
+    #[test]
+    fn resize_counts_discarded_mappings() {
+        let mut layer = Layer::new(2, 3, 1);
+        layer.buttons[0][0].mapping = "a".to_string();
+        layer.buttons[1][2].mapping = "b".to_string();
+        layer.knobs[0].cw.mapping = "volumeup".to_string();
+        let (grown, discarded) = resize_layers(std::slice::from_ref(&layer), 3, 4, 2, 2);
+        assert_eq!(discarded, 0);
+        assert_eq!(grown.len(), 2);
+        assert_eq!(grown[0].buttons[1][2].mapping, "b");
+        assert_eq!(grown[0].knobs[0].cw.mapping, "volumeup");
+        assert_eq!(grown[0].knobs.len(), 2, "new knob added");
+        let (shrunk, discarded) = resize_layers(&[layer.clone(), layer], 1, 3, 0, 1);
+        assert_eq!(discarded, 1 + 1 + 3, "key and knob of the first layer, the whole second layer");
+        assert_eq!(shrunk[0].buttons[0][0].mapping, "a");
+    }
+
+    #[test]
+    fn undo_layout_and_edits() {
+        let mut config = Macropad::new(3, 4, 2);
+        config.layers[0].buttons[2][3].mapping = "b".to_string();
+        config.layers[1].knobs[1].cw.mapping = "volumeup".to_string();
+        let mut history = History::default();
+        // what apply_layout does
+        history.push(&config);
+        let mut shrunk = config.clone();
+        (shrunk.device.rows, shrunk.device.cols, shrunk.device.knobs, shrunk.device.layers) = (1, 2, 0, 1);
+        shrunk.layers = resize_layers(&config.layers, 1, 2, 0, 1).0;
+        let restored = history.undo(&shrunk).unwrap();
+        assert_eq!((restored.device.rows, restored.device.cols, restored.device.knobs, restored.device.layers), (3, 4, 2, 3));
+        assert_eq!(restored.layers.len(), 3);
+        assert_eq!(restored.layers[0].buttons[2][3].mapping, "b");
+        assert_eq!(restored.layers[1].knobs[1].cw.mapping, "volumeup");
+        assert!(history.undo(&restored).is_none());
+        assert_eq!(history.redo(&restored).unwrap().layers.len(), 1);
+        // typing into one key is undone at once
+        let target = EditTarget::Button(0, Selection::Button(0, 0));
+        history.push_edit(&config, target);
+        history.push_edit(&config, target);
+        history.push_edit(&config, EditTarget::Leds);
+        assert_eq!((history.undo.len(), history.redo.len()), (3, 0));
+        // renaming a layer is undone at once too
+        history.push_edit(&config, EditTarget::LayerName(1));
+        history.push_edit(&config, EditTarget::LayerName(1));
+        assert_eq!(history.undo.len(), 4);
+    }
+
+    #[test]
+    fn led_preview_modes() {
+        use crate::keyboard::LedColor;
+        let lit = |mode, pid, time| led_preview_colors(mode, pid, LedColor::Red, time, 6).iter().filter(|c| c.is_some()).count();
+        assert_eq!(lit(0, 0x8842, 0.1), 0, "off");
+        assert_eq!(lit(1, 0x8842, 0.1), 6, "always on");
+        assert_eq!(lit(2, 0x8842, 0.1), 6, "shock right after a press");
+        assert_eq!(lit(2, 0x8842, 1.1), 0, "shock faded out");
+        assert_eq!(lit(4, 0x8842, 0.1), 1, "light key");
+        assert_eq!(lit(1, 0x8890, 0.1), 1, "last pushed");
+        assert_eq!(lit(2, 0x8890, 0.1), 6, "cycle colors");
+        assert_eq!(led_preview_colors(5, 0x8842, LedColor::Red, 0.0, 1)[0], Some(eframe::egui::Color32::WHITE));
+    }
+
+    #[test]
+    fn fill_rows_and_columns() {
+        let mut layer = Layer::new(3, 4, 1);
+        let btn = Button { delay: 10, mapping: "numpad1".to_string(), led_color: None };
+        fill_row(&mut layer, 1, &btn);
+        assert!(layer.buttons[1].iter().all(|b| b.mapping == "numpad1" && b.delay == 10));
+        assert!(layer.buttons[0].iter().chain(&layer.buttons[2]).all(|b| b.mapping.is_empty()));
+        fill_col(&mut layer, 3, &Button { mapping: "enter".to_string(), ..Button::new() });
+        assert!(layer.buttons.iter().all(|keys| keys[3].mapping == "enter"));
+        assert_eq!(layer.buttons[1][2].mapping, "numpad1");
+        fill_row(&mut layer, 5, &btn);
+        assert!(layer.knobs[0].press.mapping.is_empty(), "knobs are left alone");
+    }
+}
@@ -2,10 +2,11 @@ use crate::{
     consts,
     decoder::{Decoder, KeyMapping},
     keyboard::{
-        Configuration, Keyboard, LedColor, MediaCode, Messages, Modifier, MouseAction, MouseButton,
+        key_sequence, split_waits, Configuration, DeviceInfo, Keyboard, KnobAction, LedColor,
+        MediaCode, Messages, Modifier, MouseAction, MouseButton, PacketGroup, Progress,
         WellKnownCode,
     },
-    mapping::Macropad,
+    mapping::{Button, Layer, Macropad, Position},
 };
 use anyhow::{anyhow, ensure, Result};
 use log::{debug, info};
@@ -47,6 +48,7 @@ impl Configuration for Keyboard884x {
         // not sure if that would be an issue as i don't know the usb protocol. mabye
         // we could process here too??
         let mut mappings: Vec<KeyMapping> = Vec::new();
+        let num_layers = consts::max_layers(Some(self.pid));
         if *layer > 0 {
             // specific layer
             self.send(&self.read_config(device_info.num_keys, device_info.num_encoders, *layer))?;
@@ -67,7 +69,7 @@ impl Configuration for Keyboard884x {
             }
         } else {
             // read keys for all layers
-            for i in 1..=consts::NUM_LAYERS {
+            for i in 1..=num_layers {
                 self.send(&self.read_config(device_info.num_keys, device_info.num_encoders, i))?;
                 info!("reading keys for layer {i}");
                 let data = self.read_config(device_info.num_keys, device_info.num_encoders, i);
@@ -89,6 +91,11 @@ impl Configuration for Keyboard884x {
         // process responses from device
         let rows_cols = Self::guestimate_rows_cols(device_info.num_keys)?;
         let mut mp = Macropad::new(rows_cols.0, rows_cols.1, device_info.num_encoders);
+        mp.device.layers = num_layers;
+        mp.layers.resize(
+            num_layers.into(),
+            Layer::new(rows_cols.0, rows_cols.1, device_info.num_encoders),
+        );
         let mut knob_idx = 0;
         let mut knob_type = 0;
         let mut last_layer = 0;
@@ -149,6 +156,7 @@ impl Configuration for Keyboard884x {
     }
 }
 
+// 0x8850 answers the same messages as 0x8842
 impl Messages for Keyboard884x {
     fn read_config(&self, keys: u8, encoders: u8, layer: u8) -> Vec<u8> {
         if self.pid == 0x8840 {
@@ -190,13 +198,14 @@ impl Messages for Keyboard884x {
         }
     }
 
-    fn program_led(&self, mode: u8, layer: u8, color: LedColor) -> Vec<u8> {
+    fn program_led(&self, mode: u8, layer: u8, color: LedColor, _brightness: u8) -> Vec<u8> {
         let mut m_c = <LedColor as ToPrimitive>::to_u8(&color).unwrap();
         m_c |= mode;
         debug!("mode and code: 0x{m_c:02} layer: {layer}");
         let mut msg = vec![0x03, 0xfe, 0xb0, layer, 0x08];
         msg.extend_from_slice(&[0; 5]);
         msg.extend_from_slice(&[0x01, 0x00, m_c]);
+        // the brightness isn't sent until its byte is verified, see consts::LED_BRIGHTNESS_PIDS
         msg.extend_from_slice(&[0; 52]);
         msg
     }
@@ -206,32 +215,8 @@ impl Messages for Keyboard884x {
         msg.extend_from_slice(&[0; 61]);
         msg
     }
-}
-
-impl Keyboard for Keyboard884x {
-    fn program(&mut self, macropad: &Macropad) -> Result<()> {
-        // ensure the config we have matches the connected device we want to program
-        let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
-
-        // get the type of device
-        self.send(&self.device_type())?;
-        let bytes_read = self.recieve(&mut buf)?;
-
-        if bytes_read > 0 {
-            let device_info = Decoder::get_device_info(&buf);
-            ensure!(
-                device_info.num_keys == (macropad.device.rows * macropad.device.cols)
-                    && device_info.num_encoders == macropad.device.knobs,
-                "Configuration file and macropad mismatch.\nLooks like you are trying to program a different macropad.\nDid you select the right configuration file?\n\n\
-                If you think your mapping is correct, use the -s option to skip this check and program your device. Some of the 0x8840 products do not support\n\
-                reading and so you must use this option when programming."
-            );
-        } else {
-            // we probably have the type from amazon, while have the same product id, does not
-            // support reading. do not error out, but skip the check and continue to program
-            println!("Unable perform sanity check - device does not support reading of configuration. Programming macropad.");
-        }
 
+    fn build_packets(&self, macropad: &Macropad) -> Result<Vec<PacketGroup>> {
         // get our layout of buttons relative to programming orientation
         let layout = self.get_layout(
             macropad.device.orientation,
@@ -240,6 +225,7 @@ impl Keyboard for Keyboard884x {
         )?;
         debug!("layout: {layout:?}");
 
+        let mut groups = Vec::new();
         for (i, layer) in macropad.layers.iter().enumerate() {
             let lyr = (i + 1) as u8;
             let mut key_num;
@@ -247,79 +233,134 @@ impl Keyboard for Keyboard884x {
                 for (col_idx, btn) in row.iter().enumerate() {
                     debug!("get position in layout: row_idx: {row_idx} col_idx: {col_idx}");
                     key_num = layout[row_idx][col_idx];
-                    debug!(
-                        "program layer: {} key: 0x{:02x} to: {btn:?}",
-                        i + 1,
-                        key_num
-                    );
-                    self.send(&self.build_key_msg(&btn.mapping, lyr, key_num, 0)?)?;
-                    if btn.delay > 0 {
-                        let mut msg = self.build_key_msg(&btn.mapping, lyr, key_num, btn.delay)?;
-                        msg[4] = 5;
-                        self.send(&msg)?;
-                    }
+                    debug!("program layer: {lyr} key: 0x{key_num:02x} to: {btn:?}");
+                    let position = Position::Key(row_idx as u8 + 1, col_idx as u8 + 1);
+                    groups.push(PacketGroup::action(
+                        lyr,
+                        position,
+                        key_num,
+                        &btn.mapping,
+                        self.key_packets(btn, lyr, key_num)?,
+                    ));
                 }
             }
 
             // TODO: test 9x3 to see if the 3 knobs are top to bottom with key number
             key_num = 0x10;
-            for knob in &layer.knobs {
-                debug!(
-                    "layer: {} key: 0x{:02x} knob ccw {}",
-                    i + 1,
-                    key_num,
-                    knob.ccw.mapping
-                );
-                self.send(&self.build_key_msg(&knob.ccw.mapping, lyr, key_num, 0)?)?;
-                if knob.ccw.delay > 0 {
-                    let mut msg =
-                        self.build_key_msg(&knob.ccw.mapping, lyr, key_num, knob.ccw.delay)?;
-                    msg[4] = 5;
-                    self.send(&msg)?;
-                }
-                key_num += 1;
-
-                debug!(
-                    "layer: {} key: 0x{:02x} knob press {}",
-                    i + 1,
-                    key_num,
-                    knob.press.mapping
-                );
-                self.send(&self.build_key_msg(&knob.press.mapping, lyr, key_num, 0)?)?;
-                if knob.press.delay > 0 {
-                    let mut msg =
-                        self.build_key_msg(&knob.press.mapping, lyr, key_num, knob.press.delay)?;
-                    msg[4] = 5;
-                    self.send(&msg)?;
+            for (knob_idx, knob) in layer.knobs.iter().enumerate() {
+                for (action, btn) in [
+                    (KnobAction::RotateCCW, &knob.ccw),
+                    (KnobAction::Press, &knob.press),
+                    (KnobAction::RotateCW, &knob.cw),
+                ] {
+                    debug!(
+                        "layer: {lyr} key: 0x{key_num:02x} knob {action} {}",
+                        btn.mapping
+                    );
+                    let position = Position::Knob(knob_idx as u8 + 1, action);
+                    groups.push(PacketGroup::action(
+                        lyr,
+                        position,
+                        key_num,
+                        &btn.mapping,
+                        self.key_packets(btn, lyr, key_num)?,
+                    ));
+                    key_num += 1;
                 }
-                key_num += 1;
+            }
 
-                debug!(
-                    "layer: {} key: 0x{:02x} knob cw {}",
-                    i + 1,
-                    key_num,
-                    knob.cw.mapping
-                );
-                self.send(&self.build_key_msg(&knob.cw.mapping, lyr, key_num, 0)?)?;
-                if knob.cw.delay > 0 {
-                    let mut msg =
-                        self.build_key_msg(&knob.cw.mapping, lyr, key_num, knob.cw.delay)?;
-                    msg[4] = 5;
-                    self.send(&msg)?;
+            // per key LED colors; keys without a color use the global LED color
+            let per_key_led = consts::PER_KEY_LED_PIDS.contains(&self.pid)
+                && layer
+                    .buttons
+                    .iter()
+                    .flatten()
+                    .any(|b| b.led_color.is_some());
+            if per_key_led {
+                let default_color = macropad.led_settings.map(|l| l.color);
+                let mut packets = Vec::new();
+                for (row_idx, row) in layer.buttons.iter().enumerate() {
+                    for (col_idx, btn) in row.iter().enumerate() {
+                        if let Some(color) = btn.led_color.or(default_color) {
+                            key_num = layout[row_idx][col_idx];
+                            packets.extend(self.program_key_led(lyr, key_num, color));
+                        }
+                    }
                 }
-                key_num += 1;
+                groups.push(PacketGroup::step(format!("layer {lyr} key LEDs"), packets));
             }
-            self.send(&self.end_program())?;
+            groups.push(PacketGroup::step(
+                format!("layer {lyr} end of programming"),
+                vec![self.end_program()],
+            ));
         }
-        Ok(())
+        Ok(groups)
     }
+}
 
-    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor) -> Result<()> {
-        self.send(&self.program_led(mode, layer, color))?;
+impl Keyboard for Keyboard884x {
+    fn program(&mut self, macropad: &Macropad, progress: Progress) -> Result<()> {
+        // ensure the config we have matches the connected device we want to program
+        let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
+
+        // get the type of device
+        self.send(&self.device_type())?;
+        let bytes_read = self.recieve(&mut buf)?;
+
+        if bytes_read > 0 {
+            let device_info = Decoder::get_device_info(&buf);
+            ensure!(
+                device_info.num_keys == (macropad.device.rows * macropad.device.cols)
+                    && device_info.num_encoders == macropad.device.knobs,
+                "Configuration file and macropad mismatch.\nLooks like you are trying to program a different macropad.\nDid you select the right configuration file?\n\n\
+                If you think your mapping is correct, use the -s option to skip this check and program your device. Some of the 0x8840 products do not support\n\
+                reading and so you must use this option when programming."
+            );
+        } else {
+            // we probably have the type from amazon, while have the same product id, does not
+            // support reading. do not error out, but skip the check and continue to program
+            println!("Unable perform sanity check - device does not support reading of configuration. Programming macropad.");
+        }
+
+        let groups = self.build_packets(macropad)?;
+        self.send_packets(&groups, progress)
+    }
+
+    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor, brightness: u8) -> Result<()> {
+        self.send(&self.program_led(mode, layer, color, brightness))?;
         self.send(&self.end_program())?;
         Ok(())
     }
 
+    fn read_device_info(&mut self) -> Result<DeviceInfo> {
+        let mut info = DeviceInfo::from_usb(self.get_handle(), self.pid)?;
+        let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
+        self.send(&self.device_type())?;
+        // some 0x8840 products don't answer, leave the key counts unknown for those
+        if self.recieve(&mut buf)? > 0 {
+            let device_info = Decoder::get_device_info(&buf);
+            info.num_keys = Some(device_info.num_keys);
+            info.num_encoders = Some(device_info.num_encoders);
+        }
+        Ok(info)
+    }
+
+    fn query_layout(&mut self) -> Result<(u8, u8, u8, u8)> {
+        let layers = consts::max_layers(Some(self.pid));
+        if let Some((rows, cols, knobs)) = consts::fixed_layout(self.pid) {
+            return Ok((rows, cols, knobs, layers));
+        }
+        let info = self.read_device_info()?;
+        let (Some(keys), Some(knobs)) = (info.num_keys, info.num_encoders) else {
+            return Err(anyhow!(
+                "0x{:04x} doesn't report its layout, set it by hand",
+                self.pid
+            ));
+        };
+        let (rows, cols) = Self::guestimate_rows_cols(keys)?;
+        Ok((rows, cols, knobs, layers))
+    }
+
     fn get_handle(&self) -> &DeviceHandle<Context> {
         self.handle.as_ref().unwrap()
     }
@@ -350,6 +391,25 @@ impl Keyboard884x {
         Ok(keyboard)
     }
 
+    /// Returns the packets with the mapping of a key, followed by its delay when it has
+    /// one. The delay comes from the button or from the `wait:` pauses of its sequence
+    ///
+    /// #Arguments
+    /// `btn` - button to program
+    /// `layer` - layer of the key
+    /// `key_num` - number of the key on the device
+    ///
+    fn key_packets(&self, btn: &Button, layer: u8, key_num: u8) -> Result<Vec<Vec<u8>>> {
+        let (_, delay) = split_waits(&key_sequence(&btn.mapping)?, btn.delay)?;
+        let mut packets = vec![self.build_key_msg(&btn.mapping, layer, key_num, 0)?];
+        if delay > 0 {
+            let mut msg = self.build_key_msg(&btn.mapping, layer, key_num, delay)?;
+            msg[4] = 5;
+            packets.push(msg);
+        }
+        Ok(packets)
+    }
+
     fn build_key_msg(
         &self,
         key_chord: &str,
@@ -357,7 +417,13 @@ impl Keyboard884x {
         key_pos: u8,
         delay: u16,
     ) -> Result<Vec<u8>> {
-        let keys: Vec<_> = key_chord.split(',').collect();
+        let (keys, _) = split_waits(&key_sequence(key_chord)?, delay)?;
+        ensure!(
+            keys.len() <= consts::MAX_KEY_PRESSES_884X,
+            "maximum key presses for this macropad is {}",
+            consts::MAX_KEY_PRESSES_884X
+        );
+        MouseAction::check_move_steps(&keys)?;
         let mut msg = vec![0x03, 0xfd, key_pos, layer, 0x01];
         msg.extend_from_slice(&[0; 5]);
         msg.extend_from_slice(&[keys.len().try_into()?]);
@@ -371,9 +437,17 @@ impl Keyboard884x {
         let mut cnt = 0;
         let mut mouse_action = 0u8;
         let mut mouse_click = 0u8;
+        let mut mouse_move = None;
         let mut media_key = false;
         let mut media_val = 0u8;
         for binding in &keys {
+            if let Some(MouseAction::Move(dx, dy)) = MouseAction::parse_move(binding)? {
+                msg[4] = 0x03;
+                mouse_move = Some((dx, dy));
+                msg.extend_from_slice(&[0x00, 0x00]);
+                cnt += 1;
+                continue;
+            }
             let kc: Vec<_> = binding.split('-').collect();
             let mut m_c = 0x00u8;
             let mut wkk = 0x00;
@@ -425,6 +499,10 @@ impl Keyboard884x {
         if mouse_action > 0 {
             msg[15] = mouse_action;
         }
+        if let Some((dx, dy)) = mouse_move {
+            msg[13] = dx as u8;
+            msg[14] = dy as u8;
+        }
 
         // last 18 bytes are always 0
         msg.extend_from_slice(&[0; 18]);
@@ -705,7 +783,7 @@ mod tests {
     #[test]
     fn led_mode3_blue_layer_3() -> anyhow::Result<()> {
         let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
-        let msg = kbd.program_led(3, 3, LedColor::Blue);
+        let msg = kbd.program_led(3, 3, LedColor::Blue, 100);
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
         assert_eq!(msg[0], 0x03, "checking first byte of led programming");
@@ -718,6 +796,14 @@ mod tests {
         }
         assert_eq!(msg[10], 0x01, "checking eleventh byte of programming led");
         assert_eq!(msg[12], 0x63, "checking mode and color of programming led");
+        for i in msg.iter().skip(13) {
+            assert_eq!(*i, 0x00);
+        }
+        assert_eq!(
+            kbd.program_led(3, 3, LedColor::Blue, 40),
+            msg,
+            "brightness isn't sent"
+        );
         Ok(())
     }
 
@@ -737,6 +823,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn consumer_keys() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        // usages below 0x100 have no high byte
+        for (key, count, low, high) in [
+            ("sleep", 0x02, 0x32, 0x00),
+            ("mail", 0x01, 0x8a, 0x01),
+            ("mycomputer", 0x01, 0x94, 0x01),
+            ("search", 0x02, 0x21, 0x02),
+        ] {
+            let msg = kbd.build_key_msg(key, 1u8, 1u8, 0)?;
+            assert_eq!(msg[4], 0x02, "{key}: checking byte 4");
+            assert_eq!(msg[10], count, "{key}: checking byte 10");
+            assert_eq!(msg[11], low, "{key}: checking byte 11");
+            assert_eq!(msg[12], high, "{key}: checking byte 12");
+        }
+        Ok(())
+    }
+
     #[test]
     fn back() -> anyhow::Result<()> {
         let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
@@ -752,4 +857,203 @@ mod tests {
         assert_eq!(msg[12], 0x02, "checking byte 12");
         Ok(())
     }
+
+    #[test]
+    fn text() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let msg = kbd.build_key_msg("text:Hi!", 1u8, 1u8, 0)?;
+        println!("{:02x?}", msg);
+        assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
+        assert_eq!(msg[4], 0x01, "checking byte 4");
+        assert_eq!(msg[10], 0x03, "checking number of keys to program");
+        assert_eq!(msg[11], 0x02, "checking for shift modifier");
+        assert_eq!(msg[12], 0x0b, "checking for 'h' key");
+        assert_eq!(msg[13], 0x00, "checking for no modifier");
+        assert_eq!(msg[14], 0x0c, "checking for 'i' key");
+        assert_eq!(msg[15], 0x02, "checking for shift modifier");
+        assert_eq!(msg[16], 0x1e, "checking for '1' key");
+        Ok(())
+    }
+
+    #[test]
+    fn hold() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let msg = kbd.build_key_msg("hold:shift-w", 1u8, 1u8, 0)?;
+        println!("{:02x?}", msg);
+        assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
+        assert_eq!(
+            msg[10],
+            consts::HOLD_REPEATS as u8,
+            "checking number of key presses"
+        );
+        for i in 0..consts::HOLD_REPEATS {
+            assert_eq!(msg[11 + i * 2], 0x02, "checking for shift modifier");
+            assert_eq!(msg[12 + i * 2], 0x1a, "checking for 'w' key");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn wait() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let msg = kbd.build_key_msg("ctrl-c,wait:300,ctrl-v", 1u8, 1u8, 300)?;
+        println!("{:02x?}", msg);
+        assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
+        assert_eq!(&msg[5..7], &300u16.to_le_bytes(), "checking delay");
+        assert_eq!(msg[10], 0x02, "checking number of keys to program");
+        assert_eq!(
+            &msg[11..15],
+            &[0x01, 0x06, 0x01, 0x19],
+            "checking ctrl-c, ctrl-v"
+        );
+        assert!(kbd
+            .build_key_msg("ctrl-c,wait:300,ctrl-v,ctrl-x", 1u8, 1u8, 0)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn text_unknown_char() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let err = kbd.build_key_msg("text:café", 1u8, 1u8, 0).unwrap_err();
+        assert!(err.to_string().contains("'é'"), "error names the character");
+        Ok(())
+    }
+
+    #[test]
+    fn key_led_not_sent() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        assert!(kbd.program_key_led(2, 4, LedColor::Green).is_empty());
+        let mut macropad = crate::mapping::Macropad::new(1, 2, 0);
+        macropad.layers[0].buttons[0][1].led_color = Some(LedColor::Green);
+        let groups = kbd.build_packets(&macropad)?;
+        assert!(
+            !groups
+                .iter()
+                .flat_map(|g| &g.packets)
+                .any(|p| p[..3] == [0x03, 0xfe, 0xb1]),
+            "no per key LED packets"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn mouse_move() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let msg = kbd.build_key_msg("move:10,-5", 1u8, 1u8, 0)?;
+        println!("{:02x?}", msg);
+        assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
+        assert_eq!(msg[4], 0x03, "checking byte 4");
+        assert_eq!(msg[10], 0x01, "checking byte 10");
+        assert_eq!(msg[13], 0x0a, "checking x movement");
+        assert_eq!(msg[14], 0xfb, "checking y movement");
+        Ok(())
+    }
+
+    #[test]
+    fn mouse_move_invalid() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let err = kbd.build_key_msg("move:10,x", 1u8, 1u8, 0).unwrap_err();
+        assert!(
+            err.to_string().contains("move:10,x"),
+            "error names the token"
+        );
+        assert!(kbd.build_key_msg("move:200,0", 1u8, 1u8, 0).is_err());
+        assert!(
+            kbd.build_key_msg("ctrl-c,move:5,5", 1u8, 1u8, 0).is_err(),
+            "keys next to a movement"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn program_steps_over_layers() {
+        let macropad = crate::mapping::Macropad::new(3, 4, 2);
+        assert_eq!(
+            crate::keyboard::program_steps(&macropad),
+            macropad.layers.len() * (12 + 3 * 2)
+        );
+    }
+
+    #[test]
+    fn build_packets_by_action() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8840)?;
+        let mut macropad = crate::mapping::Macropad::new(1, 2, 1);
+        macropad.layers.truncate(1);
+        macropad.device.layers = 1;
+        macropad.layers[0].buttons[0][0].mapping = "ctrl-c".to_string();
+        macropad.layers[0].buttons[0][1].mapping = "a,wait:100,b".to_string();
+        let groups = kbd.build_packets(&macropad)?;
+        assert_eq!(groups.len(), 2 + 3 + 1);
+        assert_eq!(groups.iter().filter(|g| g.action).count(), 5);
+        assert_eq!(groups[0].label, "layer 1 row 1 btn 1 (key 0x01): 'ctrl-c'");
+        assert_eq!(
+            groups[0].packets,
+            vec![kbd.build_key_msg("ctrl-c", 1, 1, 0)?]
+        );
+        // the pause is sent as a second packet
+        assert_eq!(groups[1].packets.len(), 2);
+        assert_eq!(groups[1].packets[1][4], 5);
+        assert_eq!(groups[2].label, "layer 1 knob 1 ccw (key 0x10): ''");
+        assert_eq!(groups[5].packets, vec![kbd.end_program()]);
+        assert!(groups[0].to_string().ends_with("\n  03 fd 01 01 01 00 00 00 00 00 01 01 06 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00"));
+        Ok(())
+    }
+
+    #[test]
+    fn clear_packets() -> anyhow::Result<()> {
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8840)?;
+        let groups = kbd.build_packets(&crate::mapping::Macropad::empty(3, 4, 2, 2))?;
+        assert_eq!(groups.iter().filter(|g| g.action).count(), 2 * (12 + 2 * 3));
+        // every action is programmed without a key code
+        assert!(groups
+            .iter()
+            .filter(|g| g.action)
+            .all(|g| g.packets.len() == 1 && g.packets[0][11..].iter().all(|b| *b == 0)));
+        assert_eq!(groups.last().unwrap().packets, vec![kbd.end_program()]);
+        Ok(())
+    }
+
+    #[test]
+    fn numpad_keys() -> anyhow::Result<()> {
+        use crate::keyboard::WellKnownCode;
+        use std::str::FromStr;
+        use strum::EnumMessage;
+        let kbd = Keyboard884x::new(None, 0, 0, 0x8842)?;
+        let keys = [
+            ("num1", 0x59),
+            ("num2", 0x5a),
+            ("num3", 0x5b),
+            ("num4", 0x5c),
+            ("num5", 0x5d),
+            ("num6", 0x5e),
+            ("num7", 0x5f),
+            ("num8", 0x60),
+            ("num9", 0x61),
+            ("num0", 0x62),
+            ("numslash", 0x54),
+            ("numasterisk", 0x55),
+            ("nummultiply", 0x55),
+            ("numminus", 0x56),
+            ("numplus", 0x57),
+            ("numenter", 0x58),
+            ("numdot", 0x63),
+            ("numequal", 0x67),
+            ("numlock", 0x53),
+        ];
+        for (name, code) in keys {
+            let key = WellKnownCode::from_str(name)?;
+            let canonical = key.to_string();
+            assert!(key.get_serializations().contains(&name), "{name}");
+            for mapping in [name, canonical.as_str()] {
+                let msg = kbd.build_key_msg(mapping, 1u8, 1u8, 0)?;
+                assert_eq!(msg[10], 0x01, "{mapping}: checking number of keys");
+                assert_eq!(msg[11], 0x00, "{mapping}: checking for no modifier");
+                assert_eq!(msg[12], code, "{mapping}: checking key code");
+            }
+        }
+        assert_eq!(WellKnownCode::from_str("num1")?.to_string(), "numpad1");
+        assert_eq!(WellKnownCode::from_str("1")?.to_string(), "1");
+        Ok(())
+    }
 }
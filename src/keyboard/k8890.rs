@@ -1,12 +1,14 @@
 use crate::{
     consts,
     keyboard::{
-        Configuration, Keyboard, LedColor, MediaCode, Messages, Modifier, MouseAction, MouseButton,
-        WellKnownCode,
+        expand_mapping, split_sequence, Configuration, DeviceInfo, Keyboard, KnobAction, LedColor,
+        MediaCode, Messages, Modifier, MouseAction, MouseButton, PacketGroup, Progress,
+        Unsupported, WellKnownCode,
     },
+    mapping::{Button, Position},
     Macropad,
 };
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, ensure, Result};
 use log::debug;
 use num::ToPrimitive;
 use rusb::{Context, DeviceHandle};
@@ -33,7 +35,7 @@ impl Messages for Keyboard8890 {
         unimplemented!("reading device type is not supported");
     }
 
-    fn program_led(&self, mode: u8, _layer: u8, _color: LedColor) -> Vec<u8> {
+    fn program_led(&self, mode: u8, _layer: u8, _color: LedColor, _brightness: u8) -> Vec<u8> {
         let mut msg = vec![0x03, 0xb0, 0x18, mode];
         let size = consts::PACKET_SIZE - msg.len();
         msg.extend_from_slice(&vec![0; size]);
@@ -47,16 +49,15 @@ impl Messages for Keyboard8890 {
         msg.extend_from_slice(&vec![0; size]);
         msg
     }
-}
-
-impl Keyboard for Keyboard8890 {
-    fn program(&mut self, macropad: &Macropad) -> Result<()> {
-        debug!("programming keyboard - NOTE: hardcoding to layer 1");
 
+    fn build_packets(&self, macropad: &Macropad) -> Result<Vec<PacketGroup>> {
         // FIXME: currently hardcoding the layer to 1 as the only 8890 device
         //        i have seen only has support for one layer. if we know of
         //        one that has multiple layers, we should refactor this then
-        self.send(&self.begin_programming(1))?;
+        let mut groups = vec![PacketGroup::step(
+            "begin programming",
+            vec![self.begin_programming(1)],
+        )];
 
         // get our layout of buttons relative to programming orientation
         let layout = self.get_layout(
@@ -67,72 +68,85 @@ impl Keyboard for Keyboard8890 {
         debug!("layout: {layout:?}");
 
         for (i, layer) in macropad.layers.iter().enumerate() {
+            let lyr = (i + 1) as u8;
             let mut key_num;
             for (row_idx, row) in layer.buttons.iter().enumerate() {
                 for (col_idx, btn) in row.iter().enumerate() {
                     debug!("get position in layout: row_idx: {row_idx} col_idx: {col_idx}");
                     key_num = layout[row_idx][col_idx];
-                    debug!(
-                        "program layer: {} key: 0x{:02x} to: {btn:?}",
-                        i + 1,
-                        key_num
-                    );
-                    let keys: Vec<_> = btn.mapping.split(',').collect();
-                    if keys.len() > consts::MAX_KEY_PRESSES_8890 {
-                        return Err(anyhow!(
-                            "maximum key presses for this macropad is {}",
-                            consts::MAX_KEY_PRESSES_8890
-                        ));
-                    }
-                    for msg in self.map_key(btn.mapping.to_string(), key_num)? {
-                        self.send(&msg)?;
-                    }
+                    debug!("program layer: {lyr} key: 0x{key_num:02x} to: {btn:?}");
+                    let position = Position::Key(row_idx as u8 + 1, col_idx as u8 + 1);
+                    groups.push(PacketGroup::action(
+                        lyr,
+                        position,
+                        key_num,
+                        &btn.mapping,
+                        self.key_packets(btn, key_num)?,
+                    ));
                 }
             }
             key_num = 0x0du8;
-            for knob in &layer.knobs {
+            for (knob_idx, knob) in layer.knobs.iter().enumerate() {
                 debug!(
                     "programming knob ccw: {} cw: {} push: {}",
                     knob.ccw.mapping, knob.cw.mapping, knob.press.mapping
                 );
-                let mut btn;
-                for i in 0..3 {
-                    match i {
-                        0 => btn = knob.ccw.clone(),
-                        1 => btn = knob.press.clone(),
-                        2 => btn = knob.cw.clone(),
-                        _ => unreachable!("should not get here"),
-                    }
-                    let keys: Vec<_> = btn.mapping.split(',').collect();
-                    if keys.len() > consts::MAX_KEY_PRESSES_8890 {
-                        return Err(anyhow!(
-                            "maximum key presses for this macropad is {}",
-                            consts::MAX_KEY_PRESSES_8890
-                        ));
-                    }
-                    for msg in self.map_key(btn.mapping.to_string(), key_num)? {
-                        self.send(&msg)?;
-                    }
+                for (action, btn) in [
+                    (KnobAction::RotateCCW, &knob.ccw),
+                    (KnobAction::Press, &knob.press),
+                    (KnobAction::RotateCW, &knob.cw),
+                ] {
+                    let position = Position::Knob(knob_idx as u8 + 1, action);
+                    groups.push(PacketGroup::action(
+                        lyr,
+                        position,
+                        key_num,
+                        &btn.mapping,
+                        self.key_packets(btn, key_num)?,
+                    ));
                     key_num += 1;
                 }
             }
         }
-        self.send(&self.end_program())?;
+        groups.push(PacketGroup::step(
+            "end of programming",
+            vec![self.end_program()],
+        ));
+        Ok(groups)
+    }
+}
+
+impl Keyboard for Keyboard8890 {
+    fn program(&mut self, macropad: &Macropad, progress: Progress) -> Result<()> {
+        debug!("programming keyboard - NOTE: hardcoding to layer 1");
+        let groups = self.build_packets(macropad)?;
+        self.send_packets(&groups, progress)?;
         debug!("DONE - programming keyboard");
         Ok(())
     }
 
-    fn set_led(&mut self, mode: u8, layer: u8, _color: LedColor) -> Result<()> {
+    fn set_led(&mut self, mode: u8, layer: u8, _color: LedColor, _brightness: u8) -> Result<()> {
         if mode > 2 {
             return Err(anyhow!("macropad supports modes 0, 1, and 2 only"));
         }
         self.led_programmed = true;
         self.send(&self.begin_programming(layer))?;
-        self.send(&self.program_led(mode, layer, LedColor::Red))?;
+        self.send(&self.program_led(mode, layer, LedColor::Red, consts::MAX_LED_BRIGHTNESS))?;
         self.send(&self.end_program())?;
         Ok(())
     }
 
+    fn read_device_info(&mut self) -> Result<DeviceInfo> {
+        Err(Unsupported("0x8890 can't be queried for device information".to_string()).into())
+    }
+
+    fn query_layout(&mut self) -> Result<(u8, u8, u8, u8)> {
+        let (rows, cols, knobs) =
+            consts::fixed_layout(0x8890).ok_or_else(|| anyhow!("unknown layout for 0x8890"))?;
+        // single layer, programming always targets layer 1
+        Ok((rows, cols, knobs, 1))
+    }
+
     fn get_handle(&self) -> &DeviceHandle<Context> {
         self.handle.as_ref().unwrap()
     }
@@ -164,14 +178,45 @@ impl Keyboard8890 {
         msg
     }
 
+    /// Returns the packets programming the mapping of a key
+    ///
+    /// #Arguments
+    /// `btn` - button to program
+    /// `key_num` - number of the key on the device
+    ///
+    fn key_packets(&self, btn: &Button, key_num: u8) -> Result<Vec<Vec<u8>>> {
+        let mapping = expand_mapping(&btn.mapping)?;
+        let keys = split_sequence(&mapping);
+        ensure!(
+            !keys.iter().any(|k| k.starts_with(consts::WAIT_PREFIX)),
+            "0x8890 doesn't support {} pauses",
+            consts::WAIT_PREFIX
+        );
+        if keys.len() > consts::MAX_KEY_PRESSES_8890 {
+            return Err(anyhow!(
+                "maximum key presses for this macropad is {}",
+                consts::MAX_KEY_PRESSES_8890
+            ));
+        }
+        self.map_key(mapping, key_num)
+    }
+
     fn map_key(&self, key_chord: String, key_pos: u8) -> Result<Vec<Vec<u8>>> {
         let mut retval = Vec::new();
         let mut prepend = Vec::new();
-        let kc: Vec<_> = key_chord.split(',').collect();
+        let kc = split_sequence(&key_chord);
         let mut prepended = false;
         for (i, key) in kc.iter().enumerate() {
             let mut msg = vec![0x03, key_pos, 0x00, 0x00, 0x00, 0x00, 0x00];
             let mut remaining = consts::PACKET_SIZE - msg.len();
+            if let Some(MouseAction::Move(dx, dy)) = MouseAction::parse_move(key)? {
+                msg[2] = 0x13;
+                msg[4] = dx as u8;
+                msg[5] = dy as u8;
+                msg.extend_from_slice(&vec![0; remaining]);
+                retval.push(msg);
+                continue;
+            }
             let km: Vec<_> = key.split('-').collect();
             let mut mouse_action = 0u8;
             let mut mouse_click;
@@ -267,7 +312,7 @@ impl Keyboard8890 {
 mod tests {
     use crate::{
         consts,
-        keyboard::{k8890::Keyboard8890, LedColor, Messages},
+        keyboard::{expand_mapping, k8890::Keyboard8890, Keyboard, LedColor, Messages},
     };
 
     #[test]
@@ -351,7 +396,7 @@ mod tests {
     fn led_mode2() -> anyhow::Result<()> {
         let mut kbd = Keyboard8890::new(None, 0)?;
         kbd.led_programmed = true;
-        let msg = kbd.program_led(2, 1, LedColor::Red);
+        let msg = kbd.program_led(2, 1, LedColor::Red, 100);
         println!("{:02x?}", msg);
         assert_eq!(msg.len(), consts::PACKET_SIZE, "checking msg size");
         assert_eq!(msg[1], 0xb0, "checking first byte of led programming");
@@ -415,4 +460,43 @@ mod tests {
         assert_eq!(&expected, &msgs[0][..8], "checking message");
         Ok(())
     }
+
+    #[test]
+    fn text() -> anyhow::Result<()> {
+        let kbd = Keyboard8890::new(None, 0)?;
+        let msgs = kbd.map_key(expand_mapping("text:a@")?, 1)?;
+        println!("{:02x?}", msgs);
+        assert_eq!(msgs.len(), 3, "number of messages created");
+        let expected = vec![0x03, 0x01, 0x11, 0x02, 0x01, 0x00, 0x04];
+        assert_eq!(&expected, &msgs[1][..7], "checking message");
+        let expected = vec![0x03, 0x01, 0x11, 0x02, 0x02, 0x02, 0x1f];
+        assert_eq!(&expected, &msgs[2][..7], "checking message");
+        Ok(())
+    }
+
+    #[test]
+    fn mouse_move() -> anyhow::Result<()> {
+        let kbd = Keyboard8890::new(None, 0)?;
+        let msgs = kbd.map_key("move:-3,7".to_string(), 1)?;
+        println!("{:02x?}", msgs);
+        assert_eq!(msgs.len(), 1, "number of messages created");
+        let expected = vec![0x03, 0x01, 0x13, 0x00, 0xfd, 0x07, 0x00];
+        assert_eq!(msgs[0].len(), consts::PACKET_SIZE, "checking msg size");
+        assert_eq!(&expected, &msgs[0][..7], "checking message");
+        Ok(())
+    }
+
+    #[test]
+    fn no_key_leds() -> anyhow::Result<()> {
+        let kbd = Keyboard8890::new(None, 0)?;
+        assert!(kbd.program_key_led(1, 1, LedColor::Green).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn fixed_layout() -> anyhow::Result<()> {
+        let mut kbd = Keyboard8890::new(None, 0)?;
+        assert_eq!(kbd.query_layout()?, (1, 3, 1, 1));
+        Ok(())
+    }
 }
@@ -1,11 +1,17 @@
 pub(crate) mod k884x;
 pub(crate) mod k8890;
 
-use crate::{config, config::Orientation, consts, mapping::Macropad};
+use crate::{
+    config,
+    config::Orientation,
+    consts, layout,
+    mapping::{Macropad, Position},
+};
 
 use std::fmt::Display;
+use std::str::FromStr;
 
-use anyhow::{ensure, Result};
+use anyhow::{anyhow, ensure, Result};
 use enumset::{EnumSet, EnumSetType};
 use log::debug;
 use num_derive::{FromPrimitive, ToPrimitive};
@@ -35,14 +41,35 @@ pub trait Messages {
     /// `mode` - preset mode of the LED
     /// `layer` - layer to program
     /// `color` - the color to use for the mode
+    /// `brightness` - brightness in percent
     ///
-    fn program_led(&self, mode: u8, layer: u8, color: LedColor) -> Vec<u8>;
+    fn program_led(&self, mode: u8, layer: u8, color: LedColor, brightness: u8) -> Vec<u8>;
+
+    /// Returns the messages to program the LED of a single key on the macropad, none
+    /// when the device doesn't support per key LED colors
+    ///
+    /// #Arguments
+    /// `layer` - layer to program
+    /// `key` - key number to program
+    /// `color` - the color of the key
+    ///
+    fn program_key_led(&self, _layer: u8, _key: u8, _color: LedColor) -> Vec<Vec<u8>> {
+        Vec::new()
+    }
 
     /// Returns the "end of programming" message for the device. This message
     /// effectively tell the device to 'save its configuration' so when it is
     /// unplugged, it retains its settings
     ///
     fn end_program(&self) -> Vec<u8>;
+
+    /// Returns every packet sent to program the configuration, in order and grouped by
+    /// key or knob action. Nothing is sent, so this also works without a device
+    ///
+    /// #Arguments
+    /// `macropad` - configuration to be programmed
+    ///
+    fn build_packets(&self, macropad: &Macropad) -> Result<Vec<PacketGroup>>;
 }
 
 pub trait Configuration {
@@ -103,13 +130,109 @@ pub trait Configuration {
     }
 }
 
+/// Callback reporting how many of the keys and knob actions have been programmed,
+/// called with `(done, total)`
+pub type Progress<'a> = Option<&'a mut dyn FnMut(usize, usize)>;
+
+/// Returns the number of key and knob actions programmed for a configuration, the
+/// `total` passed to the progress callback
+///
+/// #Arguments
+/// `macropad` - configuration to be programmed
+///
+pub fn program_steps(macropad: &Macropad) -> usize {
+    macropad
+        .layers
+        .iter()
+        .map(|l| l.buttons.iter().map(|r| r.len()).sum::<usize>() + 3 * l.knobs.len())
+        .sum()
+}
+
+/// Packets sent to the macropad for one key or knob action, or for a step around them
+/// (eg. the end of programming)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PacketGroup {
+    /// what the packets program
+    pub label: String,
+    /// true for key and knob actions, which are counted by the progress callback
+    pub action: bool,
+    pub packets: Vec<Vec<u8>>,
+}
+
+impl PacketGroup {
+    /// Returns the packets programming a key or knob action
+    ///
+    /// #Arguments
+    /// `layer` - layer of the key (one based)
+    /// `position` - key or knob action
+    /// `key_num` - number of the key on the device
+    /// `mapping` - mapping of the key
+    /// `packets` - packets to send
+    ///
+    pub fn action(
+        layer: u8,
+        position: Position,
+        key_num: u8,
+        mapping: &str,
+        packets: Vec<Vec<u8>>,
+    ) -> Self {
+        Self {
+            label: format!("layer {layer} {position} (key 0x{key_num:02x}): '{mapping}'"),
+            action: true,
+            packets,
+        }
+    }
+
+    /// Returns packets that don't program a key, eg. the end of programming
+    ///
+    /// #Arguments
+    /// `label` - what the packets do
+    /// `packets` - packets to send
+    ///
+    pub fn step(label: impl Into<String>, packets: Vec<Vec<u8>>) -> Self {
+        Self {
+            label: label.into(),
+            action: false,
+            packets,
+        }
+    }
+}
+
+impl Display for PacketGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)?;
+        for packet in &self.packets {
+            write!(f, "\n  {:02x}", packet.iter().format(" "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Returns the packets that program the configuration on a macropad with the product
+/// id, without a connected device
+///
+/// #Arguments
+/// `pid` - product id of the macropad
+/// `macropad` - configuration to be programmed
+///
+pub fn build_packets(pid: u16, macropad: &Macropad) -> Result<Vec<PacketGroup>> {
+    match pid {
+        0x8840 | 0x8842 | 0x8850 => {
+            k884x::Keyboard884x::new(None, 0, 0, pid)?.build_packets(macropad)
+        }
+        0x8890 => k8890::Keyboard8890::new(None, 0)?.build_packets(macropad),
+        _ => Err(anyhow!("unsupported product id 0x{pid:04x}")),
+    }
+}
+
 pub trait Keyboard: Messages + Configuration {
     /// Programs the macropad based on the specified `Macropad`
     ///
     /// #Arguments
     /// `macropad` - configuration to be programmed
+    /// `progress` - optional callback invoked after each key or knob action
     ///
-    fn program(&mut self, macropad: &Macropad) -> Result<()>;
+    fn program(&mut self, macropad: &Macropad, progress: Progress) -> Result<()>;
 
     /// Programs the LEDs on the macropad
     ///
@@ -117,8 +240,45 @@ pub trait Keyboard: Messages + Configuration {
     /// `mode` - preset mode of the LED
     /// `layer` - layer to program
     /// `color` - the color to use for the mode
+    /// `brightness` - brightness in percent, ignored by product ids that can't dim the LEDs
+    ///
+    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor, brightness: u8) -> Result<()>;
+
+    /// Reads the identity of the macropad (USB descriptors and the number of keys
+    /// and encoders it reports). An `Unsupported` error is returned if the macropad
+    /// can't be queried
+    ///
+    fn read_device_info(&mut self) -> Result<DeviceInfo>;
+
+    /// Returns the key matrix of the macropad as (rows, cols, knobs, layers). Product
+    /// ids that only come in one size return the known values, others are asked for
+    /// their number of keys and knobs
+    ///
+    fn query_layout(&mut self) -> Result<(u8, u8, u8, u8)>;
+
+    /// Sends the packets returned by `build_packets` to the macropad
+    ///
+    /// #Arguments
+    /// `groups` - packets to send
+    /// `progress` - optional callback invoked after each key or knob action
     ///
-    fn set_led(&mut self, mode: u8, layer: u8, color: LedColor) -> Result<()>;
+    fn send_packets(&mut self, groups: &[PacketGroup], mut progress: Progress) -> Result<()> {
+        let total = groups.iter().filter(|g| g.action).count();
+        let mut done = 0;
+        for group in groups {
+            debug!("{}", group.label);
+            for packet in &group.packets {
+                self.send(packet)?;
+            }
+            if group.action {
+                done += 1;
+                if let Some(cb) = progress.as_deref_mut() {
+                    cb(done, total);
+                }
+            }
+        }
+        Ok(())
+    }
 
     /// Returns the handle of the device
     ///
@@ -184,9 +344,559 @@ pub trait Keyboard: Messages + Configuration {
     }
 }
 
+/// Identity of a connected macropad
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceInfo {
+    /// product id
+    pub pid: u16,
+    /// device release number from the USB descriptor (firmware version)
+    pub version: String,
+    pub manufacturer: Option<String>,
+    pub product: Option<String>,
+    pub serial: Option<String>,
+    /// number of keys reported by the macropad
+    pub num_keys: Option<u8>,
+    /// number of rotary encoders reported by the macropad
+    pub num_encoders: Option<u8>,
+}
+
+impl DeviceInfo {
+    /// Reads the identity strings and version from the USB descriptors of the device
+    ///
+    /// #Arguments
+    /// `handle` - handle of the opened device
+    /// `pid` - product id of the device
+    ///
+    pub fn from_usb(handle: &DeviceHandle<Context>, pid: u16) -> Result<Self> {
+        let desc = handle.device().device_descriptor()?;
+        let version = desc.device_version();
+        Ok(Self {
+            pid,
+            version: format!(
+                "{}.{}.{}",
+                version.major(),
+                version.minor(),
+                version.sub_minor()
+            ),
+            manufacturer: handle.read_manufacturer_string_ascii(&desc).ok(),
+            product: handle.read_product_string_ascii(&desc).ok(),
+            serial: handle.read_serial_number_string_ascii(&desc).ok(),
+            num_keys: None,
+            num_encoders: None,
+        })
+    }
+}
+
+/// Error for requests the connected macropad doesn't support
+#[derive(Debug)]
+pub struct Unsupported(pub String);
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unsupported: {}", self.0)
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Common reasons the macropad can't be opened, displayed with a hint how to fix them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsbFailure {
+    NotFound,
+    Permission,
+    InUse,
+}
+
+impl UsbFailure {
+    /// Classifies a libusb error, `None` for errors without a known fix
+    ///
+    /// #Arguments
+    /// `err` - error returned by libusb
+    ///
+    pub fn from_rusb(err: &rusb::Error) -> Option<Self> {
+        match err {
+            rusb::Error::NoDevice | rusb::Error::NotFound => Some(Self::NotFound),
+            rusb::Error::Access => Some(Self::Permission),
+            rusb::Error::Busy => Some(Self::InUse),
+            _ => None,
+        }
+    }
+
+    /// Returns the failure attached to an error or to any error it wraps
+    ///
+    /// #Arguments
+    /// `err` - error to inspect
+    ///
+    pub fn find(err: &anyhow::Error) -> Option<Self> {
+        err.downcast_ref::<Self>().copied().or_else(|| {
+            err.chain()
+                .find_map(|e| e.downcast_ref::<rusb::Error>())
+                .and_then(Self::from_rusb)
+        })
+    }
+
+    /// Whether trying again a moment later might succeed
+    ///
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::NotFound | Self::InUse)
+    }
+}
+
+impl Display for UsbFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotFound => write!(
+                f,
+                "macropad not found - check that it is plugged in, or try another cable or port"
+            ),
+            Self::Permission if cfg!(target_os = "linux") => write!(
+                f,
+                "no permission to access the macropad - copy 80-macropad.rules to /etc/udev/rules.d, \
+                 run 'sudo udevadm trigger' and replug the macropad"
+            ),
+            Self::Permission if cfg!(windows) => write!(
+                f,
+                "no permission to access the macropad - run as Administrator"
+            ),
+            Self::Permission => write!(
+                f,
+                "no permission to access the macropad - run with elevated privileges"
+            ),
+            Self::InUse => write!(
+                f,
+                "macropad is in use by another program - close it and try again"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UsbFailure {}
+
+/// Expands a mapping into the sequence of key chords that gets programmed. Chords are
+/// separated by commas; a `text:` token takes the rest of the mapping literally (commas
+/// included) and is converted into one chord per character (eg. ctrl-a,text:Hi! becomes
+/// ctrl-a,shift-h,i,shift-1). `hold:` mappings become repeated presses of the chord.
+/// Key names are matched in any case and returned in lowercase, and key aliases (eg.
+/// volup or esc) are replaced by their canonical names
+///
+/// #Arguments
+/// `mapping` - the mapping of a button
+///
+pub fn expand_mapping(mapping: &str) -> Result<String> {
+    if let Some(chord) = strip_prefix_ignore_case(mapping, consts::HOLD_PREFIX) {
+        return expand_hold(&canonical_chord(chord));
+    }
+    let mut chords = Vec::new();
+    let mut rest = mapping;
+    loop {
+        if let Some(text) = strip_prefix_ignore_case(rest, consts::TEXT_PREFIX) {
+            chords.push(expand_text(text)?);
+            break;
+        }
+        match rest.split_once(',') {
+            Some((token, tail)) => {
+                chords.push(canonical_chord(token));
+                rest = tail;
+            }
+            None => {
+                chords.push(canonical_chord(rest));
+                break;
+            }
+        }
+    }
+    Ok(chords.join(","))
+}
+
+/// Alternative key names and the canonical `WellKnownCode`/`MediaCode` names they stand for
+pub const KEY_ALIASES: &[(&str, &str)] = &[
+    ("esc", "escape"),
+    ("return", "enter"),
+    ("bksp", "backspace"),
+    ("del", "delete"),
+    ("ins", "insert"),
+    ("pgup", "pageup"),
+    ("pgdn", "pagedown"),
+    ("caps", "capslock"),
+    ("prtsc", "printscreen"),
+    ("app", "application"),
+    ("menu", "application"),
+    ("volup", "volumeup"),
+    ("voldown", "volumedown"),
+    ("brightnessup", "screenbrightnessup"),
+    ("brightnessdown", "screenbrightnessdown"),
+    ("calc", "calculator"),
+    ("lock", "screenlock"),
+];
+
+/// Returns the canonical name of a key, resolving aliases case-insensitively. Names
+/// without an alias are returned unchanged
+///
+/// #Arguments
+/// `key` - key name as written in the mapping
+///
+pub fn canonical_key(key: &str) -> &str {
+    KEY_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(key))
+        .map_or(key, |(_, name)| name)
+}
+
+/// Returns the rest of a token after a prefix like text: or hold:, matching the prefix
+/// in any case
+///
+/// #Arguments
+/// `token` - token of the mapping
+/// `prefix` - lowercase prefix to strip
+///
+pub fn strip_prefix_ignore_case<'a>(token: &'a str, prefix: &str) -> Option<&'a str> {
+    token
+        .get(..prefix.len())
+        .filter(|p| p.eq_ignore_ascii_case(prefix))
+        .map(|_| &token[prefix.len()..])
+}
+
+/// Lowercases the keys of a chord (eg. CTRL-Esc) and replaces the aliases with
+/// canonical key names, so every casing of a key is handled the same way
+///
+/// #Arguments
+/// `chord` - keys of the chord separated by dashes
+///
+fn canonical_chord(chord: &str) -> String {
+    let chord = chord.to_ascii_lowercase();
+    if chord.starts_with(consts::MOVE_PREFIX) {
+        return chord;
+    }
+    chord.split('-').map(canonical_key).join("-")
+}
+
+/// Returns the key sequence of a mapping, the tokenizer shared by validation and
+/// programming
+///
+/// #Arguments
+/// `mapping` - the mapping of a button
+///
+pub fn key_sequence(mapping: &str) -> Result<Vec<String>> {
+    Ok(split_sequence(&expand_mapping(mapping)?))
+}
+
+/// Converts literal text into one chord per character, as typed on the host layout
+///
+/// #Arguments
+/// `text` - the text after the text prefix
+///
+fn expand_text(text: &str) -> Result<String> {
+    ensure!(!text.is_empty(), "text mapping is empty");
+    let mut chords = Vec::new();
+    for c in text.chars() {
+        let layout = layout::host_layout();
+        let chord = layout.char_to_chord(c).ok_or_else(|| {
+            anyhow!("no key code for character '{c}' in text with the {layout} layout")
+        })?;
+        chords.push(chord);
+    }
+    Ok(chords.join(","))
+}
+
+/// Expands the chord of a `hold:` mapping into `consts::HOLD_REPEATS` presses, the
+/// closest the macropads get to keeping a key pressed. The chord must contain a known
+/// key besides any modifiers
+///
+/// #Arguments
+/// `chord` - the chord after the hold prefix (eg. space or shift-w)
+///
+fn expand_hold(chord: &str) -> Result<String> {
+    ensure!(!chord.is_empty(), "hold mapping is empty");
+    ensure!(
+        !chord.contains(','),
+        "hold only supports a single chord - {chord}"
+    );
+    let mut has_key = false;
+    for key in chord.split('-') {
+        if Modifier::from_str(key).is_ok() {
+            continue;
+        }
+        ensure!(
+            WellKnownCode::from_str(key).is_ok()
+                || MediaCode::from_str(key).is_ok()
+                || MouseButton::from_str(key).is_ok()
+                || MouseAction::from_str(key).is_ok(),
+            "unknown key in hold - {key}"
+        );
+        has_key = true;
+    }
+    ensure!(has_key, "hold needs a key besides modifiers - {chord}");
+    Ok([chord; consts::HOLD_REPEATS].join(","))
+}
+
+/// Splits a mapping into its sequence of key chords. Chords are separated by commas,
+/// except for the comma inside a relative mouse movement (eg. move:10,-5)
+///
+/// #Arguments
+/// `mapping` - the (expanded) mapping of a button
+///
+pub fn split_sequence(mapping: &str) -> Vec<String> {
+    let mut chords: Vec<String> = Vec::new();
+    for token in mapping.split(',') {
+        match chords.last_mut() {
+            Some(last) if last.starts_with(consts::MOVE_PREFIX) && !last.contains(',') => {
+                last.push(',');
+                last.push_str(token);
+            }
+            _ => chords.push(token.to_string()),
+        }
+    }
+    chords
+}
+
+/// Parses a pause like wait:200. Returns `None` when the token is not a pause and an
+/// error when the value is malformed or above `consts::MAX_DELAY`
+///
+/// #Arguments
+/// `token` - the token to parse
+///
+pub fn parse_wait(token: &str) -> Result<Option<u16>> {
+    let Some(ms) = token.strip_prefix(consts::WAIT_PREFIX) else {
+        return Ok(None);
+    };
+    match ms.trim().parse::<u16>() {
+        Ok(ms) if ms <= consts::MAX_DELAY => Ok(Some(ms)),
+        _ => Err(anyhow!(
+            "invalid pause '{token}' - expected wait:ms with at most {} ms",
+            consts::MAX_DELAY
+        )),
+    }
+}
+
+/// Separates the `wait:` pauses from the key presses of a sequence and returns the
+/// presses along with the delay to program. The firmware pauses the same time between
+/// every key press of a button, so pauses must separate every press with the same value,
+/// which also has to agree with a non-zero `delay` of the button
+///
+/// #Arguments
+/// `keys` - key sequence including pauses
+/// `delay` - delay of the button
+///
+pub fn split_waits(keys: &[String], delay: u16) -> Result<(Vec<String>, u16)> {
+    let mut presses = Vec::new();
+    let mut waits = Vec::new();
+    let mut after_press = false;
+    for key in keys {
+        if let Some(ms) = parse_wait(key)? {
+            ensure!(after_press, "{key} must be between two key presses");
+            waits.push(ms);
+            after_press = false;
+        } else {
+            presses.push(key.clone());
+            after_press = true;
+        }
+    }
+    let Some(&wait) = waits.first() else {
+        return Ok((presses, delay));
+    };
+    ensure!(after_press, "a sequence can't end with a pause");
+    ensure!(
+        waits.len() + 1 == presses.len() && waits.iter().all(|w| *w == wait),
+        "the macropad pauses the same time between all key presses, put the same wait: between every press"
+    );
+    ensure!(
+        delay == 0 || delay == wait,
+        "delay {delay} conflicts with {}{wait}",
+        consts::WAIT_PREFIX
+    );
+    Ok((presses, wait))
+}
+
+/// Splits the name of an enum variant into words (eg. PageUp becomes Page Up, NonUSHash
+/// becomes Non US Hash)
+///
+/// #Arguments
+/// `name` - name in camel case
+///
+fn split_camel_case(name: &str) -> String {
+    let chars: Vec<char> = name.chars().collect();
+    let mut words = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        let prev = i.checked_sub(1).map(|p| chars[p]);
+        let next = chars.get(i + 1);
+        let boundary = match prev {
+            Some(p) if c.is_ascii_uppercase() => {
+                p.is_ascii_lowercase()
+                    || (p.is_ascii_uppercase() && next.is_some_and(|n| n.is_ascii_lowercase()))
+            }
+            Some(p) => c.is_ascii_digit() && p.is_ascii_lowercase(),
+            None => false,
+        };
+        if boundary {
+            words.push(' ');
+        }
+        words.push(*c);
+    }
+    words
+}
+
+/// Returns the name of a key in plain words (eg. escape becomes Escape and rctrl becomes
+/// Right Ctrl). Unknown keys are returned as they are
+///
+/// #Arguments
+/// `key` - key name as written in the mapping
+///
+fn describe_key(key: &str) -> String {
+    let key = canonical_key(key);
+    if let Ok(m) = Modifier::from_str(key) {
+        split_camel_case(&format!("{m:?}"))
+    } else if let Ok(w) = WellKnownCode::from_str(key) {
+        match w.to_string() {
+            name if name.chars().count() == 1 => name.to_uppercase(),
+            _ => split_camel_case(&format!("{w:?}")).replace("Num Pad", "Numpad"),
+        }
+    } else if let Ok(m) = MediaCode::from_str(key) {
+        split_camel_case(&format!("{m:?}"))
+    } else if let Ok(b) = MouseButton::from_str(key) {
+        format!("{b:?} Click")
+    } else if let Ok(a) = MouseAction::from_str(key) {
+        match a {
+            MouseAction::WheelUp => "Wheel Up".to_string(),
+            MouseAction::WheelDown => "Wheel Down".to_string(),
+            _ => key.to_string(),
+        }
+    } else {
+        key.to_string()
+    }
+}
+
+/// Describes a chord in plain words, eg. ctrl-shift-esc becomes Ctrl + Shift + Escape
+///
+/// #Arguments
+/// `chord` - keys of the chord separated by dashes
+///
+fn describe_chord(chord: &str) -> String {
+    if let Some(ms) = chord.strip_prefix(consts::WAIT_PREFIX) {
+        return format!("Wait {} ms", ms.trim());
+    }
+    if let Some(offset) = chord.strip_prefix(consts::MOVE_PREFIX) {
+        return match offset.split_once(',') {
+            Some((dx, dy)) => format!("Move the mouse by {}, {}", dx.trim(), dy.trim()),
+            None => chord.to_string(),
+        };
+    }
+    chord.split('-').map(describe_key).join(" + ")
+}
+
+/// Describes a mapping in plain words for people who don't read key names, eg.
+/// ctrl-shift-esc becomes Ctrl + Shift + Escape. Sequences are listed one step per
+/// line and text is quoted. Empty mappings are "unassigned"
+///
+/// #Arguments
+/// `mapping` - the mapping of a button
+///
+pub fn describe_mapping(mapping: &str) -> String {
+    if mapping.trim().is_empty() {
+        return "unassigned".to_string();
+    }
+    if let Some(chord) = strip_prefix_ignore_case(mapping, consts::HOLD_PREFIX) {
+        return format!(
+            "Hold {} (sent as {} presses)",
+            describe_chord(chord),
+            consts::HOLD_REPEATS
+        );
+    }
+    let mut steps = Vec::new();
+    let mut rest = mapping;
+    loop {
+        if let Some(text) = strip_prefix_ignore_case(rest, consts::TEXT_PREFIX) {
+            steps.push(format!("Type \"{text}\""));
+            break;
+        }
+        match rest.split_once(',') {
+            // the comma of a mouse movement belongs to its token
+            Some((token, tail))
+                if token.starts_with(consts::MOVE_PREFIX) && !token.contains(',') =>
+            {
+                let (dy, tail) = tail
+                    .split_once(',')
+                    .map_or((tail, None), |(d, t)| (d, Some(t)));
+                steps.push(describe_chord(&format!("{token},{dy}")));
+                match tail {
+                    Some(t) => rest = t,
+                    None => break,
+                }
+            }
+            Some((token, tail)) => {
+                steps.push(describe_chord(token));
+                rest = tail;
+            }
+            None => {
+                steps.push(describe_chord(rest));
+                break;
+            }
+        }
+    }
+    if steps.len() == 1 {
+        return steps.remove(0);
+    }
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| format!("{}. {step}", i + 1))
+        .join("\n")
+}
+
+/// Returns the key chord that types the character `c` on a US keyboard layout
+///
+/// #Arguments
+/// `c` - character to be typed
+///
+pub fn us_char_to_chord(c: char) -> Option<String> {
+    if c.is_ascii_lowercase() || c.is_ascii_digit() {
+        return Some(c.to_string());
+    }
+    if c.is_ascii_uppercase() {
+        return Some(format!("shift-{}", c.to_ascii_lowercase()));
+    }
+    let key = match c {
+        ' ' => "space",
+        '\n' => "enter",
+        '\t' => "tab",
+        '-' => "minus",
+        '=' => "equal",
+        '[' => "leftbracket",
+        ']' => "rightbracket",
+        '\\' => "backslash",
+        ';' => "semicolon",
+        '\'' => "quote",
+        '`' => "grave",
+        ',' => "comma",
+        '.' => "dot",
+        '/' => "slash",
+        '!' => "shift-1",
+        '@' => "shift-2",
+        '#' => "shift-3",
+        '$' => "shift-4",
+        '%' => "shift-5",
+        '^' => "shift-6",
+        '&' => "shift-7",
+        '*' => "shift-8",
+        '(' => "shift-9",
+        ')' => "shift-0",
+        '_' => "shift-minus",
+        '+' => "shift-equal",
+        '{' => "shift-leftbracket",
+        '}' => "shift-rightbracket",
+        '|' => "shift-backslash",
+        ':' => "shift-semicolon",
+        '"' => "shift-quote",
+        '~' => "shift-grave",
+        '<' => "shift-comma",
+        '>' => "shift-dot",
+        '?' => "shift-slash",
+        _ => return None,
+    };
+    Some(key.to_string())
+}
+
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Default, ToPrimitive, Clone, Copy, Display, clap::ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Default, ToPrimitive, Clone, Copy, Display, EnumIter, clap::ValueEnum, PartialEq, Eq, Serialize, Deserialize)]
 pub enum LedColor {
     Red = 0x10,
     Orange = 0x20,
@@ -198,15 +908,32 @@ pub enum LedColor {
     Purple = 0x70,
 }
 
-#[allow(unused)]
-#[derive(Debug, Clone, Copy, Display)]
+impl LedColor {
+    /// Returns the approximate RGB value of the color as shown by the LEDs
+    ///
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match self {
+            LedColor::Red => (255, 0, 0),
+            LedColor::Orange => (255, 128, 0),
+            LedColor::Yellow => (255, 255, 0),
+            LedColor::Green => (0, 255, 0),
+            LedColor::Cyan => (0, 255, 255),
+            LedColor::Blue => (0, 0, 255),
+            LedColor::Purple => (160, 0, 255),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Display, clap::ValueEnum, PartialEq, Eq)]
 #[repr(u8)]
 pub enum KnobAction {
     #[strum(serialize = "ccw")]
+    #[value(name = "ccw")]
     RotateCCW,
     #[strum(serialize = "press")]
     Press,
     #[strum(serialize = "cw")]
+    #[value(name = "cw")]
     RotateCW,
 }
 
@@ -258,9 +985,15 @@ pub enum MediaCode {
     Mute = 0xe2,
     VolumeUp = 0xe9,
     VolumeDown = 0xea,
+    #[strum(to_string = "syspower")]
+    SystemPower = 0x30,
+    Sleep = 0x32,
     Favorites = 0x182,
+    Mail = 0x18a,
     Calculator = 0x192,
+    MyComputer = 0x194,
     ScreenLock = 0x19e,
+    Search = 0x221,
     ScreenBrightnessUp = 0x6f,
     ScreenBrightnessDown = 0x70,
     WebPageHome = 0x0223,
@@ -269,7 +1002,17 @@ pub enum MediaCode {
 }
 
 #[derive(
-    Debug, ToPrimitive, FromPrimitive, Clone, Copy, PartialEq, Eq, EnumString, EnumIter, Display,
+    Debug,
+    ToPrimitive,
+    FromPrimitive,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    EnumString,
+    EnumIter,
+    EnumMessage,
+    Display,
 )]
 #[repr(u8)]
 #[strum(ascii_case_insensitive)]
@@ -365,25 +1108,46 @@ pub enum WellKnownCode {
     Down,
     Up,
     NumLock,
+    #[strum(to_string = "numpadslash", serialize = "numslash")]
     NumPadSlash,
+    #[strum(
+        to_string = "numpadasterisk",
+        serialize = "numasterisk",
+        serialize = "nummultiply"
+    )]
     NumPadAsterisk,
+    #[strum(to_string = "numpadminus", serialize = "numminus")]
     NumPadMinus,
+    #[strum(to_string = "numpadplus", serialize = "numplus")]
     NumPadPlus,
+    #[strum(to_string = "numpadenter", serialize = "numenter")]
     NumPadEnter,
+    #[strum(to_string = "numpad1", serialize = "num1")]
     NumPad1,
+    #[strum(to_string = "numpad2", serialize = "num2")]
     NumPad2,
+    #[strum(to_string = "numpad3", serialize = "num3")]
     NumPad3,
+    #[strum(to_string = "numpad4", serialize = "num4")]
     NumPad4,
+    #[strum(to_string = "numpad5", serialize = "num5")]
     NumPad5,
+    #[strum(to_string = "numpad6", serialize = "num6")]
     NumPad6,
+    #[strum(to_string = "numpad7", serialize = "num7")]
     NumPad7,
+    #[strum(to_string = "numpad8", serialize = "num8")]
     NumPad8,
+    #[strum(to_string = "numpad9", serialize = "num9")]
     NumPad9,
+    #[strum(to_string = "numpad0", serialize = "num0")]
     NumPad0,
+    #[strum(to_string = "numpaddot", serialize = "numdot")]
     NumPadDot,
     NonUSBackslash,
     Application,
     Power,
+    #[strum(to_string = "numpadequal", serialize = "numequal")]
     NumPadEqual,
     F13,
     F14,
@@ -410,6 +1174,7 @@ pub enum MouseModifier {
 }
 
 #[derive(ToPrimitive, EnumString, Debug, EnumSetType, EnumIter, Display)]
+#[strum(ascii_case_insensitive)]
 pub enum MouseButton {
     #[strum(serialize = "click")]
     Left,
@@ -427,6 +1192,64 @@ pub enum MouseAction {
     Click(MouseButtons),
     WheelUp,
     WheelDown,
+    #[strum(disabled)]
+    Move(i8, i8),
+}
+
+impl MouseAction {
+    /// Parses a relative mouse movement like move:10,-5. Returns `None` when the
+    /// token is not a mouse movement and an error when the coordinates are malformed
+    ///
+    /// #Arguments
+    /// `token` - the token to parse
+    ///
+    pub fn parse_move(token: &str) -> Result<Option<MouseAction>> {
+        let Some(coords) = token.strip_prefix(consts::MOVE_PREFIX) else {
+            return Ok(None);
+        };
+        let parse = |v: Option<&str>| -> Option<i8> {
+            let v = v?.trim().parse::<i16>().ok()?;
+            if v.abs() > consts::MAX_MOUSE_MOVE {
+                return None;
+            }
+            v.try_into().ok()
+        };
+        let mut values = coords.split(',');
+        let (dx, dy) = (parse(values.next()), parse(values.next()));
+        match (dx, dy, values.next()) {
+            (Some(dx), Some(dy), None) => Ok(Some(MouseAction::Move(dx, dy))),
+            _ => Err(anyhow!(
+                "invalid mouse movement '{token}' - expected move:dx,dy with values between -{0} and {0}",
+                consts::MAX_MOUSE_MOVE
+            )),
+        }
+    }
+
+    /// Checks that a mouse movement is only combined with other mouse actions. The
+    /// movement is sent in the bytes of the first key press, so keys next to it would
+    /// be lost
+    ///
+    /// #Arguments
+    /// `keys` - key presses of a mapping, without pauses
+    ///
+    pub fn check_move_steps(keys: &[String]) -> Result<()> {
+        let is_move = |k: &String| k.starts_with(consts::MOVE_PREFIX);
+        let is_mouse = |k: &String| {
+            is_move(k)
+                || k.split('-')
+                    .all(|b| MouseButton::from_str(b).is_ok() || MouseAction::from_str(b).is_ok())
+        };
+        if let (Some(_), Some(key)) = (
+            keys.iter().find(|k| is_move(k)),
+            keys.iter().find(|k| !is_mouse(k)),
+        ) {
+            return Err(anyhow!(
+                "{} can only be combined with mouse actions, not with {key}",
+                consts::MOVE_PREFIX
+            ));
+        }
+        Ok(())
+    }
 }
 
 impl Display for MouseAction {
@@ -441,6 +1264,9 @@ impl Display for MouseAction {
             MouseAction::WheelDown => {
                 write!(f, "wheeldown")?;
             }
+            MouseAction::Move(dx, dy) => {
+                write!(f, "{}{dx},{dy}", consts::MOVE_PREFIX)?;
+            }
         }
         Ok(())
     }
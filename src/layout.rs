@@ -0,0 +1,227 @@
+use crate::keyboard;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU8, Ordering};
+use strum_macros::{Display, EnumIter};
+
+/// Keyboard layout of the computer the macropad is plugged into. Mappings always name
+/// the keys by their position on a US (QWERTY) keyboard; the host layout only changes
+/// how they are shown and which keys `text:` mappings press
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Display,
+    EnumIter,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+)]
+#[repr(u8)]
+pub enum HostLayout {
+    #[default]
+    Qwerty,
+    /// French
+    Azerty,
+    /// German
+    Qwertz,
+}
+
+/// Keys whose labels differ from QWERTY on a French AZERTY keyboard, with the characters
+/// typed alone, with shift and with AltGr. Keys without characters are dead keys
+const AZERTY: &[(&str, &str)] = &[
+    ("q", "aA"),
+    ("w", "zZ"),
+    ("a", "qQ"),
+    ("z", "wW"),
+    ("semicolon", "mM"),
+    ("m", ",?"),
+    ("comma", ";."),
+    ("dot", ":/"),
+    ("slash", "!§"),
+    ("1", "&1"),
+    ("2", "é2~"),
+    ("3", "\"3#"),
+    ("4", "'4{"),
+    ("5", "(5["),
+    ("6", "-6|"),
+    ("7", "è7`"),
+    ("8", "_8\\"),
+    ("9", "ç9^"),
+    ("0", "à0@"),
+    ("minus", ")°]"),
+    ("equal", "=+}"),
+    ("leftbracket", ""),
+    ("rightbracket", "$£¤"),
+    ("quote", "ù%"),
+    ("backslash", "*µ"),
+    ("grave", "²"),
+    ("nonusbackslash", "<>"),
+];
+
+/// Keys whose labels differ from QWERTY on a German QWERTZ keyboard, with the characters
+/// typed alone, with shift and with AltGr. Keys without characters are dead keys
+const QWERTZ: &[(&str, &str)] = &[
+    ("y", "zZ"),
+    ("z", "yY"),
+    ("q", "qQ@"),
+    ("e", "eE€"),
+    ("2", "2\"²"),
+    ("3", "3§³"),
+    ("6", "6&"),
+    ("7", "7/{"),
+    ("8", "8(["),
+    ("9", "9)]"),
+    ("0", "0=}"),
+    ("minus", "ß?\\"),
+    ("equal", ""),
+    ("leftbracket", "üÜ"),
+    ("rightbracket", "+*~"),
+    ("semicolon", "öÖ"),
+    ("quote", "äÄ"),
+    ("backslash", "#'"),
+    ("grave", ""),
+    ("comma", ",;"),
+    ("dot", ".:"),
+    ("slash", "-_"),
+    ("nonusbackslash", "<>|"),
+];
+
+static HOST_LAYOUT: AtomicU8 = AtomicU8::new(HostLayout::Qwerty as u8);
+
+/// Returns the host layout used to expand `text:` mappings
+///
+pub fn host_layout() -> HostLayout {
+    match HOST_LAYOUT.load(Ordering::Relaxed) {
+        1 => HostLayout::Azerty,
+        2 => HostLayout::Qwertz,
+        _ => HostLayout::Qwerty,
+    }
+}
+
+/// Sets the host layout used to expand `text:` mappings
+///
+/// #Arguments
+/// `layout` - layout of the computer
+///
+pub fn set_host_layout(layout: HostLayout) {
+    HOST_LAYOUT.store(layout as u8, Ordering::Relaxed);
+}
+
+impl HostLayout {
+    /// Returns the keys whose labels differ from QWERTY
+    fn keys(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            HostLayout::Qwerty => &[],
+            HostLayout::Azerty => AZERTY,
+            HostLayout::Qwertz => QWERTZ,
+        }
+    }
+
+    /// Returns the key chord that types `c` on this layout
+    ///
+    /// #Arguments
+    /// `c` - character to be typed
+    ///
+    pub fn char_to_chord(self, c: char) -> Option<String> {
+        for (key, chars) in self.keys() {
+            for (modifier, typed) in ["", "shift-", "ralt-"].iter().zip(chars.chars()) {
+                if typed == c {
+                    return Some(format!("{modifier}{key}"));
+                }
+            }
+        }
+        let chord = keyboard::us_char_to_chord(c)?;
+        let key = chord.rsplit('-').next().unwrap_or_default();
+        // the QWERTY key types something else on this layout
+        if self.keys().iter().any(|(k, _)| *k == key) {
+            return None;
+        }
+        Some(chord)
+    }
+
+    /// Returns the label of a key on this layout when it differs from QWERTY, eg. the q
+    /// key is labelled a on AZERTY
+    ///
+    /// #Arguments
+    /// `key` - canonical key name
+    ///
+    pub fn key_label(self, key: &str) -> Option<char> {
+        let (_, chars) = self.keys().iter().find(|(k, _)| *k == key)?;
+        let label = chars.chars().next()?;
+        (keyboard::us_char_to_chord(label).as_deref() != Some(key)).then_some(label)
+    }
+
+    /// Returns the mapping with the keys named after their labels on this layout, for
+    /// display only. `text:` mappings already hold the typed characters and are returned
+    /// unchanged
+    ///
+    /// #Arguments
+    /// `mapping` - mapping of a button
+    ///
+    pub fn display_mapping(self, mapping: &str) -> String {
+        if self == HostLayout::Qwerty
+            || keyboard::strip_prefix_ignore_case(mapping, crate::consts::TEXT_PREFIX).is_some()
+        {
+            return mapping.to_string();
+        }
+        mapping
+            .split(',')
+            .map(|chord| {
+                chord
+                    .split('-')
+                    .map(|key| match self.key_label(&key.to_ascii_lowercase()) {
+                        Some(label) => label.to_string(),
+                        None => key.to_string(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("-")
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_chords() {
+        let chord = |layout: HostLayout, c| layout.char_to_chord(c);
+        assert_eq!(chord(HostLayout::Qwerty, 'a').as_deref(), Some("a"));
+        assert_eq!(chord(HostLayout::Qwerty, '@').as_deref(), Some("shift-2"));
+        assert_eq!(chord(HostLayout::Azerty, 'a').as_deref(), Some("q"));
+        assert_eq!(chord(HostLayout::Azerty, 'A').as_deref(), Some("shift-q"));
+        assert_eq!(chord(HostLayout::Azerty, 'm').as_deref(), Some("semicolon"));
+        assert_eq!(chord(HostLayout::Azerty, '1').as_deref(), Some("shift-1"));
+        assert_eq!(chord(HostLayout::Azerty, '@').as_deref(), Some("ralt-0"));
+        assert_eq!(chord(HostLayout::Azerty, 'é').as_deref(), Some("2"));
+        assert_eq!(chord(HostLayout::Azerty, 'b').as_deref(), Some("b"));
+        assert_eq!(chord(HostLayout::Azerty, ' ').as_deref(), Some("space"));
+        assert_eq!(chord(HostLayout::Qwertz, 'z').as_deref(), Some("y"));
+        assert_eq!(chord(HostLayout::Qwertz, 'Y').as_deref(), Some("shift-z"));
+        assert_eq!(chord(HostLayout::Qwertz, '@').as_deref(), Some("ralt-q"));
+        assert_eq!(chord(HostLayout::Qwertz, '-').as_deref(), Some("slash"));
+        assert_eq!(chord(HostLayout::Qwertz, 'ß').as_deref(), Some("minus"));
+        // the grave key is a dead key on QWERTZ
+        assert_eq!(chord(HostLayout::Qwertz, '`'), None);
+        assert_eq!(chord(HostLayout::Qwerty, 'é'), None);
+    }
+
+    #[test]
+    fn display_labels() {
+        assert_eq!(
+            HostLayout::Azerty.display_mapping("ctrl-q,a,semicolon"),
+            "ctrl-a,q,m"
+        );
+        assert_eq!(HostLayout::Azerty.display_mapping("text:qa"), "text:qa");
+        assert_eq!(HostLayout::Qwertz.display_mapping("ctrl-z,f1"), "ctrl-y,f1");
+        assert_eq!(HostLayout::Qwerty.display_mapping("ctrl-z"), "ctrl-z");
+        assert_eq!(HostLayout::Qwertz.key_label("q"), None);
+        assert_eq!(HostLayout::Qwertz.key_label("comma"), None);
+        assert_eq!(HostLayout::Azerty.key_label("1"), Some('&'));
+    }
+}
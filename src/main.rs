@@ -1,20 +1,25 @@
 mod config;
 mod consts;
 mod decoder;
+mod gui;
 mod keyboard;
+mod layout;
 mod mapping;
 mod options;
 mod parse;
-mod gui;
+mod profiles;
+mod simulate;
+mod templates;
 
 use crate::consts::PRODUCT_IDS;
 use crate::decoder::Decoder;
 use crate::keyboard::{
-    k884x, k8890, Keyboard, MediaCode, Modifier, MouseAction, MouseButton, WellKnownCode,
+    k884x, k8890, Keyboard, MediaCode, Modifier, MouseAction, MouseButton, UsbFailure,
+    WellKnownCode,
 };
-use crate::mapping::Macropad;
+use crate::mapping::{Macropad, Position};
 use crate::options::Options;
-use crate::options::{Command, LedCommand};
+use crate::options::{Command, DevelOptions, ExportFormat, LedCommand};
 
 use anyhow::{anyhow, ensure, Result};
 use indoc::indoc;
@@ -34,6 +39,9 @@ fn main() -> Result<()> {
     env_logger::init();
     let options = Options::parse();
     debug!("options: {:?}", options.devel_options);
+    if let Some(host_layout) = options.host_layout {
+        layout::set_host_layout(host_layout);
+    }
 
     match &options.command {
         Command::ShowKeys => {
@@ -45,7 +53,7 @@ fn main() -> Result<()> {
             println!();
             println!("Keys:");
             for c in WellKnownCode::iter() {
-                println!(" - {c}");
+                println!(" - {}", c.get_serializations().iter().join(" / "));
             }
 
             println!();
@@ -64,6 +72,32 @@ fn main() -> Result<()> {
             for b in MouseButton::iter() {
                 println!(" - {b}");
             }
+            println!(
+                " - {}<dx>,<dy> (relative movement, -{} to {})",
+                consts::MOVE_PREFIX,
+                consts::MAX_MOUSE_MOVE,
+                consts::MAX_MOUSE_MOVE
+            );
+
+            println!();
+            println!("Aliases:");
+            for (alias, name) in keyboard::KEY_ALIASES {
+                println!(" - {alias} -> {name}");
+            }
+
+            println!();
+            println!("Actions:");
+            println!(" - {}<text> (types the text)", consts::TEXT_PREFIX);
+            println!(
+                " - {}<ms> (pause between key presses, at most {} ms)",
+                consts::WAIT_PREFIX,
+                consts::MAX_DELAY
+            );
+            println!(
+                " - {}<key> (approximated with {} repeated presses)",
+                consts::HOLD_PREFIX,
+                consts::HOLD_REPEATS
+            );
         }
 
         Command::Validate {
@@ -71,13 +105,17 @@ fn main() -> Result<()> {
             product_id,
             device_connected,
         } => {
+            let macropad = Mapping::read(config_file).context("reading config file")?;
             if *device_connected {
                 debug!("validating with connected device");
-                if let Ok(device) = find_device(consts::VENDOR_ID, None) {
+                if let Ok(device) =
+                    find_device(consts::VENDOR_ID, None, options.devel_options.address)
+                {
                     // read the config for buttons/knobs and validate against file
                     if device.2 != 0x8890 {
                         // 0x8890 does not support reading configuration
-                        let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
+                        let mut keyboard =
+                            open_keyboard(&options.devel_options).context("opening keyboard")?;
                         let mut buf = vec![0; consts::READ_BUF_SIZE.into()];
 
                         // get the type of device
@@ -94,7 +132,6 @@ fn main() -> Result<()> {
                             device_info.num_keys, device_info.num_encoders
                         );
 
-                        let macropad = Mapping::read(config_file).context("reading config file")?;
                         if device_info.num_keys != macropad.device.rows * macropad.device.cols {
                             return Err(anyhow!(
                                 "Number of keys specified in config does not match device"
@@ -106,9 +143,8 @@ fn main() -> Result<()> {
                             ));
                         }
                     }
-                    Mapping::validate(config_file, Some(device.2))
+                    validate_config_file(&macropad, config_file, Some(device.2))
                         .context("validating configuration file with connected device")?;
-                    println!("config is valid 👌")
                 } else {
                     return Err(anyhow!(
                         "Unable to find connected device with vendor id: 0x{:02x}",
@@ -117,31 +153,67 @@ fn main() -> Result<()> {
                 }
             } else if let Some(pid) = product_id {
                 debug!("validating with supplied product id 0x{pid:02x}");
-                Mapping::validate(config_file, Some(*pid))
+                validate_config_file(&macropad, config_file, Some(*pid))
                     .context("validating configuration file against specified product id")?;
-                println!("config is valid 👌")
             } else {
                 // load and validate mapping
                 println!("validating general ron formatting - unable to do more granular checking; use -p option to check against device");
-                Mapping::validate(config_file, None)
+                validate_config_file(&macropad, config_file, None)
                     .context("generic validation of configuration file")?;
-                println!("config is valid 👌")
             }
         }
 
-        Command::Program { config_file } => {
-            let config = Mapping::read(config_file).context("reading config file")?;
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
-            keyboard.program(&config).context("programming macropad")?;
-            println!("successfully programmed device");
+        Command::Program {
+            config_file,
+            allow_empty,
+            dry_run,
+        } => {
+            if *dry_run {
+                dump_packets(&options, config_file, None)?;
+                println!("DRY RUN — nothing was written");
+            } else {
+                program_config_file(&options.devel_options, config_file, *allow_empty)?;
+                println!("successfully programmed device");
+            }
+        }
+
+        Command::BatchProgram { dir, allow_empty } => {
+            batch_program(&options.devel_options, dir, *allow_empty)?;
+        }
+
+        Command::Clear { yes } => {
+            clear_device(&options.devel_options, *yes)?;
+        }
+
+        Command::DumpPackets {
+            config_file,
+            product_id,
+        } => {
+            dump_packets(&options, config_file, *product_id)?;
+        }
+
+        Command::Watch {
+            config_file,
+            allow_empty,
+        } => {
+            watch_config_file(&options.devel_options, config_file, *allow_empty)?;
+        }
+
+        Command::Simulate {
+            config_file,
+            layer,
+            countdown,
+        } => {
+            simulate_config_file(config_file, *layer, *countdown)?;
         }
 
         Command::Led(LedCommand {
             index,
             layer,
             led_color,
+            brightness,
         }) => {
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
+            let mut keyboard = open_keyboard(&options.devel_options).context("opening keyboard")?;
 
             // color is not supported on 0x8890 so don't require one to be passed
             let color = if led_color.is_some() {
@@ -150,17 +222,153 @@ fn main() -> Result<()> {
                 LedColor::Red
             };
             keyboard
-                .set_led(*index, *layer, color)
+                .set_led(*index, *layer, color, *brightness)
                 .context("programming LED on macropad")?;
         }
 
-        Command::Read { layer } => {
+        Command::Read { layer, ron_style } => {
             debug!("dev options: {:?}", options.devel_options);
-            let mut keyboard = open_keyboard(&options).context("opening keyboard")?;
+            let mut keyboard = open_keyboard(&options.devel_options).context("opening keyboard")?;
             let macropad_config = keyboard
                 .read_macropad_config(layer)
                 .context("reading macropad configuration")?;
-            Mapping::print(macropad_config);
+            Mapping::print(macropad_config, *ron_style);
+        }
+
+        Command::Dump {
+            file,
+            force,
+            ron_style,
+        } => {
+            ensure!(
+                *force || !std::path::Path::new(file).exists(),
+                "{file} already exists, use --force to overwrite it"
+            );
+            let mut keyboard = open_keyboard(&options.devel_options).context("opening keyboard")?;
+            let macropad_config = keyboard
+                .read_macropad_config(&0)
+                .context("reading macropad configuration")?;
+            Mapping::save_styled(&macropad_config, file, *ron_style)
+                .context(format!("writing {file}"))?;
+            println!("saved device configuration to {file}");
+        }
+
+        Command::ImportVia {
+            via_file,
+            config_file,
+            force,
+            ron_style,
+        } => {
+            ensure!(
+                *force || !std::path::Path::new(config_file).exists(),
+                "{config_file} already exists, use --force to overwrite it"
+            );
+            let (config, warnings) =
+                Mapping::import_via(via_file).context("importing VIA keymap")?;
+            for w in &warnings {
+                println!("Warning - {w}");
+            }
+            Mapping::save_styled(&config, config_file, *ron_style)
+                .context(format!("writing {config_file}"))?;
+            println!("imported {via_file} into {config_file}");
+        }
+
+        Command::ImportCsv {
+            csv_file,
+            config_file,
+            rows,
+            cols,
+            knobs,
+            force,
+            ron_style,
+        } => {
+            ensure!(
+                *force || !std::path::Path::new(config_file).exists(),
+                "{config_file} already exists, use --force to overwrite it"
+            );
+            let config =
+                Mapping::import_csv(csv_file, *rows, *cols, *knobs).context("importing CSV")?;
+            Mapping::save_styled(&config, config_file, *ron_style)
+                .context(format!("writing {config_file}"))?;
+            println!("imported {csv_file} into {config_file}");
+        }
+
+        Command::Export {
+            config_file,
+            file,
+            format,
+        } => {
+            let config = Mapping::read(config_file).context("reading config file")?;
+            let diagram = match format {
+                ExportFormat::Text => Mapping::export_diagram(&config),
+                ExportFormat::Markdown => Mapping::export_markdown(&config),
+                ExportFormat::Qmk => Mapping::export_qmk(&config),
+                ExportFormat::Csv => Mapping::export_csv(&config),
+            };
+            std::fs::write(file, diagram).context(format!("writing diagram to {file}"))?;
+            println!("exported diagram to {file}");
+        }
+
+        Command::Set {
+            config_file,
+            layer,
+            pos,
+            knob,
+            part,
+            mapping,
+            delay,
+            product_id,
+            ron_style,
+        } => {
+            ensure!(
+                config_file != consts::STDIN_CONFIG,
+                "set needs a configuration file to write back to"
+            );
+            let position = match (pos, knob, part) {
+                (Some((row, col)), _, _) => Position::Key(*row, *col),
+                (None, Some(knob), Some(part)) => Position::Knob(*knob, *part),
+                _ => return Err(anyhow!("pass either --pos or --knob and --part")),
+            };
+            let mut config = Mapping::read(config_file).context("reading config file")?;
+            let btn = Mapping::button_mut(&mut config, *layer, position)?;
+            btn.mapping = mapping.clone();
+            btn.delay = *delay;
+            let btn = btn.clone();
+            Mapping::validate_aliased_button(&btn, &config.aliases, *product_id)
+                .context(format!("layer {layer} {position}"))?;
+            Mapping::save_styled(&config, config_file, *ron_style).context("saving config file")?;
+            println!("layer {layer} {position} set to '{mapping}'");
+        }
+
+        Command::Diff { a, b } => {
+            let config_a = Mapping::read(a).context(format!("reading {a}"))?;
+            let config_b = Mapping::read(b).context(format!("reading {b}"))?;
+            let diffs = Mapping::diff(&config_a, &config_b);
+            if diffs.is_empty() {
+                println!("configs are identical");
+            } else {
+                for d in &diffs {
+                    println!(" - {d}");
+                }
+                return Err(anyhow!("{} difference(s) between {a} and {b}", diffs.len()));
+            }
+        }
+
+        Command::Lint { config_file } => {
+            let config = Mapping::read(config_file).context("reading config file")?;
+            let findings = config.analyze();
+            if findings.is_empty() {
+                println!("no findings 👌");
+            } else {
+                for f in &findings {
+                    println!(" - {f}");
+                }
+                return Err(anyhow!("{} finding(s) in {config_file}", findings.len()));
+            }
+        }
+
+        Command::Completions { shell } => {
+            options::write_completions(*shell, &mut std::io::stdout());
         }
 
         Command::ShowGui => {
@@ -171,6 +379,358 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// Reads the configuration file, validates it against the connected device and
+/// programs the device with it. A configuration with fewer than
+/// `consts::MIN_MAPPED_ACTIONS` mappings is refused unless `allow_empty` is set
+///
+/// #Arguments
+/// `options` - options selecting the device
+/// `config_file` - configuration file to program
+/// `allow_empty` - program (almost) empty configurations
+///
+fn program_config_file(options: &DevelOptions, config_file: &str, allow_empty: bool) -> Result<()> {
+    let config = Mapping::read(config_file).context("reading config file")?;
+    let mapped = Mapping::count_mappings(&config);
+    ensure!(
+        allow_empty || mapped >= consts::MIN_MAPPED_ACTIONS,
+        "{config_file} maps only {mapped} key/knob action(s), programming it would wipe the layout on the device - use --allow-empty to program it anyway"
+    );
+    let (_, _, pid) = find_device(options.vendor_id, options.product_id, options.address)
+        .context("find USB device")?;
+    Mapping::validate(&config, Some(pid))
+        .context(format!("validating configuration file for 0x{pid:04x}"))?;
+    let config = Mapping::resolve_aliases(&config)?;
+    let mut keyboard = open_keyboard(options).context("opening keyboard")?;
+    keyboard
+        .program(&config, None)
+        .context("programming macropad")?;
+    Ok(())
+}
+
+/// Returns the configuration files (.ron or .json) of a directory, sorted by name
+///
+/// #Arguments
+/// `dir` - directory to look in
+///
+fn config_files(dir: &str) -> Result<Vec<String>> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).context(format!("reading directory {dir}"))? {
+        let path = entry?.path();
+        let config = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("ron") || e.eq_ignore_ascii_case("json"));
+        if config && path.is_file() {
+            files.push(path.display().to_string());
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Programs every configuration file of a directory. With a single macropad connected
+/// they are programmed one after the other, waiting for the operator to connect the
+/// next macropad in between. With several connected, each one gets the configuration
+/// at its position (by bus number and address). A summary is printed at the end and
+/// an error returned if any configuration failed
+///
+/// #Arguments
+/// `options` - options selecting the device
+/// `dir` - directory with the configuration files
+/// `allow_empty` - program (almost) empty configurations
+///
+fn batch_program(options: &DevelOptions, dir: &str, allow_empty: bool) -> Result<()> {
+    let files = config_files(dir)?;
+    ensure!(
+        !files.is_empty(),
+        "no configuration files (.ron or .json) in {dir}"
+    );
+    let mut devices = find_all_devices(options.vendor_id)?;
+    devices.sort();
+    ensure!(!devices.is_empty(), "no macropad connected");
+    ensure!(
+        devices.len() == 1 || devices.len() == files.len(),
+        "{} configuration(s) in {dir} but {} macropads are connected, connect one or one per configuration",
+        files.len(),
+        devices.len()
+    );
+    // report invalid configurations before the operator starts swapping macropads
+    let mut results = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let pid = devices[i.min(devices.len() - 1)].2;
+        let valid = Mapping::read(file)
+            .and_then(|config| Mapping::validate(&config, Some(pid)))
+            .context(format!("validating {file} for 0x{pid:04x}"));
+        if let Err(e) = &valid {
+            println!("invalid {file}: {e:#}");
+        }
+        results.push((file, valid));
+    }
+    let mut first = true;
+    for (i, (file, result)) in results.iter_mut().enumerate() {
+        if result.is_err() {
+            continue;
+        }
+        let device_options = if devices.len() > 1 {
+            let (bus, address, _) = devices[i];
+            println!("programming {file} on the macropad at {bus}:{address}");
+            DevelOptions {
+                address: Some((bus, address)),
+                ..options.clone()
+            }
+        } else {
+            if !first {
+                println!("connect the macropad for {file} and press enter");
+                std::io::stdin().read_line(&mut String::new())?;
+            }
+            println!("programming {file}");
+            options.clone()
+        };
+        first = false;
+        *result = program_config_file(&device_options, file, allow_empty);
+        match result {
+            Ok(_) => println!("programmed {file}"),
+            Err(e) => println!("failed {file}: {e:#}"),
+        }
+    }
+    println!("\nsummary:");
+    for (file, result) in &results {
+        match result {
+            Ok(_) => println!(" ✅ {file}"),
+            Err(e) => println!(" ❌ {file}: {e:#}"),
+        }
+    }
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    ensure!(
+        failed == 0,
+        "{failed} of {} configuration(s) failed",
+        results.len()
+    );
+    Ok(())
+}
+
+/// Programs an empty configuration of the size the device reports, unbinding every key
+/// and knob action on every layer
+///
+/// #Arguments
+/// `options` - options selecting the device
+/// `yes` - skip the confirmation
+///
+fn clear_device(options: &DevelOptions, yes: bool) -> Result<()> {
+    let mut keyboard = open_keyboard(options).context("opening keyboard")?;
+    let (rows, cols, knobs, layers) = keyboard
+        .query_layout()
+        .context("detecting the layout of the macropad")?;
+    println!("the macropad has {layers} layer(s) of {rows}x{cols} keys and {knobs} knob(s)");
+    if !yes {
+        println!("this unbinds every key and knob on the device, type yes to continue");
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        ensure!(answer.trim() == "yes", "nothing was cleared");
+    }
+    keyboard
+        .program(&Macropad::empty(rows, cols, knobs, layers), None)
+        .context("clearing macropad")?;
+    println!("successfully cleared device");
+    Ok(())
+}
+
+/// Prints the packets programming the configuration file would send, grouped by key
+/// and knob action, without writing anything to the device
+///
+/// #Arguments
+/// `options` - options selecting the device
+/// `config_file` - configuration file to program
+/// `product_id` - product id to build the packets for, the connected device if `None`
+///
+fn dump_packets(options: &Options, config_file: &str, product_id: Option<u16>) -> Result<()> {
+    let config = Mapping::read(config_file).context("reading config file")?;
+    let pid = match product_id {
+        Some(pid) => pid,
+        None => {
+            find_device(
+                options.devel_options.vendor_id,
+                options.devel_options.product_id,
+                options.devel_options.address,
+            )
+            .context("find USB device")?
+            .2
+        }
+    };
+    Mapping::validate(&config, Some(pid))
+        .context(format!("validating configuration file for 0x{pid:04x}"))?;
+    let config = Mapping::resolve_aliases(&config)?;
+    let groups = keyboard::build_packets(pid, &config)?;
+    println!("packets for 0x{pid:04x}:");
+    for group in &groups {
+        println!("{group}");
+    }
+    println!(
+        "{} packet(s)",
+        groups.iter().map(|g| g.packets.len()).sum::<usize>()
+    );
+    Ok(())
+}
+
+/// Performs the keys and knob actions of one layer on this computer as they are picked
+/// on stdin (row,col or knob,ccw|press|cw), so macros can be tried without programming
+/// the macropad
+///
+/// #Arguments
+/// `config_file` - configuration file to simulate
+/// `layer` - layer to simulate (one based)
+/// `countdown` - seconds to wait before performing an action
+///
+fn simulate_config_file(config_file: &str, layer: u8, countdown: u64) -> Result<()> {
+    ensure!(
+        config_file != consts::STDIN_CONFIG,
+        "stdin is needed to pick the keys, specify a configuration file"
+    );
+    let config = Mapping::read(config_file).context("reading config file")?;
+    let config = Mapping::resolve_aliases(&config)?;
+    let layer = config
+        .layers
+        .get(usize::from(layer).wrapping_sub(1))
+        .ok_or_else(|| anyhow!("layer {layer} doesn't exist in {config_file}"))?;
+    println!(
+        "SIMULATION - actions are performed on this computer, nothing is sent to the macropad"
+    );
+    println!("{}", Mapping::export_diagram(&config));
+    println!(
+        "Enter a key as row,col (eg. 1,2), a knob as knob,ccw|press|cw (eg. 1,cw) or q to quit"
+    );
+    for line in std::io::stdin().lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.eq_ignore_ascii_case("q") {
+            break;
+        }
+        let Some((a, b)) = line.split_once(',') else {
+            println!("expected row,col or knob,ccw|press|cw");
+            continue;
+        };
+        let index = a
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| i.checked_sub(1));
+        let button = match (index, b.trim()) {
+            (Some(k), "ccw") => layer.knobs.get(k).map(|k| &k.ccw),
+            (Some(k), "press") => layer.knobs.get(k).map(|k| &k.press),
+            (Some(k), "cw") => layer.knobs.get(k).map(|k| &k.cw),
+            (Some(r), col) => col
+                .parse::<usize>()
+                .ok()
+                .and_then(|c| layer.buttons.get(r)?.get(c.checked_sub(1)?)),
+            _ => None,
+        };
+        let Some(button) = button else {
+            println!("no such key or knob - {line}");
+            continue;
+        };
+        let actions = match simulate::host_actions(button) {
+            Ok(actions) => actions,
+            Err(e) => {
+                println!("can't simulate '{}': {e:#}", button.mapping);
+                continue;
+            }
+        };
+        println!("performing '{}' in {countdown}s...", button.mapping);
+        std::thread::sleep(std::time::Duration::from_secs(countdown));
+        match simulate::perform(&actions) {
+            Ok(_) => println!("[{}] performed '{}'", timestamp(), button.mapping),
+            Err(e) => println!("[{}] failed: {e:#}", timestamp()),
+        }
+    }
+    Ok(())
+}
+
+/// Programs the device every time the configuration file changes. A change is only
+/// acted on once the file has been left alone for `consts::WATCH_DEBOUNCE`, so an
+/// editor writing the file in several steps triggers a single programming cycle.
+/// Failures are logged and watching continues
+///
+/// #Arguments
+/// `options` - options selecting the device
+/// `config_file` - configuration file to watch
+/// `allow_empty` - program (almost) empty configurations
+///
+fn watch_config_file(options: &DevelOptions, config_file: &str, allow_empty: bool) -> Result<()> {
+    ensure!(
+        config_file != consts::STDIN_CONFIG,
+        "stdin can't be watched, specify a configuration file"
+    );
+    let modified = || {
+        std::fs::metadata(config_file)
+            .and_then(|m| m.modified())
+            .ok()
+    };
+    let mut programmed = modified();
+    let mut pending: Option<(std::time::SystemTime, std::time::Instant)> = None;
+    println!("watching {config_file} for changes (ctrl-c to stop)");
+    loop {
+        let current = modified();
+        match (current, pending) {
+            (Some(m), Some((p, since))) if m == p && since.elapsed() >= consts::WATCH_DEBOUNCE => {
+                pending = None;
+                programmed = Some(m);
+                match program_config_file(options, config_file, allow_empty) {
+                    Ok(_) => println!("[{}] programmed device", timestamp()),
+                    Err(e) => println!("[{}] failed: {e:#}", timestamp()),
+                }
+            }
+            // still being written, wait for it to settle
+            (Some(m), Some((p, _))) if m == p => {}
+            (Some(m), _) if Some(m) != programmed => {
+                pending = Some((m, std::time::Instant::now()));
+            }
+            _ => {}
+        }
+        std::thread::sleep(consts::WATCH_INTERVAL);
+    }
+}
+
+/// Returns the current time of day (UTC) as hh:mm:ss
+fn timestamp() -> String {
+    let secs = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    format!(
+        "{:02}:{:02}:{:02}",
+        secs / 3600 % 24,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Validates the configuration file and prints every problem found along with
+/// its location. Duplicate mappings are printed as warnings. An error is returned
+/// if any problem was found
+///
+/// #Arguments
+/// `config` - configuration read from the file
+/// `config_file` - configuration file to validate
+/// `pid` - product id to validate against
+///
+fn validate_config_file(config: &Macropad, config_file: &str, pid: Option<u16>) -> Result<()> {
+    let errors = Mapping::validate_all(config, pid)?;
+    for w in Mapping::duplicate_warnings(config) {
+        println!("Warning - {w}");
+    }
+    if errors.is_empty() {
+        println!("config is valid 👌");
+        return Ok(());
+    }
+    for e in &errors {
+        println!(" - {e}");
+    }
+    Err(anyhow!(
+        "{} problem(s) found in {config_file}",
+        errors.len()
+    ))
+}
+
 pub fn find_interface_and_endpoint(
     device: &Device<Context>,
     interface_num: Option<u8>,
@@ -218,7 +778,7 @@ pub fn find_interface_and_endpoint(
         let descriptors = intf_desc.endpoint_descriptors();
         for endpoint in descriptors {
             // check packet size
-            if endpoint.max_packet_size() != (consts::PACKET_SIZE - 1).try_into()? {
+            if endpoint.max_packet_size() != u16::try_from(consts::PACKET_SIZE - 1)? {
                 continue;
             }
 
@@ -261,13 +821,11 @@ pub fn find_interface_and_endpoint(
     Err(anyhow!("No valid interface/endpoint combination found!"))
 }
 
-fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
+fn open_keyboard(options: &DevelOptions) -> Result<Box<dyn Keyboard>> {
     // Find USB device based on the product id
-    let (device, desc, id_product) = find_device(
-        options.devel_options.vendor_id,
-        options.devel_options.product_id,
-    )
-    .context("find USB device")?;
+    let (device, desc, id_product) =
+        find_device(options.vendor_id, options.product_id, options.address)
+            .context("find USB device")?;
 
     ensure!(
         desc.num_configurations() == 1,
@@ -277,20 +835,24 @@ fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
     // Find correct endpoint
     let (intf_num, endpt_addr_out, endpt_addr_in) = find_interface_and_endpoint(
         &device,
-        options.devel_options.interface_number,
-        options.devel_options.out_endpoint_address,
-        options.devel_options.in_endpoint_address,
+        options.interface_number,
+        options.out_endpoint_address,
+        options.in_endpoint_address,
     )?;
 
     // Open device.
-    let handle = device.open().context("open USB device")?;
+    let handle = device
+        .open()
+        .map_err(usb_error)
+        .context("open USB device")?;
     let _ = handle.set_auto_detach_kernel_driver(true);
     handle
         .claim_interface(intf_num)
+        .map_err(usb_error)
         .context("claim interface")?;
 
     match id_product {
-        0x8840 | 0x8842 => {
+        0x8840 | 0x8842 | 0x8850 => {
             k884x::Keyboard884x::new(Some(handle), endpt_addr_out, endpt_addr_in, id_product)
                 .map(|v| Box::new(v) as Box<dyn Keyboard>)
         }
@@ -300,18 +862,90 @@ fn open_keyboard(options: &Options) -> Result<Box<dyn Keyboard>> {
     }
 }
 
-pub fn find_device(vid: u16, pid: Option<u16>) -> Result<(Device<Context>, DeviceDescriptor, u16)> {
+/// Opens the macropad like `open_keyboard`, trying again with a growing pause when
+/// it is busy or momentarily disconnected
+///
+/// #Arguments
+/// `options` - options selecting the macropad
+///
+pub fn open_keyboard_retry(options: &DevelOptions) -> Result<Box<dyn Keyboard>> {
+    let mut delay = consts::OPEN_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match open_keyboard(options) {
+            Err(e)
+                if attempt < consts::OPEN_ATTEMPTS
+                    && UsbFailure::find(&e).is_some_and(|f| f.is_transient()) =>
+            {
+                debug!("opening macropad failed (attempt {attempt}): {e:#}");
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Attaches the classified failure to a libusb error, so it is reported with a hint
+/// how to fix it
+///
+/// #Arguments
+/// `err` - error returned by libusb
+///
+fn usb_error(err: rusb::Error) -> anyhow::Error {
+    match UsbFailure::from_rusb(&err) {
+        Some(failure) => anyhow::Error::new(err).context(failure),
+        None => err.into(),
+    }
+}
+
+/// Returns the bus number, address and product id of every connected macropad
+/// with a supported product id
+///
+/// #Arguments
+/// `vid` - vendor id of the macropads
+///
+pub fn find_all_devices(vid: u16) -> Result<Vec<(u8, u8, u16)>> {
+    let usb_context = usb_context()?;
+    let mut found = vec![];
+    for device in usb_context.devices().context("get USB device list")?.iter() {
+        let desc = device.device_descriptor().context("get USB device info")?;
+        if desc.vendor_id() == vid && PRODUCT_IDS.contains(&desc.product_id()) {
+            found.push((device.bus_number(), device.address(), desc.product_id()));
+        }
+    }
+    Ok(found)
+}
+
+fn usb_context() -> Result<Context> {
+    let options = vec![
+        #[cfg(windows)]
+        rusb::UsbOption::use_usbdk(),
+    ];
+    Ok(rusb::Context::with_options(&options)?)
+}
+
+/// Finds the macropad to talk to. When several are connected, `address` selects
+/// the one at that bus number and address
+///
+/// #Arguments
+/// `vid` - vendor id of the macropad
+/// `pid` - product id of the macropad
+/// `address` - bus number and address of the macropad
+///
+pub fn find_device(
+    vid: u16,
+    pid: Option<u16>,
+    address: Option<(u8, u8)>,
+) -> Result<(Device<Context>, DeviceDescriptor, u16)> {
     debug!("vid: 0x{vid:02x}");
     if let Some(prod_id) = pid {
         debug!("pid: 0x{prod_id:02x}");
     } else {
         debug!("pid: None");
     }
-    let options = vec![
-        #[cfg(windows)]
-        rusb::UsbOption::use_usbdk(),
-    ];
-    let usb_context = rusb::Context::with_options(&options)?;
+    let usb_context = usb_context()?;
 
     let mut found = vec![];
     for device in usb_context.devices().context("get USB device list")?.iter() {
@@ -324,6 +958,9 @@ pub fn find_device(vid: u16, pid: Option<u16>) -> Result<(Device<Context>, Devic
             desc.product_id()
         );
         let product_id = desc.product_id();
+        if address.is_some_and(|a| a != (device.bus_number(), device.address())) {
+            continue;
+        }
 
         if desc.vendor_id() == vid {
             if let Some(prod_id) = pid {
@@ -337,8 +974,8 @@ pub fn find_device(vid: u16, pid: Option<u16>) -> Result<(Device<Context>, Devic
     }
 
     match found.len() {
-        0 => Err(anyhow!(
-            "macropad device not found. Use --vendor-id and --product-id to override defaults"
+        0 => Err(anyhow!(UsbFailure::NotFound).context(
+            "macropad device not found. Use --vendor-id and --product-id to override defaults",
         )),
         1 => Ok(found.pop().unwrap()),
         _ => {
@@ -1,6 +1,6 @@
-use anyhow::{anyhow, Context, Result};
+use anyhow::{anyhow, ensure, Context, Result};
 use serde::{Deserialize, Serialize};
-use crate::keyboard::{LedColor, MediaCode, Modifier, WellKnownCode};
+use crate::keyboard::{self, KnobAction, LedColor, MediaCode, Modifier, MouseAction, WellKnownCode};
 use crate::config::Orientation;
 use crate::consts;
 
@@ -9,11 +9,46 @@ use crate::consts;
 pub struct Button {
     pub delay: u16,
     pub mapping: String,
+    /// LED color of the key; falls back to `LedSettings` when not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub led_color: Option<LedColor>,
 }
 
 impl Button {
     pub fn new() -> Self {
-        Self { delay: 0, mapping: String::new() }
+        Self { delay: 0, mapping: String::new(), led_color: None }
+    }
+}
+
+/// Key or knob action of a layer, one based like the command line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    /// row and column
+    Key(u8, u8),
+    Knob(u8, KnobAction),
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Position::Key(row, col) => write!(f, "row {} btn {}", row, col),
+            Position::Knob(knob, action) => write!(f, "knob {} {}", knob, action),
+        }
+    }
+}
+
+/// Possible problem in a configuration, found by `Macropad::analyze`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+    /// layer of the key or knob action (one based)
+    pub layer: u8,
+    pub position: Position,
+    pub message: String,
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "layer {} {}: {}", self.layer, self.position, self.message)
     }
 }
 
@@ -23,11 +58,22 @@ pub struct Knob {
     pub ccw: Button,
     pub press: Button,
     pub cw: Button,
+    /// times the rotation actions are sent per detent, the macropads only know one so
+    /// the action is repeated in its sequence when programming
+    #[serde(default = "default_steps_per_detent", skip_serializing_if = "is_single_step")]
+    pub steps_per_detent: u8,
 }
 
+fn default_steps_per_detent() -> u8 { 1 }
+
+fn is_single_step(steps: &u8) -> bool { *steps == 1 }
+
 /// Layer configuration
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Layer {
+    /// what the layer is for (eg. Media), only shown by the editor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
     pub buttons: Vec<Vec<Button>>,
     pub knobs: Vec<Knob>,
 }
@@ -37,12 +83,42 @@ impl Layer {
         let mut buttons = Vec::new();
         for _ in 0..rows { buttons.push(vec![Button::new(); cols.into()]); }
         let mut knobs = Vec::new();
-        for _ in 0..num_knobs { knobs.push(Knob { ccw: Button::new(), press: Button::new(), cw: Button::new() }); }
-        Self { buttons, knobs }
+        for _ in 0..num_knobs { knobs.push(Knob { ccw: Button::new(), press: Button::new(), cw: Button::new(), steps_per_detent: 1 }); }
+        Self { name: None, buttons, knobs }
     }
+
+    /// Returns the name of the layer, or "Layer N" when it has none
+    ///
+    /// #Arguments
+    /// `index` - index of the layer (zero based)
+    ///
+    pub fn title(&self, index: usize) -> String {
+        match self.name.as_deref().map(str::trim) {
+            Some(name) if !name.is_empty() => name.to_string(),
+            _ => format!("Layer {}", index + 1),
+        }
+    }
+
+    /// Returns every key and knob action of the layer with its position
+    pub fn actions(&self) -> impl Iterator<Item = (Position, &Button)> {
+        let buttons = self.buttons.iter().enumerate().flat_map(|(r, row)| row.iter().enumerate().map(move |(c, btn)| (Position::Key(r as u8 + 1, c as u8 + 1), btn)));
+        let knobs = self.knobs.iter().enumerate().flat_map(|(k, knob)| [(KnobAction::RotateCCW, &knob.ccw), (KnobAction::Press, &knob.press), (KnobAction::RotateCW, &knob.cw)].into_iter().map(move |(action, btn)| (Position::Knob(k as u8 + 1, action), btn)));
+        buttons.chain(knobs)
+    }
+
+    /// Reverses the order of the columns and of the knobs, keeping each button as it is
+    pub fn mirror_horizontal(&mut self) {
+        self.buttons.iter_mut().for_each(|row| row.reverse());
+        self.knobs.reverse();
+    }
+
+    /// Reverses the order of the rows, keeping each button as it is
+    pub fn mirror_vertical(&mut self) { self.buttons.reverse(); }
 }
 
-fn default_layers_count() -> u8 { 3 }
+fn default_layers_count() -> u8 { consts::NUM_LAYERS }
+
+fn default_led_brightness() -> u8 { consts::MAX_LED_BRIGHTNESS }
 
 /// Device configuration
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -55,140 +131,1545 @@ pub struct Device {
     pub layers: u8,
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub struct LedSettings {
     pub mode: u8,
     pub layer: u8,
     pub color: LedColor,
+    /// brightness in percent, only for `consts::LED_BRIGHTNESS_PIDS`
+    #[serde(default = "default_led_brightness")]
+    pub brightness: u8,
 }
 
 /// Mapping configuration of a macropad
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Macropad {
+    /// version of the configuration format, 0 for files written before it was introduced
+    #[serde(default)]
+    pub version: u32,
     pub device: Device,
     pub layers: Vec<Layer>,
     pub led_settings: Option<LedSettings>,
+    /// user defined names for keys or sequences (eg. ctl for ctrl), sorted so saved files
+    /// don't change order
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub aliases: BTreeMap<String, String>,
 }
 
 impl Macropad {
     pub fn new(rows: u8, cols: u8, knobs: u8) -> Self {
-        let layers_count = 3;
+        let layers_count = default_layers_count();
         Self {
+            version: consts::CONFIG_VERSION,
             device: Device { orientation: Orientation::Normal, rows, cols, knobs, layers: layers_count },
             layers: vec![Layer::new(rows, cols, knobs); layers_count as usize],
-            led_settings: Some(LedSettings { mode: 1, layer: 1, color: LedColor::Cyan }),
+            led_settings: Some(LedSettings { mode: 1, layer: 1, color: LedColor::Cyan, brightness: consts::MAX_LED_BRIGHTNESS }),
+            aliases: BTreeMap::new(),
         }
     }
+
+    /// Returns a configuration with every key and knob action of every layer unassigned,
+    /// programming it clears the device
+    ///
+    /// #Arguments
+    /// `rows` - rows of keys
+    /// `cols` - keys of a row
+    /// `knobs` - number of knobs
+    /// `layers` - number of layers
+    ///
+    pub fn empty(rows: u8, cols: u8, knobs: u8, layers: u8) -> Self {
+        let mut config = Self::new(rows, cols, knobs);
+        config.device.layers = layers;
+        config.layers = vec![Layer::new(rows, cols, knobs); layers as usize];
+        config
+    }
+
+    /// Looks for bindings that are likely mistakes: a command mapped twice on a layer,
+    /// a command of the first (base) layer that sits at another position on a later
+    /// layer and knobs sending the same command both ways
+    pub fn analyze(&self) -> Vec<Finding> {
+        let mut findings = Vec::new();
+        let base: Vec<(Position, &Button)> = self.layers.first().map(|l| l.actions().filter(|(_, b)| !b.mapping.is_empty()).collect()).unwrap_or_default();
+        for (i, layer) in self.layers.iter().enumerate() {
+            let layer_no = i as u8 + 1;
+            let duplicates = Mapping::duplicate_mappings(layer);
+            for (position, btn) in layer.actions().filter(|(_, b)| !b.mapping.is_empty()) {
+                let mut finding = |message| findings.push(Finding { layer: layer_no, position, message });
+                if let Some((_, positions)) = duplicates.iter().find(|(m, _)| *m == btn.mapping) {
+                    let others: Vec<String> = positions.iter().filter(|p| **p != position).map(|p| p.to_string()).collect();
+                    finding(format!("'{}' is also mapped to {}", btn.mapping, others.join(", ")));
+                }
+                if i == 0 || base.iter().any(|(p, b)| *p == position && b.mapping == btn.mapping) { continue; }
+                if let Some((moved, _)) = base.iter().find(|(_, b)| b.mapping == btn.mapping) { finding(format!("'{}' is at {} on layer 1", btn.mapping, moved)); }
+            }
+            for (k, knob) in layer.knobs.iter().enumerate() {
+                if !knob.cw.mapping.is_empty() && knob.ccw.mapping == knob.cw.mapping {
+                    findings.push(Finding { layer: layer_no, position: Position::Knob(k as u8 + 1, KnobAction::RotateCW), message: format!("turning the knob either way sends '{}'", knob.cw.mapping) });
+                }
+            }
+        }
+        findings
+    }
 }
 
+/// QMK modifier names (keycode and wrapper function) and their mapping names, the first
+/// name is the canonical one
+const QMK_MODIFIERS: &[(&str, &str)] = &[
+    ("LCTL|LCTRL|C", "ctrl"), ("LSFT|LSHIFT|S", "shift"), ("LALT|LOPT|A", "alt|opt"), ("LGUI|LCMD|LWIN|G", "win|cmd"),
+    ("RCTL|RCTRL", "rctrl"), ("RSFT|RSHIFT", "rshift"), ("RALT|ROPT", "ralt|ropt"), ("RGUI|RCMD|RWIN", "rwin|rcmd"),
+];
+
+/// QMK keycodes (without KC_) and their mapping names, the first name is the canonical one.
+/// Letters, digits, function and numpad digit keys are translated directly
+const QMK_KEYS: &[(&str, &str)] = &[
+    ("ENT|ENTER", "enter"), ("ESC|ESCAPE", "escape"), ("BSPC|BACKSPACE", "backspace"), ("TAB", "tab"), ("SPC|SPACE", "space"),
+    ("MINS|MINUS", "minus"), ("EQL|EQUAL", "equal"), ("LBRC|LEFT_BRACKET", "leftbracket"), ("RBRC|RIGHT_BRACKET", "rightbracket"),
+    ("BSLS|BACKSLASH", "backslash"), ("SCLN|SEMICOLON", "semicolon"), ("QUOT|QUOTE", "quote"), ("GRV|GRAVE", "grave"),
+    ("COMM|COMMA", "comma"), ("DOT", "dot"), ("SLSH|SLASH", "slash"), ("CAPS|CAPS_LOCK", "capslock"),
+    ("PSCR|PRINT_SCREEN", "printscreen"), ("SCRL|SLCK|SCROLL_LOCK", "scrolllock"), ("PAUS|PAUSE", "pause"), ("INS|INSERT", "insert"),
+    ("HOME", "home"), ("PGUP|PAGE_UP", "pageup"), ("DEL|DELETE", "delete"), ("END", "end"), ("PGDN|PAGE_DOWN", "pagedown"),
+    ("RGHT|RIGHT", "right"), ("LEFT", "left"), ("DOWN", "down"), ("UP", "up"), ("APP|APPLICATION", "application"),
+    ("NUM|NLCK|NUM_LOCK", "numlock"), ("PSLS", "numpadslash"), ("PAST", "numpadasterisk"), ("PMNS", "numpadminus"), ("PPLS", "numpadplus"),
+    ("PENT", "numpadenter"), ("PDOT", "numpaddot"), ("PEQL", "numpadequal"),
+    ("MNXT", "next"), ("MPRV", "previous|prev"), ("MSTP", "stop"), ("MPLY", "play"), ("MUTE|AUDIO_MUTE", "mute"), ("VOLU|AUDIO_VOL_UP", "volumeup"), ("VOLD|AUDIO_VOL_DOWN", "volumedown"),
+    ("CALC", "calculator"), ("WFAV", "favorites"), ("WHOM", "webpagehome"), ("WBAK", "webpageback"), ("WFWD", "webpageforward"), ("BRIU", "screenbrightnessup"), ("BRID", "screenbrightnessdown"),
+];
+
+/// QMK mouse keycodes accepted on import. They are not exported since they need the
+/// mousekey feature on the QMK side
+const QMK_MOUSE: &[(&str, &str)] = &[("BTN1", "click"), ("BTN2", "rclick"), ("BTN3", "mclick"), ("WH_U", "wheelup"), ("WH_D", "wheeldown")];
+
 use ron::de::from_reader;
 use ron::ser::{to_string_pretty, PrettyConfig};
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::str::FromStr;
 
+/// How configurations are laid out when written as RON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::Args)]
+pub struct RonStyle {
+    /// Nesting depth written on separate lines, deeper values stay on one line (0 for no limit)
+    #[arg(long = "ron-depth", default_value_t = 4)]
+    pub depth: usize,
+
+    /// Number array items (eg. rows and keys) in comments, which helps finding a key in large files
+    #[arg(long, default_value_t = false)]
+    pub enumerate_arrays: bool,
+}
+
+impl Default for RonStyle {
+    fn default() -> Self { Self { depth: 4, enumerate_arrays: false } }
+}
+
+impl RonStyle {
+    fn pretty(self) -> PrettyConfig {
+        let depth = if self.depth == 0 { usize::MAX } else { self.depth };
+        PrettyConfig::new().depth_limit(depth).separate_tuple_members(true).enumerate_arrays(self.enumerate_arrays)
+    }
+}
+
 pub struct Mapping {}
 
 impl Mapping {
     pub fn config_path() -> std::path::PathBuf {
         let mut path = std::env::current_exe().unwrap_or_else(|_| std::path::PathBuf::from("."));
         path.pop();
-        path.push("mapping.ron");
+        path.push(consts::DEFAULT_CONFIG);
         path
     }
 
+    /// Reads the configuration from `cfg_file` (JSON when it has a .json extension), or
+    /// from stdin when it is `-`
     pub fn read(cfg_file: &str) -> Result<Macropad> {
-        let path = if cfg_file == "mapping.ron" { Self::config_path() } else { std::path::PathBuf::from(cfg_file) };
-        if !path.exists() {
+        if cfg_file == consts::STDIN_CONFIG {
+            let config = from_reader(std::io::stdin().lock()).map(Self::migrate).map_err(|e| anyhow!("Failed to load config from stdin: {e}"))?;
+            Self::check_dimensions(&config).context("Config from stdin is inconsistent")?;
+            return Ok(config);
+        }
+        let is_default = cfg_file == consts::DEFAULT_CONFIG;
+        let path = if is_default { Self::config_path() } else { std::path::PathBuf::from(cfg_file) };
+        // only the default config gets created; a file the user chose must exist
+        if is_default && !path.exists() {
             let default_config = Macropad::new(2, 3, 1);
             Self::save(&default_config, path.to_str().unwrap()).context("Creating default config")?;
         }
-        let f = File::open(path).context("Failed opening file")?;
-        let config: Macropad = from_reader(f).map_err(|e| anyhow!("Failed to load config: {e}"))?;
+        let f = File::open(&path).context(format!("Failed opening file {}", path.display()))?;
+        let config: Macropad = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+            serde_json::from_reader(f).map_err(|e| anyhow!("Failed to load config: {e}"))?
+        } else {
+            from_reader(f).map_err(|e| anyhow!("Failed to load config: {e}"))?
+        };
+        if config.version < consts::CONFIG_VERSION { println!("Note - {} uses config version {} and was upgraded to version {}, save it to keep the changes", path.display(), config.version, consts::CONFIG_VERSION); }
+        let config = Self::migrate(config);
+        Self::check_dimensions(&config).context(format!("{} is inconsistent", path.display()))?;
         Ok(config)
     }
 
-    pub fn print(config: Macropad) {
-        let pretty = PrettyConfig::new().depth_limit(4).separate_tuple_members(true).enumerate_arrays(false);
-        let s = to_string_pretty(&config, pretty).expect("Serialization failed");
+    /// Checks that the layers, rows, columns and knobs of every layer match what `device`
+    /// declares, so a hand edited file can't index past the end of a layer
+    ///
+    /// #Arguments
+    /// `config` - configuration to check
+    ///
+    pub fn check_dimensions(config: &Macropad) -> Result<()> {
+        let Device { rows, cols, knobs, layers, .. } = config.device;
+        ensure!(config.layers.len() == layers as usize, "device.layers is {} but there are {} layers", layers, config.layers.len());
+        for (i, layer) in config.layers.iter().enumerate() {
+            ensure!(layer.buttons.len() == rows as usize, "layer {} has {} rows of buttons but device.rows is {}", i + 1, layer.buttons.len(), rows);
+            if let Some((r, row)) = layer.buttons.iter().enumerate().find(|(_, row)| row.len() != cols as usize) {
+                return Err(anyhow!("layer {} row {} has {} buttons but device.cols is {}", i + 1, r + 1, row.len(), cols));
+            }
+            ensure!(layer.knobs.len() == knobs as usize, "layer {} has {} knobs but device.knobs is {}", i + 1, layer.knobs.len(), knobs);
+        }
+        Ok(())
+    }
+
+    /// Upgrades a configuration written by an older version of the tool to the current
+    /// `consts::CONFIG_VERSION`. Version 0 files get the default LED settings when they
+    /// have none and their layers are padded to `device.layers` and the grid size; layers
+    /// beyond `device.layers` are only dropped when they are empty
+    ///
+    /// #Arguments
+    /// `config` - configuration as read from the file
+    ///
+    pub fn migrate(mut config: Macropad) -> Macropad {
+        if config.version < 1 {
+            if config.led_settings.is_none() { config.led_settings = Macropad::new(1, 1, 0).led_settings; }
+            let Device { rows, cols, knobs, layers, .. } = config.device;
+            let is_empty = |l: &Layer| l.buttons.iter().flatten().chain(l.knobs.iter().flat_map(|k| [&k.ccw, &k.press, &k.cw])).all(|b| b.mapping.is_empty());
+            while config.layers.len() > layers as usize && config.layers.last().is_some_and(is_empty) { config.layers.pop(); }
+            config.device.layers = config.device.layers.max(u8::try_from(config.layers.len()).unwrap_or(u8::MAX));
+            config.layers.resize(config.device.layers as usize, Layer::new(rows, cols, knobs));
+            for layer in &mut config.layers {
+                layer.buttons.resize(layer.buttons.len().max(rows as usize), Vec::new());
+                for row in &mut layer.buttons { row.resize(row.len().max(cols as usize), Button::new()); }
+                let default_knob = Layer::new(0, 0, 1).knobs.remove(0);
+                layer.knobs.resize(layer.knobs.len().max(knobs as usize), default_knob);
+            }
+        }
+        config.version = consts::CONFIG_VERSION;
+        config
+    }
+
+    pub fn print(config: Macropad, style: RonStyle) {
+        let s = to_string_pretty(&config, style.pretty()).expect("Serialization failed");
         println!("{s}");
     }
 
+    /// Writes the configuration to `cfg_file`, as JSON when it has a .json extension and
+    /// as RON otherwise
     pub fn save(config: &Macropad, cfg_file: &str) -> Result<()> {
-        let path = if cfg_file == "mapping.ron" { Self::config_path() } else { std::path::PathBuf::from(cfg_file) };
-        let pretty = PrettyConfig::new().depth_limit(4).separate_tuple_members(true).enumerate_arrays(false);
-        let s = to_string_pretty(config, pretty).map_err(|e| anyhow!("Serialization failed: {}", e))?;
+        Self::save_styled(config, cfg_file, RonStyle::default())
+    }
+
+    /// Writes the configuration like `save`, laying out RON files as `style` asks
+    ///
+    /// #Arguments
+    /// `config` - configuration to write
+    /// `cfg_file` - file to write
+    /// `style` - layout of RON files
+    ///
+    pub fn save_styled(config: &Macropad, cfg_file: &str, style: RonStyle) -> Result<()> {
+        let path = if cfg_file == consts::DEFAULT_CONFIG { Self::config_path() } else { std::path::PathBuf::from(cfg_file) };
+        let s = if path.extension().is_some_and(|e| e.eq_ignore_ascii_case("json")) {
+            serde_json::to_string_pretty(config).map_err(|e| anyhow!("Serialization failed: {}", e))?
+        } else {
+            to_string_pretty(config, style.pretty()).map_err(|e| anyhow!("Serialization failed: {}", e))?
+        };
         std::fs::write(path, s).map_err(|e| anyhow!("Failed to write file: {}", e))?;
         Ok(())
     }
 
-    pub fn validate(cfg_file: &str, pid: Option<u16>) -> Result<()> {
-        let mut max_programmable_keys = 0xff;
-        if let Some(max) = pid {
-            match max {
-                0x8840 | 0x8842 => max_programmable_keys = consts::MAX_KEY_PRESSES_884X,
-                0x8890 => max_programmable_keys = consts::MAX_KEY_PRESSES_8890,
-                _ => return Err(anyhow!("Unknown product id 0x{:02x}", max)),
+    /// Writes a single layer to its own file, as JSON when it has a .json extension and
+    /// as RON otherwise, so it can be imported into another configuration
+    ///
+    /// #Arguments
+    /// `layer` - layer to export
+    /// `path` - file to write
+    ///
+    pub fn export_layer(layer: &Layer, path: &str) -> Result<()> {
+        let s = if path.to_lowercase().ends_with(".json") {
+            serde_json::to_string_pretty(layer).map_err(|e| anyhow!("Serialization failed: {}", e))?
+        } else {
+            let pretty = PrettyConfig::new().depth_limit(3).separate_tuple_members(true).enumerate_arrays(false);
+            to_string_pretty(layer, pretty).map_err(|e| anyhow!("Serialization failed: {}", e))?
+        };
+        std::fs::write(path, s).context(format!("Failed to write layer file {path}"))
+    }
+
+    /// Reads a layer written by `export_layer`
+    ///
+    /// #Arguments
+    /// `path` - file to read
+    ///
+    pub fn import_layer(path: &str) -> Result<Layer> {
+        let f = File::open(path).context(format!("Failed opening file {path}"))?;
+        let layer: Layer = if path.to_lowercase().ends_with(".json") {
+            serde_json::from_reader(f).map_err(|e| anyhow!("{path} is not a layer file: {e}"))?
+        } else {
+            from_reader(f).map_err(|e| anyhow!("{path} is not a layer file: {e}"))?
+        };
+        Ok(layer)
+    }
+
+    /// Writes a timestamped copy of the configuration next to the config file and prunes
+    /// old copies so only the most recent `consts::MAX_BACKUPS` remain
+    ///
+    /// #Arguments
+    /// `config` - configuration to back up
+    /// `cfg_file` - config file the backup is stored next to
+    ///
+    pub fn backup(config: &Macropad, cfg_file: &str) -> Result<std::path::PathBuf> {
+        let path = if cfg_file == consts::DEFAULT_CONFIG { Self::config_path() } else { std::path::PathBuf::from(cfg_file) };
+        let dir = match path.parent() { Some(p) if !p.as_os_str().is_empty() => p.to_path_buf(), _ => std::path::PathBuf::from(".") };
+        let stem = path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or("mapping".to_string());
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH)?.as_secs();
+        let backup = dir.join(format!("{stem}.backup.{timestamp}.ron"));
+        Self::save(config, &backup.to_string_lossy()).context("Writing backup")?;
+        Self::prune_backups(&dir, &stem, consts::MAX_BACKUPS)?;
+        Ok(backup)
+    }
+
+    fn prune_backups(dir: &std::path::Path, stem: &str, keep: usize) -> Result<()> {
+        let prefix = format!("{stem}.backup.");
+        let mut backups: Vec<(u64, std::path::PathBuf)> = std::fs::read_dir(dir)?.filter_map(|e| e.ok()).filter_map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let timestamp = name.strip_prefix(&prefix)?.strip_suffix(".ron")?.parse::<u64>().ok()?;
+            Some((timestamp, e.path()))
+        }).collect();
+        backups.sort_by_key(|b| std::cmp::Reverse(b.0));
+        for (_, old) in backups.iter().skip(keep) { std::fs::remove_file(old).context(format!("Removing old backup {}", old.display()))?; }
+        Ok(())
+    }
+
+    /// Renders every layer as a grid of boxed mappings followed by the knob actions.
+    /// The button matrix is stored as seen in the configured orientation, so the grid
+    /// is drawn row by row as stored to match the physical layout
+    ///
+    /// #Arguments
+    /// `config` - configuration to render
+    ///
+    pub fn export_diagram(config: &Macropad) -> String {
+        let mut out = format!("Macropad {}x{} ({:?}), {} knob(s)\n", config.device.rows, config.device.cols, config.device.orientation, config.device.knobs);
+        for (i, layer) in config.layers.iter().enumerate() {
+            out.push_str(&format!("\nLayer {}\n", i + 1));
+            let cols = layer.buttons.iter().map(|r| r.len()).max().unwrap_or(0);
+            let widths: Vec<usize> = (0..cols).map(|c| layer.buttons.iter().filter_map(|r| r.get(c)).map(|b| b.mapping.chars().count()).max().unwrap_or(0).max(1)).collect();
+            let border = widths.iter().fold("+".to_string(), |acc, w| format!("{acc}{}+", "-".repeat(w + 2)));
+            out.push_str(&border); out.push('\n');
+            for row in &layer.buttons {
+                out.push('|');
+                for (c, w) in widths.iter().enumerate() { out.push_str(&format!(" {:<w$} |", row.get(c).map(|b| b.mapping.as_str()).unwrap_or(""), w = w)); }
+                out.push('\n'); out.push_str(&border); out.push('\n');
+            }
+            for (k, knob) in layer.knobs.iter().enumerate() {
+                out.push_str(&format!("Knob {}: CCW [{}]  Press [{}]  CW [{}]\n", k + 1, knob.ccw.mapping, knob.press.mapping, knob.cw.mapping));
             }
         }
-        let cfg = Self::read(cfg_file)?;
-        if cfg.layers.is_empty() || cfg.layers.len() > 3 { return Err(anyhow!("number of layers must be > 0 and < 4")); }
-        for (i, layer) in cfg.layers.iter().enumerate() {
-            if layer.buttons.len() != cfg.device.rows.into() { return Err(anyhow!("rows mismatch at layer {}", i+1)); }
+        out
+    }
+
+    /// Renders every layer as a Markdown table followed by a table of knob actions
+    ///
+    /// #Arguments
+    /// `config` - configuration to render
+    ///
+    pub fn export_markdown(config: &Macropad) -> String {
+        let cell = |b: &Button| if b.mapping.is_empty() { String::new() } else { format!("`{}`", b.mapping.replace('|', "\\|")) };
+        let mut out = format!("# Macropad {}x{} ({:?})\n", config.device.rows, config.device.cols, config.device.orientation);
+        for (i, layer) in config.layers.iter().enumerate() {
+            out.push_str(&format!("\n## Layer {}\n\n", i + 1));
+            let cols = layer.buttons.iter().map(|r| r.len()).max().unwrap_or(0);
+            out.push_str(&format!("| |{}\n", (1..=cols).map(|c| format!(" Col {c} |")).collect::<String>()));
+            out.push_str(&format!("|---|{}\n", "---|".repeat(cols)));
+            for (r, row) in layer.buttons.iter().enumerate() {
+                out.push_str(&format!("| **Row {}** |{}\n", r + 1, row.iter().map(|b| format!(" {} |", cell(b))).collect::<String>()));
+            }
+            if !layer.knobs.is_empty() {
+                out.push_str("\n| Knob | CCW | Press | CW |\n|---|---|---|---|\n");
+                for (k, knob) in layer.knobs.iter().enumerate() { out.push_str(&format!("| {} | {} | {} | {} |\n", k + 1, cell(&knob.ccw), cell(&knob.press), cell(&knob.cw))); }
+            }
+        }
+        out
+    }
+
+    /// Returns the number of keys and knob actions with a mapping, over all layers
+    ///
+    /// #Arguments
+    /// `config` - configuration to count
+    ///
+    pub fn count_mappings(config: &Macropad) -> usize {
+        config.layers.iter().map(|l| l.buttons.iter().flatten().chain(l.knobs.iter().flat_map(|k| [&k.ccw, &k.press, &k.cw])).filter(|b| !b.mapping.is_empty()).count()).sum()
+    }
+
+    /// Returns the button of a key or knob action, with an error naming the valid range
+    /// when the layer or position is outside of the configuration
+    ///
+    /// #Arguments
+    /// `config` - configuration holding the button
+    /// `layer` - layer of the button (one based)
+    /// `position` - key or knob action (one based)
+    ///
+    pub fn button_mut(config: &mut Macropad, layer: u8, position: Position) -> Result<&mut Button> {
+        let layers = config.layers.len();
+        let layer = usize::from(layer).checked_sub(1).and_then(|l| config.layers.get_mut(l)).ok_or_else(|| anyhow!("layer {} is out of range, the configuration has layers 1 to {}", layer, layers))?;
+        match position {
+            Position::Key(row, col) => {
+                let (rows, cols) = (layer.buttons.len(), layer.buttons.first().map_or(0, |r| r.len()));
+                usize::from(row).checked_sub(1).and_then(|r| layer.buttons.get_mut(r)).and_then(|r| usize::from(col).checked_sub(1).and_then(|c| r.get_mut(c)))
+                    .ok_or_else(|| anyhow!("key {},{} is out of range, the configuration has {} rows and {} columns", row, col, rows, cols))
+            }
+            Position::Knob(knob, action) => {
+                let knobs = layer.knobs.len();
+                let k = usize::from(knob).checked_sub(1).and_then(|k| layer.knobs.get_mut(k)).ok_or_else(|| anyhow!("knob {} is out of range, the configuration has {} knob(s)", knob, knobs))?;
+                Ok(match action { KnobAction::RotateCCW => &mut k.ccw, KnobAction::Press => &mut k.press, KnobAction::RotateCW => &mut k.cw })
+            }
+        }
+    }
+
+    /// Compares two configurations and describes every difference in device settings,
+    /// LED settings, layers and individual buttons/knobs. Mismatched dimensions are
+    /// reported as added or removed entries
+    ///
+    /// #Arguments
+    /// `a` - original configuration
+    /// `b` - configuration to compare against
+    ///
+    pub fn diff(a: &Macropad, b: &Macropad) -> Vec<String> {
+        let mut diffs = Vec::new();
+        let (da, db) = (&a.device, &b.device);
+        if da.orientation != db.orientation { diffs.push(format!("device orientation: {:?} -> {:?}", da.orientation, db.orientation)); }
+        if (da.rows, da.cols) != (db.rows, db.cols) { diffs.push(format!("device grid: {}x{} -> {}x{}", da.rows, da.cols, db.rows, db.cols)); }
+        if da.knobs != db.knobs { diffs.push(format!("device knobs: {} -> {}", da.knobs, db.knobs)); }
+        if da.layers != db.layers { diffs.push(format!("device layers: {} -> {}", da.layers, db.layers)); }
+        if a.led_settings != b.led_settings {
+            let led = |l: &Option<LedSettings>| l.map(|l| format!("mode {} layer {} color {:?} brightness {}%", l.mode, l.layer, l.color, l.brightness)).unwrap_or("none".to_string());
+            diffs.push(format!("led settings: {} -> {}", led(&a.led_settings), led(&b.led_settings)));
+        }
+        let describe = |btn: &Button| format!("'{}' delay {}{}", btn.mapping, btn.delay, btn.led_color.map(|c| format!(" led {:?}", c)).unwrap_or_default());
+        let compare = |location: String, x: Option<&Button>, y: Option<&Button>| match (x, y) {
+            (Some(x), Some(y)) if x.mapping != y.mapping || x.delay != y.delay || x.led_color != y.led_color => Some(format!("{location}: {} -> {}", describe(x), describe(y))),
+            (Some(x), None) => Some(format!("{location}: removed {}", describe(x))),
+            (None, Some(y)) => Some(format!("{location}: added {}", describe(y))),
+            _ => None,
+        };
+        for i in 0..a.layers.len().max(b.layers.len()) {
+            let (la, lb) = match (a.layers.get(i), b.layers.get(i)) {
+                (Some(la), Some(lb)) => (la, lb),
+                (Some(_), None) => { diffs.push(format!("layer {}: removed", i + 1)); continue; }
+                (None, _) => { diffs.push(format!("layer {}: added", i + 1)); continue; }
+            };
+            for r in 0..la.buttons.len().max(lb.buttons.len()) {
+                let (ra, rb) = (la.buttons.get(r), lb.buttons.get(r));
+                let cols = ra.map(|v| v.len()).unwrap_or(0).max(rb.map(|v| v.len()).unwrap_or(0));
+                diffs.extend((0..cols).filter_map(|c| compare(format!("layer {} row {} btn {}", i + 1, r + 1, c + 1), ra.and_then(|v| v.get(c)), rb.and_then(|v| v.get(c)))));
+            }
+            for k in 0..la.knobs.len().max(lb.knobs.len()) {
+                let (ka, kb) = (la.knobs.get(k), lb.knobs.get(k));
+                diffs.extend(compare(format!("layer {} knob {} ccw", i + 1, k + 1), ka.map(|k| &k.ccw), kb.map(|k| &k.ccw)));
+                diffs.extend(compare(format!("layer {} knob {} press", i + 1, k + 1), ka.map(|k| &k.press), kb.map(|k| &k.press)));
+                diffs.extend(compare(format!("layer {} knob {} cw", i + 1, k + 1), ka.map(|k| &k.cw), kb.map(|k| &k.cw)));
+                if let (Some(ka), Some(kb)) = (ka, kb) {
+                    if ka.steps_per_detent != kb.steps_per_detent { diffs.push(format!("layer {} knob {} steps per detent: {} -> {}", i + 1, k + 1, ka.steps_per_detent, kb.steps_per_detent)); }
+                }
+            }
+        }
+        diffs
+    }
+
+    /// Returns every non-empty mapping used more than once within the layer together
+    /// with the positions (eg. row 1 btn 2, knob 1 press) it is used at
+    ///
+    /// #Arguments
+    /// `layer` - layer to check
+    ///
+    pub fn duplicate_mappings(layer: &Layer) -> Vec<(String, Vec<Position>)> {
+        let mut used: Vec<(String, Vec<Position>)> = Vec::new();
+        for (position, btn) in layer.actions() {
+            if btn.mapping.is_empty() { continue; }
+            match used.iter_mut().find(|(m, _)| *m == btn.mapping) {
+                Some((_, positions)) => positions.push(position),
+                None => used.push((btn.mapping.clone(), vec![position])),
+            }
+        }
+        used.retain(|(_, positions)| positions.len() > 1);
+        used
+    }
+
+    /// Describes every mapping used more than once within a layer. Duplicates are
+    /// sometimes intentional, so these are only warnings
+    pub fn duplicate_warnings(cfg: &Macropad) -> Vec<String> {
+        cfg.layers.iter().enumerate().flat_map(|(i, layer)| Self::duplicate_mappings(layer).into_iter().map(move |(mapping, positions)| format!("layer {}: '{}' is mapped to {}", i+1, mapping, positions.iter().map(|p| p.to_string()).collect::<Vec<_>>().join(", ")))).collect()
+    }
+
+    /// Imports a VIA keymap (JSON with `layers` of keycodes and optionally `encoders` and
+    /// a `matrix` of rows/cols). Keycodes that have no equivalent are left unassigned and
+    /// reported in the returned warnings
+    ///
+    /// #Arguments
+    /// `path` - VIA keymap file
+    ///
+    pub fn import_via(path: &str) -> Result<(Macropad, Vec<String>)> {
+        let f = File::open(path).context(format!("Failed opening file {}", path))?;
+        let via: serde_json::Value = serde_json::from_reader(f).map_err(|e| anyhow!("Failed to parse VIA keymap: {e}"))?;
+        let layers = via["layers"].as_array().ok_or_else(|| anyhow!("VIA keymap has no layers"))?;
+        ensure!(!layers.is_empty(), "VIA keymap has no layers");
+        let mut warnings = Vec::new();
+        let keys = layers[0].as_array().map(|l| l.len()).unwrap_or(0);
+        let (rows, cols) = match (via["matrix"]["rows"].as_u64(), via["matrix"]["cols"].as_u64()) {
+            (Some(r), Some(c)) => (u8::try_from(r)?, u8::try_from(c)?),
+            _ => { warnings.push(format!("no matrix size in keymap, using a single row of {} keys", keys)); (1, u8::try_from(keys)?) }
+        };
+        let knobs = via["encoders"].as_array().and_then(|e| e.first()).and_then(|e| e.as_array()).map(|e| e.len()).unwrap_or(0);
+        let mut config = Macropad::new(rows, cols, u8::try_from(knobs)?);
+        config.device.layers = u8::try_from(layers.len())?;
+        config.layers = vec![Layer::new(rows, cols, knobs as u8); layers.len()];
+        let mut convert = |code: &serde_json::Value, location: String| -> String {
+            let code = code.as_str().unwrap_or_default();
+            if matches!(code, "" | "KC_NO" | "KC_TRNS" | "KC_TRANSPARENT" | "_______" | "XXXXXXX") { return String::new(); }
+            Self::via_keycode(code).unwrap_or_else(|| { warnings.push(format!("{location}: unsupported keycode {code}")); String::new() })
+        };
+        for (i, layer) in layers.iter().enumerate() {
+            for (k, code) in layer.as_array().into_iter().flatten().enumerate() {
+                let (r, c) = (k / cols as usize, k % cols as usize);
+                if r >= rows as usize { break; }
+                config.layers[i].buttons[r][c].mapping = convert(code, format!("layer {} row {} btn {}", i+1, r+1, c+1));
+            }
+            for (k, encoder) in via["encoders"][i].as_array().into_iter().flatten().enumerate().take(knobs) {
+                config.layers[i].knobs[k].ccw.mapping = convert(&encoder[0], format!("layer {} knob {} ccw", i+1, k+1));
+                config.layers[i].knobs[k].cw.mapping = convert(&encoder[1], format!("layer {} knob {} cw", i+1, k+1));
+            }
+        }
+        Ok((config, warnings))
+    }
+
+    /// Returns the mapping for a VIA/QMK keycode (eg. KC_A, KC_LCTL or LCTL(KC_C))
+    fn via_keycode(code: &str) -> Option<String> {
+        if let Some((wrapper, inner)) = code.strip_suffix(')').and_then(|c| c.split_once('(')) {
+            let modifier = QMK_MODIFIERS.iter().find(|(w, _)| w.split('|').any(|w| w == wrapper))?.1;
+            return Some(format!("{}-{}", modifier, Self::via_keycode(inner)?));
+        }
+        let key = code.strip_prefix("KC_")?;
+        if key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric()) { return Some(key.to_lowercase()); }
+        if let Some(n) = key.strip_prefix('F').and_then(|n| n.parse::<u8>().ok()) { return (1..=24).contains(&n).then(|| format!("f{}", n)); }
+        if let Some(n) = key.strip_prefix('P').and_then(|n| n.parse::<u8>().ok()) { return (n <= 9).then(|| format!("numpad{}", n)); }
+        if let Some((_, mapping)) = QMK_MOUSE.iter().find(|(k, _)| *k == key) { return Some(mapping.to_string()); }
+        QMK_KEYS.iter().find(|(k, _)| k.split('|').any(|k| k == key)).map(|(_, m)| m.to_string())
+    }
+
+    /// Returns the QMK keycode for a mapping, `None` when it has no QMK equivalent
+    /// (sequences, text, holds, mouse actions or unknown keys)
+    fn qmk_keycode(mapping: &str) -> Option<String> {
+        if mapping.is_empty() { return Some("KC_NO".to_string()); }
+        if mapping.contains(',') || mapping.contains(':') { return None; }
+        let mut parts: Vec<String> = mapping.split('-').map(|p| p.to_lowercase()).collect();
+        // numpad aliases such as num1 go by their canonical name
+        let key = parts.pop().map(|k| WellKnownCode::from_str(&k).map(|c| c.to_string()).unwrap_or(k))?;
+        let function_key = key.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()).is_some_and(|n| (1..=24).contains(&n));
+        let mut code = if function_key || (key.len() == 1 && key.chars().all(|c| c.is_ascii_alphanumeric())) { format!("KC_{}", key.to_uppercase()) }
+            else if let Some(n) = key.strip_prefix("numpad").filter(|n| n.len() == 1 && n.chars().all(|c| c.is_ascii_digit())) { format!("KC_P{}", n) }
+            else if let Some((w, _)) = QMK_MODIFIERS.iter().find(|(_, m)| m.split('|').any(|m| m == key)) { format!("KC_{}", w.split('|').next()?) }
+            else { format!("KC_{}", QMK_KEYS.iter().find(|(_, m)| m.split('|').any(|m| m == key))?.0.split('|').next()?) };
+        for modifier in parts.iter().rev() {
+            let (w, _) = QMK_MODIFIERS.iter().find(|(_, m)| m.split('|').any(|m| m == modifier))?;
+            code = format!("{}({})", w.split('|').next()?, code);
+        }
+        Some(code)
+    }
+
+    /// Exports the layers as a QMK keymap in C. Buttons form the `keymaps` matrix and knobs
+    /// an `encoder_map`; knob presses are listed as comments since they are not part of the
+    /// matrix. Actions without a QMK keycode become `KC_NO` with a comment naming the mapping
+    ///
+    /// #Arguments
+    /// `config` - configuration to export
+    ///
+    pub fn export_qmk(config: &Macropad) -> String {
+        let code = |b: &Button| Self::qmk_keycode(&b.mapping).unwrap_or_else(|| format!("KC_NO /* TODO: {} */", b.mapping.replace("*/", "* /")));
+        let (rows, cols, knobs) = (config.device.rows, config.device.cols, config.device.knobs);
+        let mut out = format!("// Generated by macropad-tool from a {}x{} layout with {} knob(s)\n\n", rows, cols, knobs);
+        out.push_str(&format!("const uint16_t PROGMEM keymaps[][{}][{}] = {{\n", rows, cols));
+        for (i, layer) in config.layers.iter().enumerate() {
+            out.push_str(&format!("    [{}] = {{\n", i));
+            for row in &layer.buttons { out.push_str(&format!("        {{{}}},\n", row.iter().map(code).collect::<Vec<_>>().join(", "))); }
+            for (k, knob) in layer.knobs.iter().enumerate() { out.push_str(&format!("        // knob {} press: {}\n", k + 1, code(&knob.press))); }
+            out.push_str("    },\n");
+        }
+        out.push_str("};\n");
+        if knobs > 0 {
+            out.push_str(&format!("\n#if defined(ENCODER_MAP_ENABLE)\nconst uint16_t PROGMEM encoder_map[][{}][2] = {{\n", knobs));
+            for (i, layer) in config.layers.iter().enumerate() {
+                out.push_str(&format!("    [{}] = {{{}}},\n", i, layer.knobs.iter().map(|k| format!("ENCODER_CCW_CW({}, {})", code(&k.ccw), code(&k.cw))).collect::<Vec<_>>().join(", ")));
+            }
+            out.push_str("};\n#endif\n");
+        }
+        out
+    }
+
+    /// Exports the layers as CSV, one line per row of keys followed by one line per knob
+    /// with its CCW, press and CW mappings. Layers follow each other without separator,
+    /// delays and LED colors are left out
+    ///
+    /// #Arguments
+    /// `config` - configuration to export
+    ///
+    pub fn export_csv(config: &Macropad) -> String {
+        let line = |buttons: &mut dyn Iterator<Item = &Button>| buttons.map(|b| Self::csv_field(&b.mapping)).collect::<Vec<_>>().join(",") + "\n";
+        let mut out = String::new();
+        for layer in &config.layers {
+            for row in &layer.buttons { out.push_str(&line(&mut row.iter())); }
+            for knob in &layer.knobs { out.push_str(&line(&mut [&knob.ccw, &knob.press, &knob.cw].into_iter())); }
+        }
+        out
+    }
+
+    /// Imports layers written as CSV (see `export_csv`), every `rows` lines of keys followed
+    /// by `knobs` lines of CCW, press and CW mappings form a layer. Missing cells are left
+    /// unassigned and every mapping is validated, errors name the line and column of the cell
+    ///
+    /// #Arguments
+    /// `path` - CSV file
+    /// `rows` - rows of keys of a layer
+    /// `cols` - keys of a row
+    /// `knobs` - knobs of a layer
+    ///
+    pub fn import_csv(path: &str, rows: u8, cols: u8, knobs: u8) -> Result<Macropad> {
+        let text = std::fs::read_to_string(path).context(format!("Failed opening file {}", path))?;
+        let lines: Vec<&str> = text.trim_end_matches(['\r', '\n']).split('\n').map(|l| l.trim_end_matches('\r')).collect();
+        let per_layer = usize::from(rows) + usize::from(knobs);
+        ensure!(per_layer > 0 && cols > 0, "a layer needs at least one key or knob");
+        ensure!(lines.len().is_multiple_of(per_layer), "{} has {} lines, which isn't a multiple of the {} rows and {} knob lines of a layer", path, lines.len(), rows, knobs);
+        let layers = lines.len() / per_layer;
+        let max_layers = consts::max_layers(None);
+        ensure!((1..=usize::from(max_layers)).contains(&layers), "{} holds {} layers, it must be > 0 and <= {}", path, layers, max_layers);
+        let mut config = Macropad::new(rows, cols, knobs);
+        config.device.layers = layers as u8;
+        config.layers = vec![Layer::new(rows, cols, knobs); layers];
+        let mut errors = Vec::new();
+        for (n, line) in lines.iter().enumerate() {
+            let (i, r) = (n / per_layer, n % per_layer);
+            let cells = Self::csv_fields(line).context(format!("line {}", n + 1))?;
+            let layer = &mut config.layers[i];
+            let (mut buttons, width): (Vec<(&mut Button, String)>, usize) = match r.checked_sub(usize::from(rows)) {
+                None => (layer.buttons[r].iter_mut().enumerate().map(|(c, b)| (b, format!("layer {} row {} btn {}", i+1, r+1, c+1))).collect(), usize::from(cols)),
+                Some(k) => { let knob = &mut layer.knobs[k]; (vec![(&mut knob.ccw, format!("layer {} knob {} ccw", i+1, k+1)), (&mut knob.press, format!("layer {} knob {} press", i+1, k+1)), (&mut knob.cw, format!("layer {} knob {} cw", i+1, k+1))], 3) }
+            };
+            if let Some(extra) = cells.iter().skip(width).position(|c| !c.trim().is_empty()) { errors.push(format!("line {} column {}: only {} cells expected", n+1, width+extra+1, width)); }
+            for (c, ((btn, location), cell)) in buttons.iter_mut().zip(cells).enumerate() {
+                btn.mapping = cell.trim().to_string();
+                if let Err(e) = Self::validate_button(btn, None) { errors.push(format!("line {} column {} ({}): {:#}", n+1, c+1, location, e)); }
+            }
+        }
+        ensure!(errors.is_empty(), "{}", errors.join("\n"));
+        Ok(config)
+    }
+
+    /// Quotes a CSV cell when it holds a comma, quote or surrounding spaces
+    fn csv_field(cell: &str) -> String {
+        if cell.contains([',', '"']) || cell.trim() != cell { format!("\"{}\"", cell.replace('"', "\"\"")) } else { cell.to_string() }
+    }
+
+    /// Splits a CSV line into its cells, quoted cells can hold commas and doubled quotes
+    fn csv_fields(line: &str) -> Result<Vec<String>> {
+        let (mut cells, mut cell, mut quoted) = (Vec::new(), String::new(), false);
+        let mut chars = line.chars().peekable();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '"' if quoted && chars.peek() == Some(&'"') => { cell.push('"'); chars.next(); }
+                '"' if quoted => quoted = false,
+                '"' if cell.trim().is_empty() => { cell.clear(); quoted = true; }
+                ',' if !quoted => cells.push(std::mem::take(&mut cell)),
+                ch => cell.push(ch),
+            }
+        }
+        ensure!(!quoted, "unterminated quote");
+        cells.push(cell);
+        Ok(cells)
+    }
+
+    fn max_key_presses(pid: Option<u16>) -> Result<usize> {
+        match pid {
+            None => Ok(0xff),
+            Some(0x8840 | 0x8842) => Ok(consts::MAX_KEY_PRESSES_884X),
+            Some(0x8850) => Ok(consts::MAX_KEY_PRESSES_8850),
+            Some(0x8890) => Ok(consts::MAX_KEY_PRESSES_8890),
+            Some(p) => Err(anyhow!("Unknown product id 0x{:02x}", p)),
+        }
+    }
+
+    pub fn validate(cfg: &Macropad, pid: Option<u16>) -> Result<()> {
+        let max_programmable_keys = Self::max_key_presses(pid)?;
+        let max_layers = consts::max_layers(pid);
+        if cfg.layers.is_empty() || cfg.layers.len() > max_layers.into() { return Err(anyhow!("number of layers must be > 0 and <= {}", max_layers)); }
+        if let Some(e) = Self::check_led_settings(cfg, pid) { return Err(anyhow!("{}", e)); }
+        if let Some(e) = Self::check_knob_steps(cfg).first() { return Err(anyhow!("{}", e)); }
+        if let Some(e) = Self::check_aliases(&cfg.aliases).first() { return Err(anyhow!("{}", e)); }
+        let resolved = Self::resolve_aliases(cfg)?;
+        for (i, layer) in resolved.layers.iter().enumerate() {
+            if layer.buttons.len() != usize::from(cfg.device.rows) { return Err(anyhow!("rows mismatch at layer {}", i+1)); }
             for (j, btn_mapping) in layer.buttons.iter().enumerate() {
-                if btn_mapping.len() != cfg.device.cols.into() { return Err(anyhow!("cols mismatch at layer {} row {}", i+1, j+1)); }
+                if btn_mapping.len() != usize::from(cfg.device.cols) { return Err(anyhow!("cols mismatch at layer {} row {}", i+1, j+1)); }
                 for (k, btn) in btn_mapping.iter().enumerate() {
                     Self::validate_key_mapping(btn, max_programmable_keys, pid).context(format!("layer {} row {} btn {}", i+1, j+1, k+1))?;
+                    if let Some(p) = pid {
+                        if btn.led_color.is_some() && !consts::PER_KEY_LED_PIDS.contains(&p) { println!("Warning - 0x{:04x} doesn't support per key LED colors yet (layer {} row {} btn {})", p, i+1, j+1, k+1); }
+                    }
                 }
             }
-            if layer.knobs.len() != cfg.device.knobs.into() { return Err(anyhow!("knobs mismatch at layer {}", i+1)); }
+            if layer.knobs.len() != usize::from(cfg.device.knobs) { return Err(anyhow!("knobs mismatch at layer {}", i+1)); }
             for (k, knob) in layer.knobs.iter().enumerate() {
                 Self::validate_key_mapping(&knob.ccw, max_programmable_keys, pid).context(format!("layer {} knob {} ccw", i+1, k+1))?;
                 Self::validate_key_mapping(&knob.press, max_programmable_keys, pid).context(format!("layer {} knob {} press", i+1, k+1))?;
                 Self::validate_key_mapping(&knob.cw, max_programmable_keys, pid).context(format!("layer {} knob {} cw", i+1, k+1))?;
             }
         }
+        Self::check_key_budget(&resolved, pid)?;
+        for warning in Self::duplicate_warnings(cfg) { println!("Warning - {}", warning); }
         Ok(())
     }
 
+    /// Checks the LED brightness, warning when the product id can't dim its LEDs
+    ///
+    /// #Arguments
+    /// `cfg` - configuration to check
+    /// `pid` - product id to check against
+    ///
+    fn check_led_settings(cfg: &Macropad, pid: Option<u16>) -> Option<String> {
+        let brightness = cfg.led_settings?.brightness;
+        if brightness > consts::MAX_LED_BRIGHTNESS { return Some(format!("LED brightness must be <= {}% (found {}%)", consts::MAX_LED_BRIGHTNESS, brightness)); }
+        if let Some(p) = pid.filter(|p| brightness < consts::MAX_LED_BRIGHTNESS && !consts::LED_BRIGHTNESS_PIDS.contains(p)) { println!("Warning - 0x{:04x} doesn't support LED brightness yet, the LEDs stay at full brightness", p); }
+        None
+    }
+
+    /// Returns the number of key presses stored for the whole configuration. Pauses
+    /// aren't key presses and mappings that don't parse are left out, they are reported
+    /// by the validation of their key
+    ///
+    /// #Arguments
+    /// `cfg` - configuration with its aliases resolved
+    ///
+    pub fn key_presses(cfg: &Macropad) -> usize {
+        cfg.layers.iter().flat_map(|l| l.actions()).filter(|(_, btn)| !btn.mapping.is_empty())
+            .filter_map(|(_, btn)| keyboard::key_sequence(&btn.mapping).and_then(|seq| keyboard::split_waits(&seq, btn.delay)).ok())
+            .map(|(keys, _)| keys.len()).sum()
+    }
+
+    /// Checks that the key presses of the whole configuration fit into what the product id
+    /// can store, see `consts::KEY_PRESS_CAPACITY`
+    ///
+    /// #Arguments
+    /// `cfg` - configuration with its aliases resolved
+    /// `pid` - product id to check against
+    ///
+    fn check_key_budget(cfg: &Macropad, pid: Option<u16>) -> Result<()> {
+        let Some((pid, capacity)) = pid.and_then(|p| Some((p, consts::key_press_capacity(p)?))) else { return Ok(()); };
+        Self::check_key_capacity(cfg, pid, capacity)
+    }
+
+    /// Checks that the key presses of the whole configuration fit into `capacity`
+    ///
+    /// #Arguments
+    /// `cfg` - configuration with its aliases resolved
+    /// `pid` - product id to report
+    /// `capacity` - total number of key presses the device stores
+    ///
+    fn check_key_capacity(cfg: &Macropad, pid: u16, capacity: usize) -> Result<()> {
+        let used = Self::key_presses(cfg);
+        ensure!(used <= capacity, "the configuration has {} key presses in total but 0x{:04x} stores at most {} ({} over budget)", used, pid, capacity, used - capacity);
+        Ok(())
+    }
+
+    /// Replaces the aliases in a mapping by what they stand for. Aliases may use other
+    /// aliases; `text:` is typed literally and never expanded
+    ///
+    /// #Arguments
+    /// `mapping` - mapping of a button
+    /// `aliases` - aliases of the configuration
+    ///
+    pub fn expand_aliases(mapping: &str, aliases: &BTreeMap<String, String>) -> Result<String> {
+        Self::expand_aliases_in(mapping, aliases, &mut Vec::new())
+    }
+
+    fn expand_aliases_in(mapping: &str, aliases: &BTreeMap<String, String>, stack: &mut Vec<String>) -> Result<String> {
+        if aliases.is_empty() { return Ok(mapping.to_string()); }
+        if let Some(chord) = keyboard::strip_prefix_ignore_case(mapping, consts::HOLD_PREFIX) {
+            return Ok(format!("{}{}", consts::HOLD_PREFIX, Self::expand_aliases_in(chord, aliases, stack)?));
+        }
+        let mut chords = Vec::new();
+        let mut rest = mapping;
+        loop {
+            if keyboard::strip_prefix_ignore_case(rest, consts::TEXT_PREFIX).is_some() { chords.push(rest.to_string()); break; }
+            let (chord, tail) = rest.split_once(',').map_or((rest, None), |(c, t)| (c, Some(t)));
+            let mut keys = Vec::new();
+            for key in chord.split('-') {
+                match aliases.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+                    Some((name, value)) => {
+                        if stack.contains(name) { return Err(anyhow!("alias cycle {} -> {}", stack.join(" -> "), name)); }
+                        stack.push(name.clone());
+                        keys.push(Self::expand_aliases_in(value, aliases, stack)?);
+                        stack.pop();
+                    }
+                    None => keys.push(key.to_string()),
+                }
+            }
+            chords.push(keys.join("-"));
+            match tail { Some(t) => rest = t, None => break }
+        }
+        Ok(chords.join(","))
+    }
+
+    /// Returns a copy of the configuration with every alias expanded and the rotation
+    /// actions of knobs repeated `steps_per_detent` times, as it gets programmed
+    ///
+    /// #Arguments
+    /// `cfg` - configuration to expand
+    ///
+    pub fn resolve_aliases(cfg: &Macropad) -> Result<Macropad> {
+        let mut resolved = cfg.clone();
+        resolved.aliases.clear();
+        for (i, layer) in resolved.layers.iter_mut().enumerate() {
+            let buttons = layer.buttons.iter_mut().enumerate().flat_map(|(r, row)| row.iter_mut().enumerate().map(move |(c, btn)| (Position::Key(r as u8 + 1, c as u8 + 1), btn)));
+            let knobs = layer.knobs.iter_mut().enumerate().flat_map(|(k, knob)| [(KnobAction::RotateCCW, &mut knob.ccw), (KnobAction::Press, &mut knob.press), (KnobAction::RotateCW, &mut knob.cw)].into_iter().map(move |(action, btn)| (Position::Knob(k as u8 + 1, action), btn)));
+            for (position, btn) in buttons.chain(knobs) {
+                btn.mapping = Self::expand_aliases(&btn.mapping, &cfg.aliases).context(format!("layer {} {}", i + 1, position))?;
+            }
+            for knob in &mut layer.knobs {
+                for btn in [&mut knob.ccw, &mut knob.cw] { btn.mapping = Self::repeat_mapping(&btn.mapping, knob.steps_per_detent); }
+                knob.steps_per_detent = 1;
+            }
+        }
+        Ok(resolved)
+    }
+
+    /// Returns the mapping sent `steps` times in a row. Mappings that don't parse are
+    /// returned as they are for validation to report
+    ///
+    /// #Arguments
+    /// `mapping` - mapping to repeat
+    /// `steps` - number of times to send it
+    ///
+    fn repeat_mapping(mapping: &str, steps: u8) -> String {
+        match keyboard::key_sequence(mapping) {
+            Ok(presses) if steps > 1 && !mapping.is_empty() => vec![presses.join(","); steps.into()].join(","),
+            _ => mapping.to_string(),
+        }
+    }
+
+    /// Checks that every knob sends its rotation actions 1 to `consts::MAX_STEPS_PER_DETENT` times
+    ///
+    /// #Arguments
+    /// `cfg` - configuration to check
+    ///
+    fn check_knob_steps(cfg: &Macropad) -> Vec<String> {
+        cfg.layers.iter().enumerate().flat_map(|(i, layer)| layer.knobs.iter().enumerate().map(move |(k, knob)| (i, k, knob.steps_per_detent)))
+            .filter(|(_, _, steps)| !(1..=consts::MAX_STEPS_PER_DETENT).contains(steps))
+            .map(|(i, k, steps)| format!("layer {} knob {}: steps per detent must be between 1 and {} (found {})", i+1, k+1, consts::MAX_STEPS_PER_DETENT, steps)).collect()
+    }
+
+    /// Checks the aliases of a configuration: names must be single words that aren't key
+    /// names already and must expand (without cycles) to known keys
+    ///
+    /// #Arguments
+    /// `aliases` - aliases to check
+    ///
+    pub fn check_aliases(aliases: &BTreeMap<String, String>) -> Vec<String> {
+        let mut errors = Vec::new();
+        for (name, value) in aliases {
+            if name.is_empty() || name.contains(|c: char| c == '-' || c == ',' || c == ':' || c.is_whitespace()) { errors.push(format!("alias '{}': names can't be empty or contain -, comma, : or spaces", name)); continue; }
+            let key = keyboard::canonical_key(name);
+            if Self::is_modifier_key(key) || Self::is_regular_key(key) || MediaCode::from_str(key).is_ok() || Self::is_mouse_action(key) { errors.push(format!("alias '{}': {} is already a key", name, name)); continue; }
+            // modifier only aliases (eg. ctl for ctrl) are completed by the key they are used with
+            let expanded = Self::expand_aliases(value, aliases).and_then(|mapping| {
+                if !mapping.is_empty() && mapping.split('-').all(|k| Self::is_modifier_key(keyboard::canonical_key(k))) { return Ok(()); }
+                Self::validate_key_mapping(&Button { mapping, ..Button::new() }, Self::max_key_presses(None)?, None)
+            });
+            if let Err(e) = expanded { errors.push(format!("alias '{}': {:#}", name, e)); }
+        }
+        errors
+    }
+
+    /// Validates the whole configuration and returns every problem found (with its
+    /// location) instead of stopping at the first one
+    pub fn validate_all(cfg: &Macropad, pid: Option<u16>) -> Result<Vec<String>> {
+        let max_programmable_keys = Self::max_key_presses(pid)?;
+        let mut errors = Vec::new();
+        let max_layers = consts::max_layers(pid);
+        if cfg.layers.is_empty() || cfg.layers.len() > max_layers.into() { errors.push(format!("number of layers must be > 0 and <= {} (found {})", max_layers, cfg.layers.len())); }
+        errors.extend(Self::check_led_settings(cfg, pid));
+        errors.extend(Self::check_knob_steps(cfg));
+        if cfg.layers.len() != usize::from(cfg.device.layers) { errors.push(format!("device specifies {} layers but {} are configured", cfg.device.layers, cfg.layers.len())); }
+        let alias_errors = Self::check_aliases(&cfg.aliases);
+        if !alias_errors.is_empty() { errors.extend(alias_errors); return Ok(errors); }
+        let resolved = Self::resolve_aliases(cfg)?;
+        for (i, layer) in resolved.layers.iter().enumerate() {
+            if layer.buttons.len() != usize::from(cfg.device.rows) { errors.push(format!("layer {}: rows mismatch (expected {} found {})", i+1, cfg.device.rows, layer.buttons.len())); }
+            for (j, btn_mapping) in layer.buttons.iter().enumerate() {
+                if btn_mapping.len() != usize::from(cfg.device.cols) { errors.push(format!("layer {} row {}: cols mismatch (expected {} found {})", i+1, j+1, cfg.device.cols, btn_mapping.len())); }
+                for (k, btn) in btn_mapping.iter().enumerate() {
+                    if let Err(e) = Self::validate_key_mapping(btn, max_programmable_keys, pid) { errors.push(format!("layer {} row {} btn {}: {:#}", i+1, j+1, k+1, e)); }
+                }
+            }
+            if layer.knobs.len() != usize::from(cfg.device.knobs) { errors.push(format!("layer {}: knobs mismatch (expected {} found {})", i+1, cfg.device.knobs, layer.knobs.len())); }
+            for (k, knob) in layer.knobs.iter().enumerate() {
+                for (part, btn) in [("ccw", &knob.ccw), ("press", &knob.press), ("cw", &knob.cw)] {
+                    if let Err(e) = Self::validate_key_mapping(btn, max_programmable_keys, pid) { errors.push(format!("layer {} knob {} {}: {:#}", i+1, k+1, part, e)); }
+                }
+            }
+        }
+        if let Err(e) = Self::check_key_budget(&resolved, pid) { errors.push(e.to_string()); }
+        Ok(errors)
+    }
+
+    /// Validates the mapping and delay of a single button against the limits of the
+    /// product id
+    ///
+    /// #Arguments
+    /// `btn` - button to validate
+    /// `pid` - product id to validate against
+    ///
+    pub fn validate_button(btn: &Button, pid: Option<u16>) -> Result<()> {
+        Self::validate_key_mapping(btn, Self::max_key_presses(pid)?, pid)
+    }
+
+    /// Validates a single button after expanding the aliases of its configuration
+    ///
+    /// #Arguments
+    /// `btn` - button to validate
+    /// `aliases` - aliases of the configuration
+    /// `pid` - product id to validate against
+    ///
+    pub fn validate_aliased_button(btn: &Button, aliases: &BTreeMap<String, String>, pid: Option<u16>) -> Result<()> {
+        let mapping = Self::expand_aliases(&btn.mapping, aliases)?;
+        Self::validate_button(&Button { mapping, ..btn.clone() }, pid)
+    }
+
     fn validate_key_mapping(btn: &Button, max_size: usize, pid: Option<u16>) -> Result<()> {
-        let keys: Vec<_> = btn.mapping.split(',').collect();
-        if keys.len() > max_size { return Err(anyhow!("Too many keys")); }
+        if btn.mapping.is_empty() {
+            // unassigned key
+            return Ok(());
+        }
+        let sequence = keyboard::key_sequence(&btn.mapping)?;
+        if keyboard::strip_prefix_ignore_case(&btn.mapping, consts::HOLD_PREFIX).is_some() { println!("Warning - keys can't be held down by the macropad, '{}' is sent as {} repeated presses", btn.mapping, consts::HOLD_REPEATS); }
+        if pid == Some(0x8890) && sequence.iter().any(|k| k.starts_with(consts::WAIT_PREFIX)) { return Err(anyhow!("0x8890 doesn't support {} pauses", consts::WAIT_PREFIX)); }
+        let (keys, _) = keyboard::split_waits(&sequence, btn.delay)?;
+        if keys.len() > max_size {
+            if btn.mapping.to_ascii_lowercase().contains(consts::TEXT_PREFIX) { return Err(anyhow!("text expands to {} key presses but the maximum is {}", keys.len(), max_size)); }
+            return Err(anyhow!("Too many keys"));
+        }
         if max_size == consts::MAX_KEY_PRESSES_8890 {
             if btn.delay > 0 { println!("Warning - 0x8890 doesn't support delay"); }
         } else if btn.delay > consts::MAX_DELAY { return Err(anyhow!("delay too high")); }
+        MouseAction::check_move_steps(&keys)?;
         for (i, k) in keys.iter().enumerate() {
+            if MouseAction::parse_move(k)?.is_some() { continue; }
             let single_key: Vec<_> = k.split('-').collect();
             if max_size == consts::MAX_KEY_PRESSES_8890 && i > 0 && single_key.len() > 1 { return Err(anyhow!("0x8890 only supports mods on first key")); }
-            for sk in single_key {
-                let da_key = Self::uppercase_first(sk);
-                let mut found = false;
-                if Self::is_modifier_key(&da_key) { found = true; }
-                else if Self::is_media_key(&da_key) {
-                    found = true;
-                    if pid == Some(0x8890) {
-                        match da_key.as_str() { "Play" | "Previous" | "Next" | "Mute" | "Volumeup" | "Volumedown" => (), _ => return Err(anyhow!("unsupported media key for 8890")), }
-                    }
+            // a combo is any number of modifiers followed by exactly one key, media key or mouse action
+            let mut base_key = None;
+            for sk in single_key.into_iter().filter(|sk| !sk.is_empty()) {
+                if Self::is_modifier_key(sk) {
+                    if let Some(base) = base_key { return Err(anyhow!("modifier {} must come before {} in {}", sk, base, k)); }
+                    continue;
+                }
+                if let Ok(media) = MediaCode::from_str(sk) {
+                    if pid == Some(0x8890) && !matches!(media, MediaCode::Play | MediaCode::Previous | MediaCode::Next | MediaCode::Mute | MediaCode::VolumeUp | MediaCode::VolumeDown) { return Err(anyhow!("{} is unsupported on 8890", media)); }
                 }
-                else if Self::is_regular_key(&da_key) { found = true; }
-                else if Self::is_mouse_action(&da_key) { found = true; }
-                if !found { return Err(anyhow!("unknown key - {}", sk)); }
+                else if !(Self::is_regular_key(sk) || Self::is_mouse_action(sk)) { return Err(anyhow!("unknown key - {}", sk)); }
+                if let Some(base) = base_key { return Err(anyhow!("multiple base keys ({} and {}) in {}", base, sk, k)); }
+                base_key = Some(sk);
             }
+            if base_key.is_none() { return Err(anyhow!("no base key in {}, modifiers need a key to go with", k)); }
         }
         Ok(())
     }
 
-    fn uppercase_first(data: &str) -> String {
-        let mut result = String::new();
-        let mut first = true;
-        for value in data.chars() {
-            if first { result.push(value.to_ascii_uppercase()); first = false; }
-            else { result.push(value); }
+    fn is_modifier_key(keystr: &str) -> bool { Modifier::from_str(keystr).is_ok() }
+    fn is_regular_key(keystr: &str) -> bool { WellKnownCode::from_str(keystr).is_ok() }
+    fn is_mouse_action(keystr: &str) -> bool { matches!(keystr, "wheelup" | "wheeldown" | "click" | "mclick" | "rclick") }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mapping::{Button, Layer, LedSettings, Macropad, Mapping, RonStyle};
+
+    #[test]
+    fn validate_all_collects_errors() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(2, 3, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-frob".to_string();
+        mp.layers[1].buttons[1][2].mapping = "a".to_string();
+        mp.layers[1].buttons[1][2].delay = 9000;
+        mp.layers[2].knobs[0].cw.mapping = "volumeup".to_string();
+        let errors = Mapping::validate_all(&mp, Some(0x8842))?;
+        println!("{:?}", errors);
+        assert_eq!(errors.len(), 2, "number of errors");
+        assert!(errors[0].starts_with("layer 1 row 1 btn 1"), "first error location");
+        assert!(errors[1].starts_with("layer 2 row 2 btn 3"), "second error location");
+        Ok(())
+    }
+
+    #[test]
+    fn validate_sequences_with_commas() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 4, 0);
+        mp.layers.truncate(1);
+        mp.device.layers = 1;
+        for (i, mapping) in ["comma", "ctrl-c,ctrl-v", "text:a,b,c", "dot,text:x, y"].iter().enumerate() { mp.layers[0].buttons[0][i].mapping = mapping.to_string(); }
+        assert!(Mapping::validate_all(&mp, Some(0x8842))?.is_empty());
+        assert_eq!(crate::keyboard::key_sequence("text:a,b,c")?, vec!["a", "comma", "b", "comma", "c"]);
+        assert_eq!(crate::keyboard::key_sequence("ctrl-c,text:a,b")?, vec!["ctrl-c", "a", "comma", "b"]);
+        assert_eq!(crate::keyboard::key_sequence("move:10,-5,comma")?, vec!["move:10,-5", "comma"]);
+        Ok(())
+    }
+
+    #[test]
+    fn validate_key_aliases() -> anyhow::Result<()> {
+        for (alias, name) in crate::keyboard::KEY_ALIASES {
+            let btn = crate::mapping::Button { delay: 0, mapping: format!("ctrl-{}", alias.to_uppercase()), led_color: None };
+            Mapping::validate_button(&btn, Some(0x8842)).map_err(|e| anyhow::anyhow!("{alias}: {e}"))?;
+            assert_eq!(crate::keyboard::key_sequence(&btn.mapping)?, vec![format!("ctrl-{name}")]);
         }
-        result
+        assert_eq!(crate::keyboard::key_sequence("hold:esc")?[0], "escape");
+        Ok(())
     }
 
-    fn is_modifier_key(keystr: &str) -> bool { Modifier::from_str(keystr).is_ok() }
-    fn is_media_key(keystr: &str) -> bool { MediaCode::from_str(keystr).is_ok() }
-    fn is_regular_key(keystr: &str) -> bool { WellKnownCode::from_str(keystr).is_ok() }
-    fn is_mouse_action(keystr: &str) -> bool { matches!(keystr.to_lowercase().as_str(), "wheelup" | "wheeldown" | "click" | "mclick" | "rclick") }
+    #[test]
+    fn read_migrates_version_0() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("macropad_v0_test_{}.ron", std::process::id()));
+        std::fs::write(&path, r#"(
+            device: (orientation: Normal, rows: 2, cols: 2, knobs: 1, layers: 2),
+            layers: [
+                (buttons: [[(delay: 0, mapping: "a"), (delay: 0, mapping: "b")], [(delay: 0, mapping: "c")]], knobs: []),
+            ],
+        )"#)?;
+        let mp = Mapping::read(&path.to_string_lossy())?;
+        std::fs::remove_file(&path)?;
+        assert_eq!(mp.version, crate::consts::CONFIG_VERSION);
+        assert_eq!(mp.led_settings, Macropad::new(1, 1, 0).led_settings);
+        assert_eq!(mp.layers.len(), 2);
+        assert_eq!(mp.layers[0].buttons[1].len(), 2, "short row padded");
+        assert_eq!(mp.layers[0].buttons[0][1].mapping, "b");
+        assert_eq!(mp.layers[0].knobs.len(), 1);
+        assert!(Mapping::validate_all(&mp, Some(0x8840))?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn knob_steps_per_detent() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 1, 1);
+        mp.layers.truncate(1);
+        mp.device.layers = 1;
+        mp.aliases.insert("louder".to_string(), "volumeup".to_string());
+        mp.layers[0].knobs[0] = ron::from_str(r#"(ccw: (delay: 0, mapping: "text:ab"), press: (delay: 0, mapping: "mute"), cw: (delay: 0, mapping: "louder"))"#)?;
+        assert_eq!(mp.layers[0].knobs[0].steps_per_detent, 1, "old configs send one step");
+        mp.layers[0].knobs[0].steps_per_detent = 3;
+        let resolved = Mapping::resolve_aliases(&mp)?;
+        let knob = &resolved.layers[0].knobs[0];
+        assert_eq!((knob.ccw.mapping.as_str(), knob.press.mapping.as_str(), knob.cw.mapping.as_str()), ("a,b,a,b,a,b", "mute", "volumeup,volumeup,volumeup"));
+        assert_eq!(Mapping::resolve_aliases(&resolved)?.layers[0].knobs[0].cw.mapping, knob.cw.mapping, "resolving twice repeats once");
+        assert!(Mapping::validate(&mp, Some(0x8840)).is_ok());
+        // the repeated action must still fit the key press limit of the device
+        mp.layers[0].knobs[0].cw.mapping = "a,b,c,d,e,f,g,h".to_string();
+        assert!(Mapping::validate(&mp, Some(0x8890)).is_err());
+        mp.layers[0].knobs[0].steps_per_detent = 11;
+        assert_eq!(Mapping::validate_all(&mp, None)?, vec!["layer 1 knob 1: steps per detent must be between 1 and 10 (found 11)"]);
+        Ok(())
+    }
+
+    #[test]
+    fn led_brightness() -> anyhow::Result<()> {
+        let led: LedSettings = ron::from_str("(mode: 1, layer: 1, color: Red)")?;
+        assert_eq!(led.brightness, crate::consts::MAX_LED_BRIGHTNESS, "old configs are at full brightness");
+        let mut mp = Macropad::new(1, 1, 0);
+        mp.layers.truncate(1);
+        mp.device.layers = 1;
+        mp.led_settings = Some(LedSettings { brightness: 40, ..led });
+        assert!(Mapping::validate(&mp, Some(0x8890)).is_ok(), "only a warning");
+        mp.led_settings = Some(LedSettings { brightness: 150, ..led });
+        assert!(Mapping::validate(&mp, Some(0x8840)).is_err());
+        assert_eq!(Mapping::validate_all(&mp, None)?, vec!["LED brightness must be <= 100% (found 150%)"]);
+        Ok(())
+    }
+
+    #[test]
+    fn migrate_keeps_extra_layers_with_mappings() {
+        let mut mp = Macropad::new(1, 1, 0);
+        mp.version = 0;
+        mp.led_settings = None;
+        mp.device.layers = 1;
+        mp.layers[2].buttons[0][0].mapping = "x".to_string();
+        let mp = Mapping::migrate(mp);
+        assert_eq!((mp.device.layers, mp.layers.len()), (3, 3));
+        assert!(mp.led_settings.is_some());
+    }
+
+    #[test]
+    fn count_mappings_over_layers() {
+        let mut mp = Macropad::new(2, 2, 1);
+        assert_eq!(Mapping::count_mappings(&mp), 0);
+        mp.layers[0].buttons[1][1].mapping = "a".to_string();
+        mp.layers[2].knobs[0].press.mapping = "mute".to_string();
+        assert_eq!(Mapping::count_mappings(&mp), 2);
+    }
+
+    #[test]
+    fn validate_waits() {
+        let btn = |mapping: &str, delay: u16| crate::mapping::Button { delay, mapping: mapping.to_string(), led_color: None };
+        assert!(Mapping::validate_button(&btn("ctrl-c,wait:200,ctrl-v", 0), Some(0x8842)).is_ok());
+        assert!(Mapping::validate_button(&btn("a,wait:50,b,wait:50,c", 50), Some(0x8840)).is_ok());
+        assert!(Mapping::validate_button(&btn("ctrl-c,wait:200,ctrl-v", 0), Some(0x8890)).is_err(), "no delays on 0x8890");
+        assert!(Mapping::validate_button(&btn("a,wait:7000,b", 0), Some(0x8842)).is_err(), "above MAX_DELAY");
+        assert!(Mapping::validate_button(&btn("a,wait:10,b,wait:20,c", 0), Some(0x8842)).is_err(), "uneven pauses");
+        assert!(Mapping::validate_button(&btn("a,wait:10,b,c", 0), Some(0x8842)).is_err(), "missing pause");
+        assert!(Mapping::validate_button(&btn("wait:10,a", 0), Some(0x8842)).is_err(), "leading pause");
+        assert!(Mapping::validate_button(&btn("a,wait:10,b", 20), Some(0x8842)).is_err(), "conflicting delay");
+    }
+
+    #[test]
+    fn validate_modifier_order() {
+        let btn = |mapping: &str| crate::mapping::Button { delay: 0, mapping: mapping.to_string(), led_color: None };
+        assert!(Mapping::validate_button(&btn("ctrl-shift-a,ctrl-wheelup,win-volumeup"), Some(0x8842)).is_ok());
+        let err = Mapping::validate_button(&btn("ctrl-"), Some(0x8842)).unwrap_err();
+        assert!(format!("{:#}", err).contains("no base key"), "{err:#}");
+        assert!(Mapping::validate_button(&btn("a,shift"), Some(0x8842)).is_err(), "modifier only");
+        let err = Mapping::validate_button(&btn("a-b"), Some(0x8842)).unwrap_err();
+        assert!(format!("{:#}", err).contains("multiple base keys"), "{err:#}");
+        assert!(Mapping::validate_button(&btn("a-ctrl"), Some(0x8842)).is_err(), "modifier after the key");
+    }
+
+    #[test]
+    fn validate_any_case() -> anyhow::Result<()> {
+        let btn = |mapping: &str| crate::mapping::Button { delay: 0, mapping: mapping.to_string(), led_color: None };
+        for (mappings, expected) in [
+            (["ctrl-c", "CTRL-C", "Ctrl-c", "ctrl-C"], "ctrl-c"),
+            (["volumeup", "VOLUMEUP", "VolumeUp", "VolUp"], "volumeup"),
+            (["shift-wheelup", "SHIFT-WHEELUP", "Shift-WheelUp", "shift-wheelUP"], "shift-wheelup"),
+            (["rclick", "RCLICK", "RClick", "rClick"], "rclick"),
+            (["a,wait:100,esc", "A,WAIT:100,ESC", "a,Wait:100,Escape", "A,wait:100,Esc"], "a,wait:100,escape"),
+        ] {
+            for mapping in mappings {
+                assert!(Mapping::validate_button(&btn(mapping), Some(0x8842)).is_ok(), "{mapping}");
+                assert_eq!(crate::keyboard::key_sequence(mapping)?.join(","), expected, "{mapping}");
+            }
+        }
+        assert_eq!(crate::keyboard::key_sequence("HOLD:Space")?, crate::keyboard::key_sequence("hold:space")?);
+        assert_eq!(crate::keyboard::key_sequence("Text:Hi")?, vec!["shift-h", "i"], "text keeps its case");
+        Ok(())
+    }
+
+    #[test]
+    fn button_mut_ranges() -> anyhow::Result<()> {
+        use crate::keyboard::KnobAction;
+        use crate::mapping::Position;
+        let mut mp = Macropad::new(2, 3, 1);
+        Mapping::button_mut(&mut mp, 2, Position::Key(2, 3))?.mapping = "ctrl-c".to_string();
+        Mapping::button_mut(&mut mp, 1, Position::Knob(1, KnobAction::RotateCW))?.mapping = "volumeup".to_string();
+        assert_eq!(mp.layers[1].buttons[1][2].mapping, "ctrl-c");
+        assert_eq!(mp.layers[0].knobs[0].cw.mapping, "volumeup");
+        let err = Mapping::button_mut(&mut mp, 1, Position::Key(3, 1)).unwrap_err();
+        assert!(err.to_string().contains("2 rows and 3 columns"), "{err}");
+        assert!(Mapping::button_mut(&mut mp, 1, Position::Key(0, 1)).is_err());
+        assert!(Mapping::button_mut(&mut mp, 4, Position::Key(1, 1)).is_err());
+        assert!(Mapping::button_mut(&mut mp, 1, Position::Knob(2, KnobAction::Press)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn validate_8850() {
+        let mut cfg = Macropad::new(1, 3, 1);
+        cfg.layers[0].buttons[0][0].mapping = vec!["a"; crate::consts::MAX_KEY_PRESSES_8850].join(",");
+        assert!(Mapping::validate(&cfg, Some(0x8850)).is_ok());
+        cfg.layers[0].buttons[0][0].mapping = vec!["a"; crate::consts::MAX_KEY_PRESSES_8850 + 1].join(",");
+        assert!(Mapping::validate(&cfg, Some(0x8850)).is_err(), "above the key press limit");
+    }
+
+    #[test]
+    fn validate_numpad_keys() {
+        let mut cfg = Macropad::new(1, 1, 0);
+        for key in ["num0", "num9", "numplus", "numminus", "numasterisk", "numslash", "numenter", "numdot", "numequal", "numlock", "ctrl-num5", "NumPad7"] {
+            cfg.layers[0].buttons[0][0].mapping = key.to_string();
+            assert!(Mapping::validate(&cfg, Some(0x8840)).is_ok(), "{key}");
+        }
+        cfg.layers[0].buttons[0][0].mapping = "num10".to_string();
+        assert!(Mapping::validate(&cfg, Some(0x8840)).is_err());
+    }
+
+    #[test]
+    fn validate_layers_per_pid() {
+        let mut cfg = Macropad::new(1, 3, 1);
+        assert!(Mapping::validate(&cfg, Some(0x8840)).is_ok(), "3 layers on 0x8840");
+        let err = Mapping::validate(&cfg, Some(0x8890)).unwrap_err();
+        assert!(format!("{:#}", err).contains("number of layers must be > 0 and <= 1"), "{err:#}");
+        cfg.layers.truncate(1);
+        cfg.device.layers = 1;
+        assert!(Mapping::validate(&cfg, Some(0x8890)).is_ok(), "1 layer on 0x8890");
+        assert!(Mapping::validate(&cfg, Some(0x8840)).is_ok(), "1 layer on 0x8840");
+    }
+
+    #[test]
+    fn validate_mouse_move_alone() {
+        let btn = |mapping: &str| Button { delay: 0, mapping: mapping.to_string(), led_color: None };
+        assert!(Mapping::validate_button(&btn("move:5,5"), Some(0x8840)).is_ok());
+        assert!(Mapping::validate_button(&btn("move:5,5,click,wheelup"), Some(0x8840)).is_ok(), "other mouse actions");
+        let err = Mapping::validate_button(&btn("ctrl-c,move:5,5"), Some(0x8840)).unwrap_err();
+        assert!(err.to_string().contains("move: can only be combined with mouse actions, not with ctrl-c"), "{err}");
+    }
+
+    #[test]
+    fn validate_consumer_keys() {
+        let mut cfg = Macropad::new(1, 1, 0);
+        cfg.layers.truncate(1);
+        cfg.device.layers = 1;
+        for key in ["sleep", "syspower", "calculator", "search", "mycomputer", "mail", "webpagehome"] {
+            cfg.layers[0].buttons[0][0].mapping = key.to_string();
+            assert!(Mapping::validate(&cfg, Some(0x8840)).is_ok(), "{key}");
+            let err = Mapping::validate(&cfg, Some(0x8890)).unwrap_err();
+            assert!(format!("{:#}", err).contains(&format!("{key} is unsupported on 8890")), "{err:#}");
+        }
+        cfg.layers[0].buttons[0][0].mapping = "volumeup".to_string();
+        assert!(Mapping::validate(&cfg, Some(0x8890)).is_ok());
+        use std::str::FromStr;
+        assert_eq!(crate::keyboard::MediaCode::from_str("syspower"), Ok(crate::keyboard::MediaCode::SystemPower), "syspower is the consumer key");
+        assert_eq!(crate::keyboard::WellKnownCode::from_str("power").map(|w| w as u8), Ok(0x66), "power stays the keyboard key");
+        cfg.layers[0].buttons[0][0].mapping = "power".to_string();
+        assert!(Mapping::validate(&cfg, Some(0x8890)).is_ok(), "power still validates on 0x8890");
+    }
+
+    #[test]
+    fn read_inconsistent_dimensions() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("macropad_inconsistent_{}.ron", std::process::id()));
+        let path = path.to_string_lossy();
+        let mut mp = Macropad::new(2, 3, 1);
+        Mapping::save(&mp, &path)?;
+        assert!(Mapping::read(&path).is_ok());
+        mp.device.rows = 3;
+        Mapping::save(&mp, &path)?;
+        assert!(format!("{:#}", Mapping::read(&path).unwrap_err()).contains("layer 1 has 2 rows of buttons but device.rows is 3"));
+        mp.device.rows = 2;
+        mp.layers[1].buttons[1].pop();
+        Mapping::save(&mp, &path)?;
+        assert!(format!("{:#}", Mapping::read(&path).unwrap_err()).contains("layer 2 row 2 has 2 buttons but device.cols is 3"));
+        mp.layers[1].buttons[1].push(Button::new());
+        mp.layers[2].knobs.clear();
+        Mapping::save(&mp, &path)?;
+        assert!(format!("{:#}", Mapping::read(&path).unwrap_err()).contains("layer 3 has 0 knobs but device.knobs is 1"));
+        mp.layers.pop();
+        Mapping::save(&mp, &path)?;
+        assert!(format!("{:#}", Mapping::read(&path).unwrap_err()).contains("device.layers is 3 but there are 2 layers"));
+        std::fs::remove_file(path.as_ref())?;
+        Ok(())
+    }
+
+    #[test]
+    fn aliases() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 2, 0);
+        mp.aliases = [("ctl", "ctrl"), ("ret", "enter"), ("copy", "ctl-c"), ("paste", "CTL-v")].into_iter().map(|(n, m)| (n.to_string(), m.to_string())).collect();
+        assert_eq!(Mapping::expand_aliases("copy,wait:100,paste,ret", &mp.aliases)?, "ctrl-c,wait:100,ctrl-v,enter");
+        assert_eq!(Mapping::expand_aliases("hold:ctl-a", &mp.aliases)?, "hold:ctrl-a");
+        assert_eq!(Mapping::expand_aliases("Ret,text:copy,ret", &mp.aliases)?, "enter,text:copy,ret");
+        mp.layers[0].buttons[0][0].mapping = "copy,ret".to_string();
+        mp.layers[0].buttons[0][1].mapping = "shift-ret".to_string();
+        assert!(Mapping::validate(&mp, Some(0x8840)).is_ok());
+        assert_eq!(Mapping::resolve_aliases(&mp)?.layers[0].buttons[0][0].mapping, "ctrl-c,enter");
+        assert!(Mapping::validate_aliased_button(&Button { mapping: "ctl-z".to_string(), ..Button::new() }, &mp.aliases, None).is_ok());
+        let mut cyclic = mp.aliases.clone();
+        cyclic.insert("a1".to_string(), "a2".to_string());
+        cyclic.insert("a2".to_string(), "shift-a1".to_string());
+        assert!(Mapping::expand_aliases("a1", &cyclic).unwrap_err().to_string().contains("alias cycle a1 -> a2 -> a1"));
+        let mut bad = mp.aliases.clone();
+        bad.insert("oops".to_string(), "ctrl-frob".to_string());
+        bad.insert("esc".to_string(), "q".to_string());
+        let errors = Mapping::check_aliases(&bad);
+        assert!(errors.iter().any(|e| e.contains("alias 'oops': unknown key - frob")), "{errors:?}");
+        assert!(errors.iter().any(|e| e.contains("alias 'esc': esc is already a key")), "{errors:?}");
+        mp.aliases = bad;
+        assert!(Mapping::validate(&mp, Some(0x8840)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn backup_prunes_oldest() -> anyhow::Result<()> {
+        let dir = std::env::temp_dir().join(format!("macropad_backup_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        for ts in [100, 300, 200, 400] { std::fs::write(dir.join(format!("pad.backup.{ts}.ron")), "")?; }
+        std::fs::write(dir.join("pad.ron"), "")?;
+        Mapping::prune_backups(&dir, "pad", 2)?;
+        let mut left: Vec<String> = std::fs::read_dir(&dir)?.map(|e| e.unwrap().file_name().to_string_lossy().to_string()).collect();
+        left.sort();
+        std::fs::remove_dir_all(&dir)?;
+        assert_eq!(left, vec!["pad.backup.300.ron", "pad.backup.400.ron", "pad.ron"]);
+        Ok(())
+    }
+
+    #[test]
+    fn export_diagram_aligns_columns() {
+        let mut mp = Macropad::new(2, 2, 1);
+        mp.layers.truncate(1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-c".to_string();
+        mp.layers[0].buttons[1][1].mapping = "a".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "volumeup".to_string();
+        let diagram = Mapping::export_diagram(&mp);
+        println!("{diagram}");
+        let lines: Vec<&str> = diagram.lines().collect();
+        assert_eq!(lines[2], "Layer 1");
+        assert_eq!(lines[3], "+--------+---+");
+        assert_eq!(lines[4], "| ctrl-c |   |");
+        assert_eq!(lines[6], "|        | a |");
+        assert_eq!(lines[8], "Knob 1: CCW []  Press []  CW [volumeup]");
+    }
+
+    #[test]
+    fn diff_reports_changes_and_size_mismatch() {
+        let a = Macropad::new(2, 3, 1);
+        let mut b = Macropad::new(3, 3, 1);
+        b.layers[0].buttons[0][1].mapping = "ctrl-c".to_string();
+        b.layers.pop();
+        let diffs = Mapping::diff(&a, &b);
+        println!("{:?}", diffs);
+        assert!(diffs.contains(&"device grid: 2x3 -> 3x3".to_string()));
+        assert!(diffs.contains(&"layer 1 row 1 btn 2: '' delay 0 -> 'ctrl-c' delay 0".to_string()));
+        assert!(diffs.contains(&"layer 1 row 3 btn 1: added '' delay 0".to_string()));
+        assert!(diffs.contains(&"layer 3: removed".to_string()));
+        assert!(Mapping::diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn validate_all_hold() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 3, 0);
+        mp.layers.truncate(1);
+        mp.device.layers = 1;
+        mp.layers[0].buttons[0][0].mapping = "hold:space".to_string();
+        mp.layers[0].buttons[0][1].mapping = "hold:ctrl-shift".to_string();
+        mp.layers[0].buttons[0][2].mapping = "hold:frob".to_string();
+        let errors = Mapping::validate_all(&mp, Some(0x8890))?;
+        println!("{:?}", errors);
+        assert_eq!(errors.len(), 2, "number of errors");
+        assert!(errors[0].contains("hold needs a key"), "modifier only hold");
+        assert!(errors[1].contains("unknown key in hold"), "unknown key hold");
+        Ok(())
+    }
+
+    #[test]
+    fn duplicate_mappings_in_layer() {
+        let mut mp = Macropad::new(2, 2, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-c".to_string();
+        mp.layers[0].buttons[1][1].mapping = "ctrl-c".to_string();
+        mp.layers[0].knobs[0].press.mapping = "ctrl-c".to_string();
+        mp.layers[0].buttons[0][1].mapping = "a".to_string();
+        mp.layers[1].buttons[0][0].mapping = "a".to_string();
+        let warnings = Mapping::duplicate_warnings(&mp);
+        assert_eq!(warnings, vec!["layer 1: 'ctrl-c' is mapped to row 1 btn 1, row 2 btn 2, knob 1 press"]);
+    }
+
+    #[test]
+    fn save_and_read_json() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("macropad_json_test_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let mut mp = Macropad::new(1, 2, 1);
+        mp.layers[0].buttons[0][1].mapping = "ctrl-v".to_string();
+        Mapping::save(&mp, &path)?;
+        let text = std::fs::read_to_string(&path)?;
+        let read = Mapping::read(&path)?;
+        std::fs::remove_file(&path)?;
+        assert!(text.trim_start().starts_with('{'), "saved as json");
+        assert!(Mapping::diff(&mp, &read).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn import_via_keymap() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("macropad_via_test_{}.json", std::process::id()));
+        std::fs::write(&path, r#"{
+            "matrix": { "rows": 1, "cols": 3 },
+            "layers": [["KC_A", "LCTL(KC_C)", "KC_NO"], ["KC_MPLY", "RGB_TOG", "LSFT(KC_F5)"]],
+            "encoders": [[["KC_VOLD", "KC_VOLU"]], [["KC_TRNS", "KC_WH_D"]]]
+        }"#)?;
+        let (mp, warnings) = Mapping::import_via(&path.to_string_lossy())?;
+        std::fs::remove_file(&path)?;
+        assert_eq!((mp.device.rows, mp.device.cols, mp.device.knobs, mp.device.layers), (1, 3, 1, 2));
+        let mappings: Vec<&str> = mp.layers[0].buttons[0].iter().chain(&mp.layers[1].buttons[0]).map(|b| b.mapping.as_str()).collect();
+        assert_eq!(mappings, vec!["a", "ctrl-c", "", "play", "", "shift-f5"]);
+        assert_eq!(mp.layers[0].knobs[0].ccw.mapping, "volumedown");
+        assert_eq!(mp.layers[1].knobs[0].cw.mapping, "wheeldown");
+        assert_eq!(warnings, vec!["layer 2 row 1 btn 2: unsupported keycode RGB_TOG"]);
+        assert!(Mapping::validate_all(&mp, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn csv_round_trip() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(2, 2, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-c,ctrl-v".to_string();
+        mp.layers[0].buttons[1][1].mapping = "text:say \"hi\"".to_string();
+        mp.layers[1].knobs[0].cw.mapping = "volumeup".to_string();
+        let csv = Mapping::export_csv(&mp);
+        assert!(csv.starts_with("\"ctrl-c,ctrl-v\",\n,\"text:say \"\"hi\"\"\"\n,,\n"), "{csv}");
+        let path = std::env::temp_dir().join(format!("macropad_csv_test_{}.csv", std::process::id()));
+        std::fs::write(&path, &csv)?;
+        let read = Mapping::import_csv(&path.to_string_lossy(), 2, 2, 1)?;
+        assert_eq!(read.device.layers, 3);
+        assert!(Mapping::diff(&mp, &read).is_empty());
+        assert!(Mapping::import_csv(&path.to_string_lossy(), 2, 2, 2).is_err(), "9 lines aren't layers of 4");
+        std::fs::write(&path, "a,ctrl-frob\nb\nvolumedown,mute,volumeup,\n")?;
+        let err = Mapping::import_csv(&path.to_string_lossy(), 2, 2, 1).unwrap_err().to_string();
+        std::fs::remove_file(&path)?;
+        assert!(err.starts_with("line 1 column 2 (layer 1 row 1 btn 2):"), "{err}");
+        assert_eq!(err.lines().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn export_qmk_keymap() {
+        let mut mp = Macropad::new(1, 3, 1);
+        mp.layers[0].buttons[0][0].mapping = "a".to_string();
+        mp.layers[0].buttons[0][1].mapping = "ctrl-shift-Escape".to_string();
+        mp.layers[0].buttons[0][2].mapping = "text:a*/b".to_string();
+        mp.layers[0].knobs[0].ccw.mapping = "prev".to_string();
+        mp.layers[0].knobs[0].cw.mapping = "wheeldown".to_string();
+        mp.layers[0].knobs[0].press.mapping = "num5".to_string();
+        let qmk = Mapping::export_qmk(&mp);
+        assert!(qmk.contains("const uint16_t PROGMEM keymaps[][1][3] = {"));
+        assert!(qmk.contains("{KC_A, LCTL(LSFT(KC_ESC)), KC_NO /* TODO: text:a* /b */},"));
+        assert!(qmk.contains("// knob 1 press: KC_P5"));
+        assert!(qmk.contains("[0] = {ENCODER_CCW_CW(KC_MPRV, KC_NO /* TODO: wheeldown */)},"));
+    }
+
+    #[test]
+    fn validate_all_empty_config() -> anyhow::Result<()> {
+        let mp = Macropad::new(2, 3, 1);
+        assert!(Mapping::validate_all(&mp, None)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn mirror_layer() {
+        let mut layer = Layer::new(2, 3, 2);
+        for (r, row) in layer.buttons.iter_mut().enumerate() { for (c, btn) in row.iter_mut().enumerate() { btn.mapping = format!("{}{}", r, c); btn.delay = (r * 3 + c) as u16; } }
+        layer.knobs[0].press.mapping = "mute".to_string();
+        layer.mirror_horizontal();
+        let mappings = |layer: &Layer| layer.buttons.iter().map(|row| row.iter().map(|b| b.mapping.as_str()).collect::<Vec<_>>().join(" ")).collect::<Vec<_>>();
+        assert_eq!(mappings(&layer), ["02 01 00", "12 11 10"]);
+        assert_eq!(layer.buttons[1][0].delay, 5);
+        assert_eq!(layer.knobs[1].press.mapping, "mute");
+        layer.mirror_vertical();
+        assert_eq!(mappings(&layer), ["12 11 10", "02 01 00"]);
+        assert_eq!(layer.knobs[1].press.mapping, "mute");
+    }
+
+    #[test]
+    fn key_press_budget() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(3, 4, 2);
+        let fill = |mp: &mut Macropad, mapping: &str| for layer in &mut mp.layers {
+            layer.buttons.iter_mut().flatten().for_each(|b| b.mapping = mapping.to_string());
+            layer.knobs.iter_mut().flat_map(|k| [&mut k.ccw, &mut k.press, &mut k.cw]).for_each(|b| b.mapping = mapping.to_string());
+        };
+        // 3 layers of 12 keys and 6 knob actions
+        fill(&mut mp, "a,b,c,d,e,f,g,h,i");
+        assert_eq!(Mapping::key_presses(&mp), 54 * 9);
+        assert!(Mapping::check_key_capacity(&mp, 0x8840, 512).is_ok());
+        fill(&mut mp, "a,wait:10,b,wait:10,c,wait:10,d,wait:10,e,wait:10,f,wait:10,g,wait:10,h,wait:10,i,wait:10,j");
+        assert_eq!(Mapping::key_presses(&mp), 540);
+        let err = Mapping::check_key_capacity(&mp, 0x8840, 512).unwrap_err().to_string();
+        assert!(err.contains("540 key presses in total but 0x8840 stores at most 512 (28 over budget)"), "{err}");
+        // product ids without a measured capacity aren't checked
+        assert!(Mapping::validate(&mp, Some(0x8840)).is_ok());
+        assert!(Mapping::validate(&mp, None).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn describe_mappings() {
+        use crate::keyboard::describe_mapping;
+        assert_eq!(describe_mapping(""), "unassigned");
+        assert_eq!(describe_mapping("ctrl-shift-esc"), "Ctrl + Shift + Escape");
+        assert_eq!(describe_mapping("rctrl-pgup"), "Right Ctrl + Page Up");
+        assert_eq!(describe_mapping("volumeup"), "Volume Up");
+        assert_eq!(describe_mapping("numpad7"), "Numpad 7");
+        assert_eq!(describe_mapping("ctrl-c,wait:100,ctrl-v"), "1. Ctrl + C\n2. Wait 100 ms\n3. Ctrl + V");
+        assert_eq!(describe_mapping("move:10,-5,click"), "1. Move the mouse by 10, -5\n2. Left Click");
+        assert_eq!(describe_mapping("ctrl-a,text:a,b"), "1. Ctrl + A\n2. Type \"a,b\"");
+        assert_eq!(describe_mapping("hold:shift-w"), "Hold Shift + W (sent as 5 presses)");
+        assert_eq!(describe_mapping("f13-frob"), "F13 + frob");
+    }
+
+    #[test]
+    fn layer_names() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(1, 2, 0);
+        mp.layers[1].name = Some("Media".to_string());
+        mp.layers[2].name = Some(" ".to_string());
+        assert_eq!(mp.layers[0].title(0), "Layer 1");
+        assert_eq!(mp.layers[1].title(1), "Media");
+        assert_eq!(mp.layers[2].title(2), "Layer 3");
+        let path = std::env::temp_dir().join(format!("macropad_names_{}.ron", std::process::id()));
+        let path = path.to_string_lossy();
+        Mapping::save(&mp, &path)?;
+        let written = std::fs::read_to_string(path.as_ref())?;
+        assert_eq!(written.matches("name:").count(), 2);
+        let read = Mapping::read(&path)?;
+        assert_eq!(read.layers[0].name, None);
+        assert_eq!(read.layers[1].name.as_deref(), Some("Media"));
+        std::fs::remove_file(path.as_ref())?;
+        Ok(())
+    }
+
+    #[test]
+    fn ron_styles() -> anyhow::Result<()> {
+        let mut mp = Macropad::new(2, 2, 1);
+        mp.layers[0].buttons[1][0].mapping = "play".to_string();
+        let path = std::env::temp_dir().join(format!("macropad_style_{}.ron", std::process::id()));
+        let path = path.to_string_lossy();
+        Mapping::save_styled(&mp, &path, RonStyle { depth: 0, enumerate_arrays: true })?;
+        let written = std::fs::read_to_string(path.as_ref())?;
+        assert!(written.contains("/*[1]*/"), "{written}");
+        assert_eq!(Mapping::read(&path)?.layers[0].buttons[1][0].mapping, "play");
+        Mapping::save(&mp, &path)?;
+        let default = std::fs::read_to_string(path.as_ref())?;
+        assert!(!default.contains("/*[1]*/"));
+        assert!(default.lines().count() < written.lines().count());
+        std::fs::remove_file(path.as_ref())?;
+        Ok(())
+    }
+
+    #[test]
+    fn export_import_layer() -> anyhow::Result<()> {
+        let mut layer = Layer::new(2, 2, 1);
+        layer.buttons[1][0].mapping = "play".to_string();
+        layer.knobs[0].cw.mapping = "volumeup".to_string();
+        for ext in ["ron", "json"] {
+            let path = std::env::temp_dir().join(format!("macropad_layer_{}.{ext}", std::process::id()));
+            let path = path.to_string_lossy();
+            Mapping::export_layer(&layer, &path)?;
+            let imported = Mapping::import_layer(&path)?;
+            assert_eq!(imported.buttons.len(), 2);
+            assert_eq!(imported.buttons[1][0].mapping, "play");
+            assert_eq!(imported.knobs[0].cw.mapping, "volumeup");
+            std::fs::remove_file(path.as_ref())?;
+        }
+        let config = std::env::temp_dir().join(format!("macropad_not_layer_{}.ron", std::process::id()));
+        Mapping::save(&Macropad::new(1, 1, 0), &config.to_string_lossy())?;
+        assert!(Mapping::import_layer(&config.to_string_lossy()).is_err());
+        std::fs::remove_file(config)?;
+        Ok(())
+    }
+
+    #[test]
+    fn analyze_findings() {
+        let mut mp = Macropad::new(2, 2, 1);
+        mp.layers[0].buttons[0][0].mapping = "ctrl-c".to_string();
+        mp.layers[0].buttons[0][1].mapping = "ctrl-v".to_string();
+        mp.layers[1].buttons[0][0].mapping = "ctrl-c".to_string();
+        mp.layers[1].buttons[1][1].mapping = "ctrl-v".to_string();
+        mp.layers[1].buttons[1][0].mapping = "ctrl-v".to_string();
+        mp.layers[2].knobs[0].ccw.mapping = "wheelup".to_string();
+        mp.layers[2].knobs[0].cw.mapping = "wheelup".to_string();
+        let findings: Vec<String> = mp.analyze().iter().map(|f| f.to_string()).collect();
+        assert_eq!(findings, [
+            "layer 2 row 2 btn 1: 'ctrl-v' is also mapped to row 2 btn 2",
+            "layer 2 row 2 btn 1: 'ctrl-v' is at row 1 btn 2 on layer 1",
+            "layer 2 row 2 btn 2: 'ctrl-v' is also mapped to row 2 btn 1",
+            "layer 2 row 2 btn 2: 'ctrl-v' is at row 1 btn 2 on layer 1",
+            "layer 3 knob 1 ccw: 'wheelup' is also mapped to knob 1 cw",
+            "layer 3 knob 1 cw: 'wheelup' is also mapped to knob 1 ccw",
+            "layer 3 knob 1 cw: turning the knob either way sends 'wheelup'",
+        ]);
+        assert!(Macropad::new(2, 2, 1).analyze().is_empty());
+    }
 }
@@ -1,7 +1,11 @@
-use crate::consts::VENDOR_ID;
-use crate::keyboard::LedColor;
+use crate::consts::{self, VENDOR_ID};
+use crate::keyboard::{KnobAction, LedColor};
+use crate::layout::HostLayout;
+use crate::mapping::RonStyle;
 use crate::parse;
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::io::Write;
 use std::num::ParseIntError;
 
 #[derive(Parser)]
@@ -9,11 +13,15 @@ pub struct Options {
     #[command(subcommand)]
     pub command: Command,
 
+    /// Keyboard layout of the computer, for the keys pressed by text: mappings
+    #[arg(long, global = true, value_enum)]
+    pub host_layout: Option<HostLayout>,
+
     #[clap(flatten)]
     pub devel_options: DevelOptions,
 }
 
-#[derive(Args, Debug)]
+#[derive(Args, Debug, Clone)]
 #[clap(
     hide(true),
     next_help_heading = "Development options (use with caution!)"
@@ -68,6 +76,10 @@ pub fn u8_hex_or_decimal(s: &str) -> Result<u8, ParseIntError> {
     }
 }
 
+fn parse_position(s: &str) -> std::result::Result<(u8, u8), nom::error::Error<String>> {
+    parse::from_str(parse::position, s)
+}
+
 fn parse_address(s: &str) -> std::result::Result<(u8, u8), nom::error::Error<String>> {
     parse::from_str(parse::address, s)
 }
@@ -79,7 +91,7 @@ pub enum Command {
 
     /// Validate key mappings config
     Validate {
-        /// Configuration file in ron format
+        /// Configuration file in ron format (- reads from stdin)
         #[clap(short, long, default_value = "./mapping.ron")]
         config_file: String,
 
@@ -92,11 +104,113 @@ pub enum Command {
         device_connected: bool,
     },
 
+    /// Change the mapping of a single key or knob action in a configuration file
+    Set {
+        /// Configuration file in ron format
+        #[clap(short, long, visible_alias = "file", default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Layer of the key or knob (one based)
+        #[clap(short, long, default_value_t = 1)]
+        layer: u8,
+
+        /// Row and column of the key (one based), eg. 2,3
+        #[clap(long, value_parser=parse_position, required_unless_present = "knob", conflicts_with = "knob")]
+        pos: Option<(u8, u8)>,
+
+        /// Knob to change (one based)
+        #[clap(long, requires = "part")]
+        knob: Option<u8>,
+
+        /// Action of the knob to change
+        #[arg(long, value_enum, requires = "knob")]
+        part: Option<KnobAction>,
+
+        /// New mapping, an empty string clears it
+        #[clap(short, long)]
+        mapping: String,
+
+        /// Delay between key presses in milliseconds
+        #[clap(short, long, default_value_t = 0)]
+        delay: u16,
+
+        /// Product ID to validate the mapping against (each product differs)
+        #[clap(short, long, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+
+        #[clap(flatten)]
+        ron_style: RonStyle,
+    },
+
     /// Program key mappings
     Program {
+        /// Configuration file in ron format (- reads from stdin)
+        #[clap(short, long, visible_alias = "file", default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Program a configuration even if (almost) no keys are mapped, clearing the device
+        #[clap(long, default_value_t = false)]
+        allow_empty: bool,
+
+        /// Print the packets that would be sent instead of programming the device
+        #[clap(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Program every configuration (.ron or .json) in a directory, one after the other on a
+    /// single macropad or one per macropad when several are connected
+    BatchProgram {
+        /// Directory with the configuration files
+        dir: String,
+
+        /// Program configurations even if (almost) no keys are mapped, clearing the device
+        #[clap(long, default_value_t = false)]
+        allow_empty: bool,
+    },
+
+    /// Unbind every key and knob action on every layer of the device, eg. before selling it
+    Clear {
+        /// Don't ask for confirmation
+        #[clap(long, default_value_t = false)]
+        yes: bool,
+    },
+
+    /// Print the USB packets that program a configuration, as hex, without writing them
+    DumpPackets {
+        /// Configuration file in ron format (- reads from stdin)
+        #[clap(short, long, visible_alias = "file", default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Product ID to build the packets for (defaults to the connected device)
+        #[clap(short, long, value_parser=u16_hex_or_decimal)]
+        product_id: Option<u16>,
+    },
+
+    /// Perform the actions of a configuration on this computer instead of programming
+    /// the macropad, to try out macros
+    Simulate {
+        /// Configuration file in ron format (- reads from stdin)
+        #[clap(short, long, visible_alias = "file", default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// Layer to simulate (one based)
+        #[clap(short, long, default_value_t = 1)]
+        layer: u8,
+
+        /// Seconds to wait before performing an action, to focus the target window
+        #[clap(long, default_value_t = consts::SIMULATE_COUNTDOWN)]
+        countdown: u64,
+    },
+
+    /// Program key mappings every time the configuration file changes
+    Watch {
         /// Configuration file in ron format
-        #[clap(short, long, default_value = "./mapping.ron")]
+        #[clap(short, long, visible_alias = "file", default_value = "./mapping.ron")]
         config_file: String,
+
+        /// Program a configuration even if (almost) no keys are mapped, clearing the device
+        #[clap(long, default_value_t = false)]
+        allow_empty: bool,
     },
 
     /// Read configuration from device
@@ -104,19 +218,141 @@ pub enum Command {
         /// Layer to read data for (layer is one based; 0 reads all layers)
         #[clap(short, long, default_value_t = 0)]
         layer: u8,
+
+        #[clap(flatten)]
+        ron_style: RonStyle,
     },
 
     /// Select LED backlight mode
     Led(LedCommand),
 
+    /// Save the configuration of the device to a file (ron, or json by extension)
+    Dump {
+        /// File to write the configuration to
+        file: String,
+
+        /// Overwrite the file if it already exists
+        #[clap(long, default_value_t = false)]
+        force: bool,
+
+        #[clap(flatten)]
+        ron_style: RonStyle,
+    },
+
+    /// Convert a VIA keymap (json) into a configuration file
+    ImportVia {
+        /// VIA keymap file
+        via_file: String,
+
+        /// Configuration file in ron format to create
+        config_file: String,
+
+        /// Overwrite the configuration file if it already exists
+        #[clap(long, default_value_t = false)]
+        force: bool,
+
+        #[clap(flatten)]
+        ron_style: RonStyle,
+    },
+
+    /// Convert a CSV grid of mappings into a configuration file
+    ImportCsv {
+        /// CSV file, a line per row of keys followed by a line per knob (ccw,press,cw), layer after layer
+        csv_file: String,
+
+        /// Configuration file in ron format to create
+        config_file: String,
+
+        /// Rows of keys of a layer
+        #[clap(long)]
+        rows: u8,
+
+        /// Keys of a row
+        #[clap(long)]
+        cols: u8,
+
+        /// Knobs of a layer
+        #[clap(long, default_value_t = 0)]
+        knobs: u8,
+
+        /// Overwrite the configuration file if it already exists
+        #[clap(long, default_value_t = false)]
+        force: bool,
+
+        #[clap(flatten)]
+        ron_style: RonStyle,
+    },
+
+    /// Export key mappings as a diagram
+    Export {
+        /// Configuration file in ron format (- reads from stdin)
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+
+        /// File to write the diagram to
+        file: String,
+
+        /// Format of the diagram
+        #[arg(short, long, value_enum, default_value_t = ExportFormat::Text)]
+        format: ExportFormat,
+    },
+
+    /// Compare two configuration files
+    Diff {
+        /// Original configuration file in ron format
+        a: String,
+
+        /// Configuration file in ron format to compare against
+        b: String,
+    },
+
+    /// Look for bindings that are likely mistakes, such as a command mapped twice on a layer
+    Lint {
+        /// Configuration file in ron format (- reads from stdin)
+        #[clap(short, long, default_value = "./mapping.ron")]
+        config_file: String,
+    },
+
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate the completions for
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+
     /// Show GUI
     ShowGui,
 }
 
+/// Output formats for the export command
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// ASCII boxes
+    Text,
+    /// Markdown tables
+    Markdown,
+    /// QMK keymap in C
+    Qmk,
+    /// CSV, a line per row of keys and per knob (ccw,press,cw)
+    Csv,
+}
+
+/// Writes the completion script for all commands and options
+///
+/// #Arguments
+/// `shell` - shell to generate the completions for
+/// `out` - where to write the script
+///
+pub fn write_completions(shell: Shell, out: &mut dyn Write) {
+    let mut cmd = Options::command();
+    let name = cmd.get_name().to_string();
+    clap_complete::generate(shell, &mut cmd, name, out);
+}
+
 #[derive(Parser, Clone, Default, Debug)]
 pub struct LedCommand {
     /// Index of LED modes
-    /// --------0x8840----------
+    /// --------0x8840, 0x8842 and 0x8850----------
     /// 0 - LEDs off
     /// 1 - backlight always on with LedColor
     /// 2 - no backlight, shock with LedColor when key pressed
@@ -141,4 +377,24 @@ pub struct LedCommand {
     /// Color to apply with mode
     #[arg(value_enum, verbatim_doc_comment)]
     pub led_color: Option<LedColor>,
+
+    /// Brightness in percent, not sent to the device yet
+    #[clap(long, default_value_t = consts::MAX_LED_BRIGHTNESS, value_parser = clap::value_parser!(u8).range(0..=consts::MAX_LED_BRIGHTNESS as i64))]
+    pub brightness: u8,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn completions_for_all_shells() {
+        for shell in Shell::value_variants() {
+            let mut out = Vec::new();
+            write_completions(*shell, &mut out);
+            let script = String::from_utf8(out).unwrap();
+            assert!(script.contains("macropad-tool"), "{shell}");
+            assert!(script.contains("import-via"), "{shell}");
+        }
+    }
 }
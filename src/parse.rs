@@ -20,6 +20,12 @@ pub fn address(input: &str) -> IResult<&str, (u8, u8)> {
     separated_pair(byte, char(':'), map_res(digit1, u8::from_str)).parse(input)
 }
 
+/// Parses a string like "2,3" into (u8, u8)
+pub fn position(input: &str) -> IResult<&str, (u8, u8)> {
+    let byte = map_res(digit1, u8::from_str);
+    separated_pair(byte, char(','), map_res(digit1, u8::from_str)).parse(input)
+}
+
 /// Runs a parser and ensures the entire input is consumed
 pub fn parse<'a, O, E, P>(parser: P, input: &'a str) -> Result<O, E>
 where
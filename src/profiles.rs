@@ -0,0 +1,113 @@
+use crate::consts;
+use crate::mapping::{Macropad, Mapping};
+use anyhow::{ensure, Context, Result};
+use std::path::PathBuf;
+
+/// Returns the directory holding the profiles. `MACROPAD_PROFILES` overrides the
+/// default of `macropad-tool/profiles` in the user's configuration directory
+///
+pub fn profiles_dir() -> PathBuf {
+    if let Some(dir) = std::env::var_os(consts::PROFILES_DIR_ENV) {
+        return PathBuf::from(dir);
+    }
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| std::env::var_os("APPDATA"))
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_default();
+    base.join("macropad-tool").join("profiles")
+}
+
+/// Returns the file of a profile, rejecting names that would leave the profiles
+/// directory
+///
+/// #Arguments
+/// `name` - name of the profile
+///
+fn profile_path(name: &str) -> Result<PathBuf> {
+    let name = name.trim();
+    ensure!(!name.is_empty(), "profile name is empty");
+    ensure!(
+        !name.starts_with('.') && !name.contains(['/', '\\', ':']),
+        "invalid profile name - {name}"
+    );
+    Ok(profiles_dir().join(format!("{name}.ron")))
+}
+
+/// Returns the names of the saved profiles in alphabetical order
+///
+pub fn list_profiles() -> Result<Vec<String>> {
+    let dir = profiles_dir();
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(&dir).context(format!("reading {}", dir.display()))? {
+        let path = entry?.path();
+        if path.extension().is_some_and(|e| e == "ron") {
+            if let Some(stem) = path.file_stem() {
+                names.push(stem.to_string_lossy().to_string());
+            }
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+/// Loads the configuration saved under a profile name
+///
+/// #Arguments
+/// `name` - name of the profile
+///
+pub fn load_profile(name: &str) -> Result<Macropad> {
+    let path = profile_path(name)?;
+    ensure!(path.exists(), "profile '{name}' doesn't exist");
+    Mapping::read(&path.to_string_lossy()).context(format!("loading profile '{name}'"))
+}
+
+/// Saves a configuration under a profile name, replacing an existing profile
+///
+/// #Arguments
+/// `name` - name of the profile
+/// `config` - configuration to save
+///
+pub fn save_profile(name: &str, config: &Macropad) -> Result<PathBuf> {
+    let path = profile_path(name)?;
+    std::fs::create_dir_all(profiles_dir()).context("creating the profiles directory")?;
+    Mapping::save(config, &path.to_string_lossy()).context(format!("saving profile '{name}'"))?;
+    Ok(path)
+}
+
+/// Deletes a saved profile
+///
+/// #Arguments
+/// `name` - name of the profile
+///
+pub fn delete_profile(name: &str) -> Result<()> {
+    let path = profile_path(name)?;
+    std::fs::remove_file(&path).context(format!("deleting profile '{name}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn save_list_load_delete() -> Result<()> {
+        let dir = std::env::temp_dir().join(format!("macropad_profiles_{}", std::process::id()));
+        std::env::set_var(consts::PROFILES_DIR_ENV, &dir);
+        let mut gaming = Macropad::new(1, 2, 0);
+        gaming.layers[0].buttons[0][0].mapping = "w".to_string();
+        save_profile("gaming", &gaming)?;
+        save_profile("work", &Macropad::new(3, 4, 1))?;
+        assert_eq!(list_profiles()?, vec!["gaming", "work"]);
+        let loaded = load_profile("gaming")?;
+        assert_eq!(loaded.layers[0].buttons[0][0].mapping, "w");
+        assert!(load_profile("editing").is_err());
+        assert!(save_profile("../escape", &gaming).is_err());
+        delete_profile("work")?;
+        assert_eq!(list_profiles()?, vec!["gaming"]);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
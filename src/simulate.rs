@@ -0,0 +1,297 @@
+use crate::keyboard::{
+    key_sequence, split_waits, MediaCode, Modifier, MouseAction, MouseButton, WellKnownCode,
+};
+use crate::layout;
+use crate::mapping::Button;
+use anyhow::{anyhow, Result};
+use enigo::{Axis, Coordinate, Direction, Enigo, Key, Keyboard as _, Mouse as _, Settings};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Input performed on the host for one step of a mapping
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Input {
+    Key(Key),
+    Click(enigo::Button),
+    /// wheel clicks, positive scrolls down
+    Scroll(i32),
+    Move(i32, i32),
+}
+
+/// Step of a mapping as performed on the host
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HostAction {
+    /// keys held down around the input (the input is `None` for modifier only chords)
+    Chord(Vec<Key>, Option<Input>),
+    /// pause in milliseconds
+    Wait(u16),
+}
+
+/// Returns the host key for a modifier
+///
+/// #Arguments
+/// `modifier` - modifier of the mapping
+///
+fn modifier_key(modifier: Modifier) -> Key {
+    match modifier {
+        Modifier::Ctrl => Key::Control,
+        Modifier::Shift => Key::Shift,
+        Modifier::Alt | Modifier::RightAlt => Key::Alt,
+        Modifier::Win | Modifier::RightWin => Key::Meta,
+        Modifier::RightCtrl => Key::RControl,
+        Modifier::RightShift => Key::RShift,
+    }
+}
+
+/// Returns the host key for a regular key, `None` for keys the host can't simulate
+/// on every platform
+///
+/// #Arguments
+/// `code` - key of the mapping
+///
+fn regular_key(code: WellKnownCode) -> Option<Key> {
+    use WellKnownCode as W;
+    let key = match code {
+        W::Enter => Key::Return,
+        W::Escape => Key::Escape,
+        W::Backspace => Key::Backspace,
+        W::Tab => Key::Tab,
+        W::Space => Key::Space,
+        W::Minus => Key::Unicode('-'),
+        W::Equal => Key::Unicode('='),
+        W::LeftBracket => Key::Unicode('['),
+        W::RightBracket => Key::Unicode(']'),
+        W::Backslash => Key::Unicode('\\'),
+        W::Semicolon => Key::Unicode(';'),
+        W::Quote => Key::Unicode('\''),
+        W::Grave => Key::Unicode('`'),
+        W::Comma => Key::Unicode(','),
+        W::Dot => Key::Unicode('.'),
+        W::Slash => Key::Unicode('/'),
+        W::CapsLock => Key::CapsLock,
+        W::F1 => Key::F1,
+        W::F2 => Key::F2,
+        W::F3 => Key::F3,
+        W::F4 => Key::F4,
+        W::F5 => Key::F5,
+        W::F6 => Key::F6,
+        W::F7 => Key::F7,
+        W::F8 => Key::F8,
+        W::F9 => Key::F9,
+        W::F10 => Key::F10,
+        W::F11 => Key::F11,
+        W::F12 => Key::F12,
+        W::F13 => Key::F13,
+        W::F14 => Key::F14,
+        W::F15 => Key::F15,
+        W::F16 => Key::F16,
+        W::F17 => Key::F17,
+        W::F18 => Key::F18,
+        W::F19 => Key::F19,
+        W::F20 => Key::F20,
+        W::Home => Key::Home,
+        W::PageUp => Key::PageUp,
+        W::Delete => Key::Delete,
+        W::End => Key::End,
+        W::PageDown => Key::PageDown,
+        W::Right => Key::RightArrow,
+        W::Left => Key::LeftArrow,
+        W::Down => Key::DownArrow,
+        W::Up => Key::UpArrow,
+        W::NumPadSlash => Key::Divide,
+        W::NumPadAsterisk => Key::Multiply,
+        W::NumPadMinus => Key::Subtract,
+        W::NumPadPlus => Key::Add,
+        W::NumPadEnter => Key::Return,
+        W::NumPad1 => Key::Numpad1,
+        W::NumPad2 => Key::Numpad2,
+        W::NumPad3 => Key::Numpad3,
+        W::NumPad4 => Key::Numpad4,
+        W::NumPad5 => Key::Numpad5,
+        W::NumPad6 => Key::Numpad6,
+        W::NumPad7 => Key::Numpad7,
+        W::NumPad8 => Key::Numpad8,
+        W::NumPad9 => Key::Numpad9,
+        W::NumPad0 => Key::Numpad0,
+        W::NumPadDot => Key::Decimal,
+        // letters and digits
+        code => {
+            let name = code.to_string();
+            let mut chars = name.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii_alphanumeric() => Key::Unicode(c),
+                _ => return None,
+            }
+        }
+    };
+    // characters are typed by what they are, so use the label of the key on the host layout
+    match (key, layout::host_layout().key_label(&code.to_string())) {
+        (Key::Unicode(_), Some(label)) => Some(Key::Unicode(label)),
+        _ => Some(key),
+    }
+}
+
+/// Returns the host key for a media key, `None` for keys the host can't simulate on
+/// every platform
+///
+/// #Arguments
+/// `code` - media key of the mapping
+///
+fn media_key(code: MediaCode) -> Option<Key> {
+    match code {
+        MediaCode::Next => Some(Key::MediaNextTrack),
+        MediaCode::Previous => Some(Key::MediaPrevTrack),
+        MediaCode::Play => Some(Key::MediaPlayPause),
+        MediaCode::Mute => Some(Key::VolumeMute),
+        MediaCode::VolumeUp => Some(Key::VolumeUp),
+        MediaCode::VolumeDown => Some(Key::VolumeDown),
+        _ => None,
+    }
+}
+
+/// Converts the mapping of a button into the steps performed on the host. It goes
+/// through the same tokenizer as programming, so text, holds and pauses behave the
+/// same way; the delay of the button is a pause between the key presses
+///
+/// #Arguments
+/// `btn` - button to simulate
+///
+pub fn host_actions(btn: &Button) -> Result<Vec<HostAction>> {
+    let (presses, delay) = split_waits(&key_sequence(&btn.mapping)?, btn.delay)?;
+    let mut actions = Vec::new();
+    for (i, press) in presses.iter().filter(|p| !p.is_empty()).enumerate() {
+        if i > 0 && delay > 0 {
+            actions.push(HostAction::Wait(delay));
+        }
+        if let Some(MouseAction::Move(dx, dy)) = MouseAction::parse_move(press)? {
+            actions.push(HostAction::Chord(
+                Vec::new(),
+                Some(Input::Move(dx.into(), dy.into())),
+            ));
+            continue;
+        }
+        let mut modifiers = Vec::new();
+        let mut input = None;
+        for key in press.split('-') {
+            let unsupported = || anyhow!("{key} can't be simulated on this computer");
+            if let Ok(m) = Modifier::from_str(key) {
+                modifiers.push(modifier_key(m));
+            } else if let Ok(w) = WellKnownCode::from_str(key) {
+                input = Some(Input::Key(regular_key(w).ok_or_else(unsupported)?));
+            } else if let Ok(m) = MediaCode::from_str(key) {
+                input = Some(Input::Key(media_key(m).ok_or_else(unsupported)?));
+            } else if let Ok(b) = MouseButton::from_str(key) {
+                input = Some(Input::Click(match b {
+                    MouseButton::Left => enigo::Button::Left,
+                    MouseButton::Right => enigo::Button::Right,
+                    MouseButton::Middle => enigo::Button::Middle,
+                }));
+            } else if let Ok(a) = MouseAction::from_str(key) {
+                input = match a {
+                    MouseAction::WheelUp => Some(Input::Scroll(-1)),
+                    MouseAction::WheelDown => Some(Input::Scroll(1)),
+                    _ => return Err(unsupported()),
+                };
+            } else {
+                return Err(anyhow!("unknown key - {key}"));
+            }
+        }
+        actions.push(HostAction::Chord(modifiers, input));
+    }
+    Ok(actions)
+}
+
+/// Performs the steps on this computer, as if the key was pressed on the macropad.
+/// Nothing is sent to the macropad
+///
+/// #Arguments
+/// `actions` - steps returned by `host_actions`
+///
+pub fn perform(actions: &[HostAction]) -> Result<()> {
+    let mut enigo = Enigo::new(&Settings::default())
+        .map_err(|e| anyhow!("can't simulate input on this computer: {e}"))?;
+    for action in actions {
+        match action {
+            HostAction::Wait(ms) => std::thread::sleep(Duration::from_millis((*ms).into())),
+            HostAction::Chord(modifiers, input) => {
+                // modifiers that went down are always released, even when the chord fails
+                let mut pressed = Vec::new();
+                let mut result = Ok(());
+                for m in modifiers {
+                    result = enigo.key(*m, Direction::Press);
+                    if result.is_err() {
+                        break;
+                    }
+                    pressed.push(*m);
+                }
+                if result.is_ok() {
+                    result = match input {
+                        Some(Input::Key(key)) => enigo.key(*key, Direction::Click),
+                        Some(Input::Click(button)) => enigo.button(*button, Direction::Click),
+                        Some(Input::Scroll(clicks)) => enigo.scroll(*clicks, Axis::Vertical),
+                        Some(Input::Move(dx, dy)) => enigo.move_mouse(*dx, *dy, Coordinate::Rel),
+                        None => Ok(()),
+                    };
+                }
+                for m in pressed.iter().rev() {
+                    let released = enigo.key(*m, Direction::Release);
+                    result = result.and(released);
+                }
+                result?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button(mapping: &str, delay: u16) -> Button {
+        Button {
+            delay,
+            mapping: mapping.to_string(),
+            led_color: None,
+        }
+    }
+
+    #[test]
+    fn sequence_with_delay() -> Result<()> {
+        let actions = host_actions(&button("ctrl-c,ctrl-v", 100))?;
+        assert_eq!(
+            actions,
+            vec![
+                HostAction::Chord(vec![Key::Control], Some(Input::Key(Key::Unicode('c')))),
+                HostAction::Wait(100),
+                HostAction::Chord(vec![Key::Control], Some(Input::Key(Key::Unicode('v')))),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn text_mouse_and_media() -> Result<()> {
+        let actions = host_actions(&button("text:A!", 0))?;
+        assert_eq!(
+            actions[0],
+            HostAction::Chord(vec![Key::Shift], Some(Input::Key(Key::Unicode('a'))))
+        );
+        assert_eq!(
+            actions[1],
+            HostAction::Chord(vec![Key::Shift], Some(Input::Key(Key::Unicode('1'))))
+        );
+        let actions = host_actions(&button("ctrl-wheelup,move:5,-5,volumeup", 0))?;
+        assert_eq!(
+            actions,
+            vec![
+                HostAction::Chord(vec![Key::Control], Some(Input::Scroll(-1))),
+                HostAction::Chord(vec![], Some(Input::Move(5, -5))),
+                HostAction::Chord(vec![], Some(Input::Key(Key::VolumeUp))),
+            ]
+        );
+        assert!(host_actions(&button("screenlock", 0)).is_err());
+        assert!(host_actions(&button("", 0))?.is_empty());
+        Ok(())
+    }
+}
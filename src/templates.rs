@@ -0,0 +1,123 @@
+use crate::mapping::{Button, Layer, Macropad};
+
+/// Starter configuration offered to new users
+pub struct Template {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub config: Macropad,
+}
+
+/// Builds the first layer of a configuration from rows of key mappings and the
+/// (ccw, press, cw) mappings of each knob. The other layers are left empty
+///
+/// #Arguments
+/// `rows` - mappings of the keys, row by row
+/// `knobs` - mappings of the knobs
+///
+fn config(rows: &[&[&str]], knobs: &[[&str; 3]]) -> Macropad {
+    let cols = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+    let mut config = Macropad::new(rows.len() as u8, cols as u8, knobs.len() as u8);
+    let button = |mapping: &str| Button {
+        mapping: mapping.to_string(),
+        ..Button::new()
+    };
+    let mut layer = Layer::new(rows.len() as u8, cols as u8, knobs.len() as u8);
+    for (r, row) in rows.iter().enumerate() {
+        for (c, mapping) in row.iter().enumerate() {
+            layer.buttons[r][c] = button(mapping);
+        }
+    }
+    for (knob, [ccw, press, cw]) in layer.knobs.iter_mut().zip(knobs) {
+        knob.ccw = button(ccw);
+        knob.press = button(press);
+        knob.cw = button(cw);
+    }
+    config.layers[0] = layer;
+    config
+}
+
+/// Returns the built-in starter templates
+///
+pub fn templates() -> Vec<Template> {
+    vec![
+        Template {
+            name: "3×3 + 1 knob media controls",
+            description: "Playback, volume and browser keys, the knob changes the volume",
+            config: config(
+                &[
+                    &["previous", "play", "next"],
+                    &["stop", "mute", "calculator"],
+                    &["webpageback", "webpagehome", "webpageforward"],
+                ],
+                &[["volumedown", "mute", "volumeup"]],
+            ),
+        },
+        Template {
+            name: "Streaming deck",
+            description: "3×4 keys sending ctrl-shift-f1 to f12 for OBS scene and source hotkeys, knobs for volume and scrolling",
+            config: config(
+                &[
+                    &["ctrl-shift-f1", "ctrl-shift-f2", "ctrl-shift-f3", "ctrl-shift-f4"],
+                    &["ctrl-shift-f5", "ctrl-shift-f6", "ctrl-shift-f7", "ctrl-shift-f8"],
+                    &["ctrl-shift-f9", "ctrl-shift-f10", "ctrl-shift-f11", "ctrl-shift-f12"],
+                ],
+                &[
+                    ["volumedown", "mute", "volumeup"],
+                    ["wheelup", "click", "wheeldown"],
+                ],
+            ),
+        },
+        Template {
+            name: "Numpad",
+            description: "3×4 number block, knob presses send 0 and the decimal point",
+            config: config(
+                &[
+                    &["numpad7", "numpad8", "numpad9", "numpadminus"],
+                    &["numpad4", "numpad5", "numpad6", "numpadplus"],
+                    &["numpad1", "numpad2", "numpad3", "numpadenter"],
+                ],
+                &[
+                    ["left", "numpad0", "right"],
+                    ["up", "numpaddot", "down"],
+                ],
+            ),
+        },
+        Template {
+            name: "Editing",
+            description: "2×3 clipboard and undo keys, the knob scrolls",
+            config: config(
+                &[&["ctrl-c", "ctrl-v", "ctrl-x"], &["ctrl-z", "ctrl-y", "ctrl-s"]],
+                &[["wheelup", "ctrl-a", "wheeldown"]],
+            ),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::Mapping;
+
+    #[test]
+    fn templates_are_valid() -> anyhow::Result<()> {
+        let templates = templates();
+        for t in &templates {
+            let errors = Mapping::validate_all(&t.config, Some(0x8840))?;
+            assert!(errors.is_empty(), "{}: {errors:?}", t.name);
+            assert!(
+                t.config.layers[0]
+                    .buttons
+                    .iter()
+                    .flatten()
+                    .all(|b| !b.mapping.is_empty()),
+                "{}",
+                t.name
+            );
+        }
+        let mut names: Vec<_> = templates.iter().map(|t| t.name).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), templates.len());
+        Ok(())
+    }
+}